@@ -1,8 +1,91 @@
 # Test that we can post data to the event endpoint
-import requests
+import os
+import requests as http
 import uuid as UUID
 
 
+def session_for(tokens):
+    session = http.Session()
+    session.headers["Authorization"] = f"Bearer {tokens['access_token']}"
+    return session
+
+
+def sign_in(role="fan"):
+    """Register a throwaway user and return a session that sends its access token"""
+    credentials = {
+        "username": f"tester-{UUID.uuid4()}",
+        "password": "correct horse",
+        "role": role,
+    }
+    response = http.post("http://localhost:3000/auth/register", json=credentials)
+    assert response.status_code == 200
+    return session_for(response.json())
+
+
+def sign_in_admin():
+    """Sign in as the admin made with `cargo run -- make-admin <username>`"""
+    credentials = {
+        "username": os.environ.get("ADMIN_USERNAME", "admin"),
+        "password": os.environ.get("ADMIN_PASSWORD", "admin-password"),
+    }
+    # Register the admin the first time the tests run, it still has to be made an admin by hand
+    http.post("http://localhost:3000/auth/register", json=credentials)
+    response = http.post("http://localhost:3000/auth/login", json=credentials)
+    assert response.status_code == 200
+    assert response.json()["user"]["role"] == "admin", (
+        f"Run `cargo run -- make-admin {credentials['username']}` first"
+    )
+    return session_for(response.json())
+
+
+# Most tests set up data as an admin, since requests that change data need a token and a role that allows them
+requests = sign_in_admin()
+
+
+def test_auth():
+    credentials = {"username": f"runner-{UUID.uuid4()}", "password": "correct horse"}
+    response = http.post("http://localhost:3000/auth/register", json=credentials)
+    assert response.status_code == 200
+    user_id = response.json()["user"]["id"]
+    assert "password_hash" not in response.json()["user"]
+
+    # The username is taken now
+    response = http.post("http://localhost:3000/auth/register", json=credentials)
+    assert response.status_code == 409
+
+    response = http.post(
+        "http://localhost:3000/auth/login",
+        json={**credentials, "password": "wrong horse"},
+    )
+    assert response.status_code == 401
+    response = http.post("http://localhost:3000/auth/login", json=credentials)
+    assert response.status_code == 200
+    tokens = response.json()
+
+    # Changing data needs an access token, reading does not
+    response = http.delete(f"http://localhost:3000/users/{user_id}")
+    assert response.status_code == 401
+    assert response.headers["WWW-Authenticate"] == "Bearer"
+    response = http.delete(
+        f"http://localhost:3000/users/{user_id}",
+        headers={"Authorization": f"Bearer {tokens['refresh_token']}"},
+    )
+    assert response.status_code == 401
+    response = http.get(f"http://localhost:3000/users/{user_id}")
+    assert response.status_code == 200
+
+    response = http.post(
+        "http://localhost:3000/auth/refresh",
+        json={"refresh_token": tokens["refresh_token"]},
+    )
+    assert response.status_code == 200
+    response = http.delete(
+        f"http://localhost:3000/users/{user_id}",
+        headers={"Authorization": f"Bearer {response.json()['access_token']}"},
+    )
+    assert response.status_code == 200
+
+
 def test_competition():
     url = "http://localhost:3000/competitions"
     data = {
@@ -28,6 +111,113 @@ def test_competition():
     assert response.status_code == 404
 
 
+def test_competition_update():
+    url = "http://localhost:3000/competitions"
+    data = {
+        "name": "test_name",
+        "location": "test_location",
+        "start_date": "2021-01-01",
+        "end_date": "2021-01-02",
+    }
+    response = requests.post(url, json=data)
+    assert response.status_code == 200
+    uuid = response.json()["id"]
+    url = "http://localhost:3000/competitions/" + uuid
+
+    # Replace the whole competition
+    data["name"] = "new_name"
+    response = requests.put(url, json=data)
+    assert response.status_code == 200
+    assert response.json()["id"] == uuid
+    assert response.json()["name"] == "new_name"
+
+    # Only change the location
+    response = requests.patch(url, json={"location": "new_location"})
+    assert response.status_code == 200
+    assert response.json()["name"] == "new_name"
+    assert response.json()["location"] == "new_location"
+
+    response = requests.delete(url)
+    assert response.status_code == 200
+
+    # Updating a competition that does not exist should not create it
+    response = requests.put(url, json=data)
+    assert response.status_code == 404
+    response = requests.patch(url, json={"location": "new_location"})
+    assert response.status_code == 404
+
+
+def test_competition_pagination():
+    url = "http://localhost:3000/competitions"
+    data = {
+        "name": "test_name",
+        "location": "test_location",
+        "start_date": "2021-01-01",
+        "end_date": "2021-01-02",
+    }
+    created = [requests.post(url, json=data).json()["id"] for _ in range(3)]
+
+    # Page through every competition one at a time
+    seen = []
+    params = {"limit": 1}
+    while True:
+        response = requests.get(url, params=params)
+        assert response.status_code == 200
+        seen += [item["id"] for item in response.json()["items"]]
+        if "next_cursor" not in response.json():
+            break
+        params["cursor"] = response.json()["next_cursor"]
+    assert set(created) <= set(seen)
+
+    response = requests.get(url, params={"cursor": "not a cursor"})
+    assert response.status_code == 400
+
+    for uuid in created:
+        requests.delete(url + "/" + uuid)
+
+
+def test_competition_batch_get():
+    url = "http://localhost:3000/competitions"
+    data = {
+        "name": "test_name",
+        "location": "test_location",
+        "start_date": "2021-01-01",
+        "end_date": "2021-01-02",
+    }
+    created = [requests.post(url, json=data).json()["id"] for _ in range(2)]
+    unknown = str(UUID.uuid4())
+
+    response = requests.post(url + "/batch-get", json={"ids": created + [unknown]})
+    assert response.status_code == 200
+    assert sorted(item["id"] for item in response.json()["items"]) == sorted(created)
+    assert response.json()["missing"] == [unknown]
+
+    for uuid in created:
+        requests.delete(url + "/" + uuid)
+
+
+def test_competition_batch_write():
+    url = "http://localhost:3000/competitions/batch"
+    data = {
+        "name": "test_name",
+        "location": "test_location",
+        "start_date": "2021-01-01",
+        "end_date": "2021-01-02",
+    }
+    response = requests.post(url, json=[data, data, data])
+    assert response.status_code == 200
+    assert [result["status"] for result in response.json()] == ["created"] * 3
+    ids = [result["item"]["id"] for result in response.json()]
+
+    response = requests.delete(url, json={"ids": ids})
+    assert response.status_code == 200
+    assert [result["status"] for result in response.json()] == ["deleted"] * 3
+
+    for uuid in ids:
+        response = requests.get("http://localhost:3000/competitions/" + uuid)
+        assert response.status_code == 404
+
+
 def test_competition_bad_date():
     url = "http://localhost:3000/competitions"
     data = {
@@ -58,8 +248,9 @@ def test_event_bad_date():
     url = "http://localhost:3000/events"
     data = {
         "competition_id": str(UUID.uuid4()),
-        "athlete_id": str(UUID.uuid4()),
-        "name": "test_name",
+        "athlete_ids": [],
+        "discipline": "200m",
+        "category": {"gender": "men"},
         "date_time": "2022-12-19T16:39:57-08:",  # invalid date
     }
     response = requests.post(url, json=data)
@@ -70,8 +261,9 @@ def test_event():
     url = "http://localhost:3000/events"
     data = {
         "competition_id": str(UUID.uuid4()),
-        "athlete_id": str(UUID.uuid4()),
-        "name": "test_name",
+        "athlete_ids": [],
+        "discipline": "200m",
+        "category": {"gender": "men"},
         "date_time": "2022-12-19T16:39:57-08:00",
     }
 
@@ -83,7 +275,7 @@ def test_event():
 
     response = requests.get(url)
     assert response.status_code == 200
-    assert response.json()["name"] == "test_name"
+    assert response.json()["discipline"] == "200m"
 
     response = requests.delete(url)
     assert response.status_code == 200
@@ -109,9 +301,29 @@ def test_user_endpoints():
     assert response.status_code == 200
     assert response.json()["username"] == "John Doe"
 
+    # Usernames are unique whatever their case
+    response = requests.post(
+        "http://localhost:3000/users", json={**user_data, "username": "john doe"}
+    )
+    assert response.status_code == 409
+    response = requests.get("http://localhost:3000/users/by-username/JOHN%20DOE")
+    assert response.status_code == 200
+    assert response.json()["id"] == user_id
+
+    # A new username moves the reservation, freeing the old one
+    response = requests.put(f"{url}/username", json={"username": "Johnny Doe"})
+    assert response.status_code == 200
+    assert response.json()["username"] == "Johnny Doe"
+    response = requests.get("http://localhost:3000/users/by-username/john%20doe")
+    assert response.status_code == 404
+    response = requests.get("http://localhost:3000/users/by-username/johnny%20doe")
+    assert response.json()["id"] == user_id
+
     # Delete the user
     response = requests.delete(url)
     assert response.status_code == 200
+    response = requests.get("http://localhost:3000/users/by-username/johnny%20doe")
+    assert response.status_code == 404
 
     # Verify the user is deleted
     response = requests.get(url)
@@ -145,3 +357,808 @@ def test_athlete_endpoints():
     # Verify the athlete is deleted
     response = requests.get(url)
     assert response.status_code == 404
+
+
+# Test following and unfollowing an athlete
+def test_follow_athlete():
+    athlete_data = {
+        "first_name": "Jane",
+        "last_name": "Doe",
+        "bio": "Running Bio",
+        "birthday": "1990-01-01",
+    }
+    athlete_id = requests.post(
+        "http://localhost:3000/athletes", json=athlete_data
+    ).json()["id"]
+    user_data = {
+        "username": f"follower-{UUID.uuid4()}",
+        "athletes_following": [str(UUID.uuid4())],
+    }
+    response = requests.post("http://localhost:3000/users", json=user_data)
+    user_id = response.json()["id"]
+    # The following list is read only, it only changes by following athletes
+    assert response.json()["athletes_following"] == []
+
+    follow_url = f"http://localhost:3000/users/{user_id}/follow/{athlete_id}"
+    response = requests.post(follow_url)
+    assert response.status_code == 200
+
+    # Following twice is a conflict
+    response = requests.post(follow_url)
+    assert response.status_code == 409
+
+    # Following an athlete that does not exist is not found
+    response = requests.post(
+        f"http://localhost:3000/users/{user_id}/follow/{UUID.uuid4()}"
+    )
+    assert response.status_code == 404
+    assert "Athlete" in response.json()["detail"]
+
+    response = requests.get(f"http://localhost:3000/users/{user_id}/following")
+    assert response.status_code == 200
+    assert [athlete["id"] for athlete in response.json()] == [athlete_id]
+
+    response = requests.get(f"http://localhost:3000/athletes/{athlete_id}/followers")
+    assert response.status_code == 200
+    assert [user["id"] for user in response.json()] == [user_id]
+    response = requests.get(f"http://localhost:3000/users/{user_id}")
+    assert response.json()["athletes_following"] == [athlete_id]
+
+    response = requests.delete(follow_url)
+    assert response.status_code == 200
+    response = requests.get(f"http://localhost:3000/users/{user_id}/following")
+    assert response.json() == []
+    response = requests.get(f"http://localhost:3000/users/{user_id}")
+    assert response.json()["athletes_following"] == []
+
+    requests.delete(f"http://localhost:3000/users/{user_id}")
+    requests.delete(f"http://localhost:3000/athletes/{athlete_id}")
+
+
+# Test adding and removing events from an athlete's calendar
+def test_athlete_calendar():
+    athlete_data = {
+        "first_name": "Jane",
+        "last_name": "Doe",
+        "bio": "Running Bio",
+        "birthday": "1990-01-01",
+    }
+    athlete_id = requests.post(
+        "http://localhost:3000/athletes", json=athlete_data
+    ).json()["id"]
+    event_ids = []
+    for date_time in ["2022-12-20T16:39:57-08:00", "2022-12-19T16:39:57-08:00"]:
+        event_data = {
+            "competition_id": str(UUID.uuid4()),
+            "athlete_ids": [],
+            "discipline": "200m",
+            "category": {"gender": "men"},
+            "date_time": date_time,
+        }
+        event_ids.append(
+            requests.post("http://localhost:3000/events", json=event_data).json()["id"]
+        )
+
+    calendar_url = f"http://localhost:3000/athletes/{athlete_id}/events"
+    for event_id in event_ids:
+        response = requests.post(f"{calendar_url}/{event_id}")
+        assert response.status_code == 200
+    response = requests.post(f"{calendar_url}/{event_ids[0]}")
+    assert response.status_code == 409
+    response = requests.post(f"{calendar_url}/{UUID.uuid4()}")
+    assert response.status_code == 404
+
+    # Events come back in date order
+    response = requests.get(calendar_url)
+    assert response.status_code == 200
+    assert [event["id"] for event in response.json()] == event_ids[::-1]
+
+    response = requests.delete(f"{calendar_url}/{event_ids[0]}")
+    assert response.status_code == 200
+    response = requests.get(calendar_url)
+    assert [event["id"] for event in response.json()] == [event_ids[1]]
+
+    # The calendar and the entry lists of the events stay in sync
+    response = requests.get(f"http://localhost:3000/events/{event_ids[0]}")
+    assert response.json()["athlete_ids"] == []
+    response = requests.get(f"http://localhost:3000/events/{event_ids[1]}/athletes")
+    assert [athlete["id"] for athlete in response.json()] == [athlete_id]
+
+    for event_id in event_ids:
+        requests.delete(f"http://localhost:3000/events/{event_id}")
+    requests.delete(f"http://localhost:3000/athletes/{athlete_id}")
+
+
+# Test an event with several athletes on its entry list
+def test_event_entry_list():
+    athlete_data = {
+        "first_name": "Jane",
+        "last_name": "Doe",
+        "bio": "Running Bio",
+        "birthday": "1990-01-01",
+    }
+    athlete_ids = [
+        requests.post("http://localhost:3000/athletes", json=athlete_data).json()["id"]
+        for _ in range(3)
+    ]
+    event_data = {
+        "competition_id": str(UUID.uuid4()),
+        "athlete_ids": athlete_ids[:2],
+        "discipline": "200m",
+        "category": {"gender": "men"},
+        "date_time": "2022-12-19T16:39:57-08:00",
+    }
+    response = requests.post("http://localhost:3000/events", json=event_data)
+    assert response.status_code == 200
+    event_id = response.json()["id"]
+    athletes_url = f"http://localhost:3000/events/{event_id}/athletes"
+
+    response = requests.get(athletes_url)
+    assert response.status_code == 200
+    assert [athlete["id"] for athlete in response.json()] == athlete_ids[:2]
+    response = requests.get(f"http://localhost:3000/athletes/{athlete_ids[0]}/events")
+    assert [event["id"] for event in response.json()] == [event_id]
+
+    # Replacing the entry list updates the calendars of the athletes
+    response = requests.patch(
+        f"http://localhost:3000/events/{event_id}",
+        json={"athlete_ids": athlete_ids[1:]},
+    )
+    assert response.status_code == 200
+    response = requests.get(athletes_url)
+    assert [athlete["id"] for athlete in response.json()] == athlete_ids[1:]
+    response = requests.get(f"http://localhost:3000/athletes/{athlete_ids[0]}/events")
+    assert response.json() == []
+
+    # Unknown athletes cannot be entered
+    response = requests.patch(
+        f"http://localhost:3000/events/{event_id}",
+        json={"athlete_ids": [str(UUID.uuid4())]},
+    )
+    assert response.status_code == 404
+
+    requests.delete(f"http://localhost:3000/events/{event_id}")
+    response = requests.get(f"http://localhost:3000/athletes/{athlete_ids[1]}/events")
+    assert response.json() == []
+    for athlete_id in athlete_ids:
+        requests.delete(f"http://localhost:3000/athletes/{athlete_id}")
+
+
+# Test recording the results of an event
+def test_event_results():
+    athlete_data = {
+        "first_name": "Jane",
+        "last_name": "Doe",
+        "bio": "Running Bio",
+        "birthday": "1990-01-01",
+    }
+    athlete_ids = [
+        requests.post("http://localhost:3000/athletes", json=athlete_data).json()["id"]
+        for _ in range(2)
+    ]
+    event_data = {
+        "competition_id": str(UUID.uuid4()),
+        "athlete_ids": athlete_ids,
+        "discipline": "100m",
+        "category": {"gender": "women"},
+        "date_time": "2022-12-19T16:39:57-08:00",
+    }
+    event_id = requests.post("http://localhost:3000/events", json=event_data).json()[
+        "id"
+    ]
+
+    result_ids = []
+    for place, (athlete_id, seconds) in enumerate(
+        [(athlete_ids[1], 11.02), (athlete_ids[0], 10.89)], start=1
+    ):
+        result_data = {
+            "event_id": event_id,
+            "athlete_id": athlete_id,
+            "place": 3 - place,
+            "mark": {"type": "time", "seconds": seconds},
+        }
+        response = requests.post("http://localhost:3000/results", json=result_data)
+        assert response.status_code == 200
+        result_ids.append(response.json()["id"])
+
+    # Only entered athletes can have a result
+    result_data["athlete_id"] = str(UUID.uuid4())
+    response = requests.post("http://localhost:3000/results", json=result_data)
+    assert response.status_code == 422
+
+    response = requests.get(f"http://localhost:3000/events/{event_id}/results")
+    assert response.status_code == 200
+    assert [result["athlete_id"] for result in response.json()] == athlete_ids
+    assert response.json()[0]["mark"] == {"type": "time", "seconds": 10.89}
+
+    response = requests.patch(
+        f"http://localhost:3000/results/{result_ids[0]}",
+        json={"mark": {"type": "time", "seconds": 11.01}},
+    )
+    assert response.status_code == 200
+    assert response.json()["mark"]["seconds"] == 11.01
+
+    for result_id in result_ids:
+        response = requests.delete(f"http://localhost:3000/results/{result_id}")
+        assert response.status_code == 200
+    requests.delete(f"http://localhost:3000/events/{event_id}")
+    for athlete_id in athlete_ids:
+        requests.delete(f"http://localhost:3000/athletes/{athlete_id}")
+
+
+# Test that results are rolled up into personal and season bests
+def test_athlete_bests():
+    from datetime import datetime, timezone
+
+    athlete_data = {
+        "first_name": "Jane",
+        "last_name": "Doe",
+        "bio": "Running Bio",
+        "birthday": "1990-01-01",
+    }
+    athlete_id = requests.post(
+        "http://localhost:3000/athletes", json=athlete_data
+    ).json()["id"]
+    this_year = datetime.now(timezone.utc).year
+    event_ids = []
+    result_ids = []
+    for gender, year, seconds in [
+        ("women", this_year - 1, 22.1),
+        ("mixed", this_year, 22.5),
+    ]:
+        event_data = {
+            "competition_id": str(UUID.uuid4()),
+            "athlete_ids": [athlete_id],
+            "discipline": "200m",
+            "category": {"gender": gender},
+            "date_time": f"{year}-01-01T12:00:00Z",
+        }
+        event_id = requests.post(
+            "http://localhost:3000/events", json=event_data
+        ).json()["id"]
+        event_ids.append(event_id)
+        result_data = {
+            "event_id": event_id,
+            "athlete_id": athlete_id,
+            "place": 1,
+            "mark": {"type": "time", "seconds": seconds},
+        }
+        result_ids.append(
+            requests.post("http://localhost:3000/results", json=result_data).json()[
+                "id"
+            ]
+        )
+
+    response = requests.get(f"http://localhost:3000/athletes/{athlete_id}/bests")
+    assert response.status_code == 200
+    [best] = response.json()
+    assert best["discipline"] == "200m"
+    assert best["personal_best"]["result_id"] == result_ids[0]
+    assert best["season_best"]["result_id"] == result_ids[1]
+
+    for result_id in result_ids:
+        requests.delete(f"http://localhost:3000/results/{result_id}")
+    response = requests.get(f"http://localhost:3000/athletes/{athlete_id}/bests")
+    assert response.json() == []
+
+    for event_id in event_ids:
+        requests.delete(f"http://localhost:3000/events/{event_id}")
+    requests.delete(f"http://localhost:3000/athletes/{athlete_id}")
+
+
+# Test the discipline catalog
+def test_disciplines():
+    response = requests.get("http://localhost:3000/disciplines")
+    assert response.status_code == 200
+    disciplines = {discipline["code"]: discipline for discipline in response.json()}
+    assert disciplines["110m_hurdles"]["group"] == "hurdles"
+    assert disciplines["pole_vault"]["mark_type"] == "height"
+    assert disciplines["decathlon"]["mark_type"] == "points"
+
+    # Events only accept disciplines from the catalog
+    event_data = {
+        "competition_id": str(UUID.uuid4()),
+        "athlete_ids": [],
+        "discipline": "egg_and_spoon",
+        "category": {"gender": "mixed"},
+        "date_time": "2022-12-19T16:39:57-08:00",
+    }
+    response = requests.post("http://localhost:3000/events", json=event_data)
+    assert response.status_code == 422
+
+
+# Test drawing heats and advancing athletes to a final
+def test_event_rounds():
+    athlete_data = {
+        "first_name": "Jane",
+        "last_name": "Doe",
+        "bio": "Running Bio",
+        "birthday": "1990-01-01",
+    }
+    athlete_ids = [
+        requests.post("http://localhost:3000/athletes", json=athlete_data).json()["id"]
+        for _ in range(6)
+    ]
+    event_data = {
+        "competition_id": str(UUID.uuid4()),
+        "athlete_ids": athlete_ids,
+        "discipline": "400m",
+        "category": {"gender": "women"},
+        "date_time": "2022-12-19T16:39:57-08:00",
+    }
+    event_id = requests.post("http://localhost:3000/events", json=event_data).json()[
+        "id"
+    ]
+    rounds_url = f"http://localhost:3000/events/{event_id}/rounds"
+
+    response = requests.post(
+        rounds_url,
+        json={
+            "round_type": "heats",
+            "heat_count": 2,
+            "lanes": 4,
+            "advancement": {"by_place": 1, "by_time": 1},
+        },
+    )
+    assert response.status_code == 200
+    heats = response.json()
+    assert heats["number"] == 1
+    assert [len(heat["assignments"]) for heat in heats["heats"]] == [3, 3]
+
+    response = requests.get(f"http://localhost:3000/rounds/{heats['id']}/start-list")
+    assert response.status_code == 200
+    assert [entry["position"] for entry in response.json()[0]["entries"]] == [1, 2, 3]
+
+    # Record a result for every athlete, placed within their heat
+    result_ids = []
+    for heat in heats["heats"]:
+        for place, assignment in enumerate(heat["assignments"], start=1):
+            seconds = 50 + heat["number"] + place
+            result_data = {
+                "event_id": event_id,
+                "athlete_id": assignment["athlete_id"],
+                "round_id": heats["id"],
+                "place": place,
+                "mark": {"type": "time", "seconds": seconds},
+            }
+            response = requests.post("http://localhost:3000/results", json=result_data)
+            assert response.status_code == 200
+            result_ids.append(response.json()["id"])
+    fastest_loser = heats["heats"][0]["assignments"][1]["athlete_id"]
+
+    response = requests.post(
+        f"http://localhost:3000/rounds/{heats['id']}/advance",
+        json={"round_type": "final"},
+    )
+    assert response.status_code == 200
+    final = response.json()
+    assert final["number"] == 2
+    finalists = [a["athlete_id"] for a in final["heats"][0]["assignments"]]
+    assert len(finalists) == 3
+    assert fastest_loser in finalists
+
+    # A round can only be advanced once
+    response = requests.post(
+        f"http://localhost:3000/rounds/{heats['id']}/advance",
+        json={"round_type": "final"},
+    )
+    assert response.status_code == 409
+
+    response = requests.get(rounds_url)
+    assert [round["id"] for round in response.json()] == [heats["id"], final["id"]]
+
+    for result_id in result_ids:
+        requests.delete(f"http://localhost:3000/results/{result_id}")
+    for round_id in [heats["id"], final["id"]]:
+        requests.delete(f"http://localhost:3000/rounds/{round_id}")
+    requests.delete(f"http://localhost:3000/events/{event_id}")
+    for athlete_id in athlete_ids:
+        requests.delete(f"http://localhost:3000/athletes/{athlete_id}")
+
+
+# Test that a relay team is entered in an event and shows up on the calendar of every leg
+def test_relay_teams():
+    athlete_data = {
+        "first_name": "Jane",
+        "last_name": "Doe",
+        "bio": "Running Bio",
+        "birthday": "1990-01-01",
+    }
+    athlete_ids = [
+        requests.post("http://localhost:3000/athletes", json=athlete_data).json()["id"]
+        for _ in range(5)
+    ]
+    response = requests.post(
+        "http://localhost:3000/relay-teams",
+        json={"name": "Jamaica", "legs": athlete_ids[:3]},
+    )
+    assert response.status_code == 422
+    response = requests.post(
+        "http://localhost:3000/relay-teams",
+        json={"name": "Jamaica", "legs": athlete_ids[:4]},
+    )
+    assert response.status_code == 200
+    team_id = response.json()["id"]
+
+    event_data = {
+        "competition_id": str(UUID.uuid4()),
+        "athlete_ids": [],
+        "discipline": "4x100m_relay",
+        "category": {"gender": "women"},
+        "date_time": "2022-12-19T16:39:57-08:00",
+    }
+    event_id = requests.post("http://localhost:3000/events", json=event_data).json()[
+        "id"
+    ]
+    teams_url = f"http://localhost:3000/events/{event_id}/teams"
+
+    # Athletes run relays for a team, not on their own
+    response = requests.post(
+        f"http://localhost:3000/athletes/{athlete_ids[0]}/events/{event_id}"
+    )
+    assert response.status_code == 422
+
+    response = requests.post(f"{teams_url}/{team_id}")
+    assert response.status_code == 200
+    response = requests.post(f"{teams_url}/{team_id}")
+    assert response.status_code == 409
+    response = requests.get(teams_url)
+    assert [team["id"] for team in response.json()] == [team_id]
+
+    response = requests.get(f"http://localhost:3000/athletes/{athlete_ids[1]}/events")
+    assert response.json()[0]["relay"] == {"team_id": team_id, "leg": 2}
+
+    # Swapping a leg moves the calendar entry to the new athlete
+    response = requests.patch(
+        f"http://localhost:3000/relay-teams/{team_id}",
+        json={"legs": [athlete_ids[4]] + athlete_ids[1:4]},
+    )
+    assert response.status_code == 200
+    response = requests.get(f"http://localhost:3000/athletes/{athlete_ids[0]}/events")
+    assert response.json() == []
+    response = requests.get(f"http://localhost:3000/athletes/{athlete_ids[4]}/events")
+    assert response.json()[0]["relay"]["leg"] == 1
+
+    result_data = {
+        "event_id": event_id,
+        "team_id": team_id,
+        "place": 1,
+        "mark": {"type": "time", "seconds": 41.02},
+    }
+    response = requests.post("http://localhost:3000/results", json=result_data)
+    assert response.status_code == 200
+    result_id = response.json()["id"]
+
+    # A team entered in an event cannot be deleted
+    response = requests.delete(f"http://localhost:3000/relay-teams/{team_id}")
+    assert response.status_code == 409
+
+    requests.delete(f"http://localhost:3000/results/{result_id}")
+    response = requests.delete(f"{teams_url}/{team_id}")
+    assert response.status_code == 200
+    response = requests.get(f"http://localhost:3000/athletes/{athlete_ids[1]}/events")
+    assert response.json() == []
+
+    requests.delete(f"http://localhost:3000/relay-teams/{team_id}")
+    requests.delete(f"http://localhost:3000/events/{event_id}")
+    for athlete_id in athlete_ids:
+        requests.delete(f"http://localhost:3000/athletes/{athlete_id}")
+
+
+# Test that wind-aided marks are flagged and left out of the bests
+def test_wind_readings():
+    athlete_data = {
+        "first_name": "Jane",
+        "last_name": "Doe",
+        "bio": "Running Bio",
+        "birthday": "1990-01-01",
+    }
+    athlete_id = requests.post(
+        "http://localhost:3000/athletes", json=athlete_data
+    ).json()["id"]
+    event_ids = []
+    for discipline in ["long_jump", "long_jump", "shot_put"]:
+        event_data = {
+            "competition_id": str(UUID.uuid4()),
+            "athlete_ids": [athlete_id],
+            "discipline": discipline,
+            "category": {"gender": "women"},
+            "date_time": "2022-12-19T16:39:57-08:00",
+        }
+        event_ids.append(
+            requests.post("http://localhost:3000/events", json=event_data).json()["id"]
+        )
+
+    result_ids = []
+    for event_id, meters, wind in [(event_ids[0], 6.80, 2.0), (event_ids[1], 7.10, 2.6)]:
+        result_data = {
+            "event_id": event_id,
+            "athlete_id": athlete_id,
+            "place": 1,
+            "mark": {"type": "distance", "meters": meters},
+            "wind": wind,
+        }
+        response = requests.post("http://localhost:3000/results", json=result_data)
+        assert response.status_code == 200
+        assert response.json()["wind_aided"] == (wind > 2.0)
+        result_ids.append(response.json()["id"])
+
+    # No wind reading is taken in the throws
+    result_data = {
+        "event_id": event_ids[2],
+        "athlete_id": athlete_id,
+        "place": 1,
+        "mark": {"type": "distance", "meters": 18.2},
+        "wind": 1.0,
+    }
+    response = requests.post("http://localhost:3000/results", json=result_data)
+    assert response.status_code == 422
+
+    response = requests.get(f"http://localhost:3000/athletes/{athlete_id}/bests")
+    assert response.status_code == 200
+    assert response.json()[0]["personal_best"]["result_id"] == result_ids[0]
+
+    for result_id in result_ids:
+        requests.delete(f"http://localhost:3000/results/{result_id}")
+    for event_id in event_ids:
+        requests.delete(f"http://localhost:3000/events/{event_id}")
+    requests.delete(f"http://localhost:3000/athletes/{athlete_id}")
+
+
+# Test that attempts appended to field results set their marks and standings
+def test_field_attempts():
+    athlete_data = {
+        "first_name": "Jane",
+        "last_name": "Doe",
+        "bio": "Running Bio",
+        "birthday": "1990-01-01",
+    }
+    athlete_ids = [
+        requests.post("http://localhost:3000/athletes", json=athlete_data).json()["id"]
+        for _ in range(2)
+    ]
+    event_data = {
+        "competition_id": str(UUID.uuid4()),
+        "athlete_ids": athlete_ids,
+        "discipline": "high_jump",
+        "category": {"gender": "women"},
+        "date_time": "2022-12-19T16:39:57-08:00",
+    }
+    event_id = requests.post("http://localhost:3000/events", json=event_data).json()[
+        "id"
+    ]
+
+    result_ids = []
+    for athlete_id in athlete_ids:
+        result_data = {"event_id": event_id, "athlete_id": athlete_id, "place": 1}
+        response = requests.post("http://localhost:3000/results", json=result_data)
+        assert response.status_code == 200
+        assert "mark" not in response.json()
+        result_ids.append(response.json()["id"])
+
+    series = [
+        [(1.90, "o"), (1.94, "x"), (1.94, "o")],
+        [(1.90, "x"), (1.90, "o"), (1.94, "x"), (1.94, "o")],
+    ]
+    for result_id, attempts in zip(result_ids, series):
+        for height, outcome in attempts:
+            response = requests.post(
+                f"http://localhost:3000/results/{result_id}/attempts",
+                json={"type": "bar", "height": height, "outcome": outcome},
+            )
+            assert response.status_code == 200
+        assert response.json()["mark"] == {"type": "height", "meters": 1.94}
+
+    # The bar never goes down
+    response = requests.post(
+        f"http://localhost:3000/results/{result_ids[0]}/attempts",
+        json={"type": "bar", "height": 1.80, "outcome": "o"},
+    )
+    assert response.status_code == 422
+
+    # Both cleared 1.94 on their second attempt, but the second athlete failed once more on the way
+    response = requests.get(f"http://localhost:3000/events/{event_id}/field-standings")
+    assert response.status_code == 200
+    assert [(s["id"], s["rank"]) for s in response.json()] == [
+        (result_ids[0], 1),
+        (result_ids[1], 2),
+    ]
+
+    for result_id in result_ids:
+        requests.delete(f"http://localhost:3000/results/{result_id}")
+    requests.delete(f"http://localhost:3000/events/{event_id}")
+    for athlete_id in athlete_ids:
+        requests.delete(f"http://localhost:3000/athletes/{athlete_id}")
+
+
+def test_combined_standings():
+    athlete_data = {
+        "first_name": "Jane",
+        "last_name": "Doe",
+        "bio": "Running Bio",
+        "birthday": "1990-01-01",
+    }
+    athlete_id = requests.post("http://localhost:3000/athletes", json=athlete_data).json()[
+        "id"
+    ]
+    competition_id = str(UUID.uuid4())
+    heptathlon_data = {
+        "competition_id": competition_id,
+        "athlete_ids": [athlete_id],
+        "discipline": "heptathlon",
+        "category": {"gender": "women"},
+        "date_time": "2022-12-19T09:00:00-08:00",
+    }
+    heptathlon_id = requests.post(
+        "http://localhost:3000/events", json=heptathlon_data
+    ).json()["id"]
+
+    component_ids = []
+    for discipline, hour in [("100m_hurdles", 10), ("shot_put", 12)]:
+        component_data = {
+            "competition_id": competition_id,
+            "discipline": discipline,
+            "category": {"gender": "women"},
+            "date_time": f"2022-12-19T{hour}:00:00-08:00",
+            "combined_event_id": heptathlon_id,
+        }
+        response = requests.post("http://localhost:3000/events", json=component_data)
+        assert response.status_code == 200
+        component_ids.append(response.json()["id"])
+
+    # The 400m is not a heptathlon component
+    response = requests.post(
+        "http://localhost:3000/events",
+        json={
+            **component_data,
+            "discipline": "400m",
+        },
+    )
+    assert response.status_code == 422
+
+    result_ids = []
+    for event_id, mark in [
+        (component_ids[0], {"type": "time", "seconds": 13.85}),
+        (component_ids[1], {"type": "distance", "meters": 17.07}),
+    ]:
+        result_data = {
+            "event_id": event_id,
+            "athlete_id": athlete_id,
+            "place": 1,
+            "mark": mark,
+        }
+        result_ids.append(
+            requests.post("http://localhost:3000/results", json=result_data).json()["id"]
+        )
+
+    response = requests.get(
+        f"http://localhost:3000/events/{heptathlon_id}/combined-standings"
+    )
+    assert response.status_code == 200
+    standing = response.json()[0]
+    assert standing["athlete_id"] == athlete_id
+    assert standing["points"] == 2000
+    assert [s["running_total"] for s in standing["scores"]] == [1000, 2000]
+
+    # Only combined events have standings
+    response = requests.get(
+        f"http://localhost:3000/events/{component_ids[0]}/combined-standings"
+    )
+    assert response.status_code == 422
+
+    for result_id in result_ids:
+        requests.delete(f"http://localhost:3000/results/{result_id}")
+    for event_id in component_ids + [heptathlon_id]:
+        requests.delete(f"http://localhost:3000/events/{event_id}")
+    requests.delete(f"http://localhost:3000/athletes/{athlete_id}")
+
+
+def test_permissions():
+    fan = sign_in()
+    competition = {
+        "name": "Permission Meet",
+        "location": "Eugene, OR",
+        "start_date": "2024-06-01",
+        "end_date": "2024-06-02",
+    }
+
+    # Fans can only read and follow
+    response = fan.post("http://localhost:3000/competitions", json=competition)
+    assert response.status_code == 403
+
+    # An admin makes a user a meet director, who then directs the competitions they create
+    response = http.post(
+        "http://localhost:3000/auth/register",
+        json={"username": f"director-{UUID.uuid4()}", "password": "correct horse"},
+    )
+    director_id = response.json()["user"]["id"]
+    director = session_for(response.json())
+    response = requests.put(
+        f"http://localhost:3000/users/{director_id}/role", json={"role": "meet_director"}
+    )
+    assert response.status_code == 200
+    assert response.json()["role"] == "meet_director"
+    response = director.post("http://localhost:3000/competitions", json=competition)
+    assert response.status_code == 200
+    assert response.json()["director_id"] == director_id
+    competition_id = response.json()["id"]
+
+    # No one else can change it
+    response = fan.put(f"http://localhost:3000/competitions/{competition_id}", json=competition)
+    assert response.status_code == 403
+
+    # Only admins hand out roles
+    response = director.put(
+        f"http://localhost:3000/users/{director_id}/role", json={"role": "admin"}
+    )
+    assert response.status_code == 403
+    response = http.post(
+        "http://localhost:3000/auth/register",
+        json={"username": f"chancer-{UUID.uuid4()}", "password": "correct horse", "role": "admin"},
+    )
+    assert response.status_code == 403
+
+    response = director.delete(f"http://localhost:3000/competitions/{competition_id}")
+    assert response.status_code == 200
+
+
+def test_athlete_claim():
+    response = requests.post(
+        "http://localhost:3000/athletes",
+        json={
+            "first_name": "Sydney",
+            "last_name": "McLaughlin",
+            "bio": "400m hurdles",
+            "birthday": "1999-08-07",
+        },
+    )
+    assert response.status_code == 200
+    athlete = response.json()
+    assert "user_id" not in athlete
+
+    response = http.post(
+        "http://localhost:3000/auth/register",
+        json={"username": f"claimer-{UUID.uuid4()}", "password": "correct horse"},
+    )
+    user_id = response.json()["user"]["id"]
+    claimer = session_for(response.json())
+
+    # The claimer cannot change the profile until the claim is approved
+    response = claimer.patch(
+        f"http://localhost:3000/athletes/{athlete['id']}", json={"bio": "Mine now"}
+    )
+    assert response.status_code == 403
+
+    response = claimer.post(f"http://localhost:3000/athletes/{athlete['id']}/claims")
+    assert response.status_code == 200
+    response = claimer.post(f"http://localhost:3000/athletes/{athlete['id']}/claims")
+    assert response.status_code == 409
+    response = requests.get(
+        f"http://localhost:3000/athlete-claims?athlete_id={athlete['id']}"
+    )
+    assert [claim["user_id"] for claim in response.json()["items"]] == [user_id]
+
+    # Only admins approve claims
+    response = claimer.post(
+        f"http://localhost:3000/athletes/{athlete['id']}/claims/{user_id}/approve"
+    )
+    assert response.status_code == 403
+    response = requests.post(
+        f"http://localhost:3000/athletes/{athlete['id']}/claims/{user_id}/approve"
+    )
+    assert response.status_code == 200
+    assert response.json()["user_id"] == user_id
+
+    response = requests.get(f"http://localhost:3000/users/{user_id}")
+    assert response.json()["role"] == "athlete"
+    assert response.json()["athlete"]["id"] == athlete["id"]
+
+    # The athlete manages their own profile now
+    response = claimer.patch(
+        f"http://localhost:3000/athletes/{athlete['id']}", json={"bio": "Mine now"}
+    )
+    assert response.status_code == 200
+    assert response.json()["user_id"] == user_id
+
+    response = requests.delete(f"http://localhost:3000/athletes/{athlete['id']}")
+    assert response.status_code == 200
@@ -1,85 +1,122 @@
+mod migrations;
 mod routes;
 
 use aws_config::BehaviorVersion;
-use aws_sdk_dynamodb::error::SdkError;
+use aws_sdk_dynamodb::error::{DisplayErrorContext, SdkError};
 use aws_sdk_dynamodb::operation::create_table::CreateTableError;
-use aws_sdk_dynamodb::{self, Client};
-use axum::{
-    extract::State,
-    response::{IntoResponse, Response},
-    routing::get,
-    Json, Router,
+use aws_sdk_dynamodb::types::{
+    AttributeDefinition, CreateGlobalSecondaryIndexAction, GlobalSecondaryIndex,
+    GlobalSecondaryIndexUpdate, KeySchemaElement, KeyType, Projection, ProjectionType,
+    ProvisionedThroughput, ScalarAttributeType,
 };
+use aws_sdk_dynamodb::{self, Client};
+use axum::{extract::State, routing::get, Json, Router};
 
-use routes::{athlete, event, user};
-use routes::{competition, user_athlete};
-use tracing::{info, warn};
+use routes::error::ApiError;
+use routes::permission::Role;
+use routes::utils::SecondaryIndex;
+use routes::{athlete, athlete_event, event, personal_best, relay_team, result, round, user};
+use routes::{athlete_claim, athlete_manager, competition, result_entry, user_athlete, username};
+use tracing::{error, info, warn};
 
-async fn list_tables(State(db_client): State<Client>) -> Response {
-    let result = db_client.list_tables().send().await;
-    match result {
-        Ok(result) => {
-            let tables = result.table_names.unwrap();
-            Json(tables).into_response()
-        }
-        Err(err) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            err.to_string(),
-        )
-            .into_response(),
-    }
+async fn list_tables(State(db_client): State<Client>) -> Result<Json<Vec<String>>, ApiError> {
+    let result = db_client.list_tables().send().await?;
+    Ok(Json(result.table_names.unwrap_or_default()))
 }
 
-async fn make_table(
-    client: &Client,
-    table_name: &str,
-    partition_key: &str,
-    sort_key: Option<&str>,
-) -> Result<(), SdkError<CreateTableError>> {
-    // The attributeDefinition for the partition key
-    let ad = aws_sdk_dynamodb::types::AttributeDefinition::builder()
-        .attribute_name(partition_key)
-        .attribute_type(aws_sdk_dynamodb::types::ScalarAttributeType::S)
+/// The attribute definition for a string key attribute
+fn string_attribute(name: &str) -> AttributeDefinition {
+    AttributeDefinition::builder()
+        .attribute_name(name)
+        .attribute_type(ScalarAttributeType::S)
         .build()
-        .expect("creating AttributeDefinition");
+        .expect("creating AttributeDefinition")
+}
 
-    let ad = if let Some(sort_key) = sort_key {
-        let sort_key = aws_sdk_dynamodb::types::AttributeDefinition::builder()
-            .attribute_name(sort_key)
-            .attribute_type(aws_sdk_dynamodb::types::ScalarAttributeType::S)
-            .build()
-            .expect("creating AttributeDefinition for sort key");
-        vec![ad, sort_key]
-    } else {
-        vec![ad]
-    };
-    let ks = aws_sdk_dynamodb::types::KeySchemaElement::builder()
+/// The key schema for a partition key and an optional sort key
+fn key_schema(partition_key: &str, sort_key: Option<&str>) -> Vec<KeySchemaElement> {
+    let ks = KeySchemaElement::builder()
         .attribute_name(partition_key)
-        .key_type(aws_sdk_dynamodb::types::KeyType::Hash)
+        .key_type(KeyType::Hash)
         .build()
         .expect("creating KeySchemaElement for partition key");
-    let ks = if let Some(sort_key) = sort_key {
-        let sort_key = aws_sdk_dynamodb::types::KeySchemaElement::builder()
+    if let Some(sort_key) = sort_key {
+        let sort_key = KeySchemaElement::builder()
             .attribute_name(sort_key)
-            .key_type(aws_sdk_dynamodb::types::KeyType::Range)
+            .key_type(KeyType::Range)
             .build()
             .expect("creating KeySchemaElement for sort key");
         vec![ks, sort_key]
     } else {
         vec![ks]
-    };
-    let pt = aws_sdk_dynamodb::types::ProvisionedThroughput::builder()
+    }
+}
+
+fn provisioned_throughput() -> ProvisionedThroughput {
+    ProvisionedThroughput::builder()
         .read_capacity_units(10)
         .write_capacity_units(5)
         .build()
-        .expect("creating ProvisionedThroughput");
+        .expect("creating ProvisionedThroughput")
+}
+
+fn global_secondary_index(index: &SecondaryIndex) -> GlobalSecondaryIndex {
+    GlobalSecondaryIndex::builder()
+        .index_name(index.name)
+        .set_key_schema(Some(key_schema(index.partition_key, index.sort_key)))
+        .projection(
+            Projection::builder()
+                .projection_type(ProjectionType::All)
+                .build(),
+        )
+        .provisioned_throughput(provisioned_throughput())
+        .build()
+        .expect("creating GlobalSecondaryIndex")
+}
+
+/// The definitions of every key attribute used by the table and its indexes, without duplicates
+fn attribute_definitions(
+    partition_key: &str,
+    sort_key: Option<&str>,
+    indexes: &[SecondaryIndex],
+) -> Vec<AttributeDefinition> {
+    let mut names = vec![partition_key];
+    names.extend(sort_key);
+    for index in indexes {
+        names.push(index.partition_key);
+        names.extend(index.sort_key);
+    }
+    let mut ad: Vec<AttributeDefinition> = Vec::new();
+    for name in names {
+        if !ad
+            .iter()
+            .any(|definition| definition.attribute_name() == name)
+        {
+            ad.push(string_attribute(name));
+        }
+    }
+    ad
+}
 
+async fn make_table(
+    client: &Client,
+    table_name: &str,
+    partition_key: &str,
+    sort_key: Option<&str>,
+    indexes: &[SecondaryIndex],
+) -> Result<(), SdkError<CreateTableError>> {
+    let gsis: Vec<GlobalSecondaryIndex> = indexes.iter().map(global_secondary_index).collect();
     match client
         .create_table()
         .table_name(table_name)
-        .set_key_schema(Some(ks))
-        .set_attribute_definitions(Some(ad))
-        .provisioned_throughput(pt)
+        .set_key_schema(Some(key_schema(partition_key, sort_key)))
+        .set_attribute_definitions(Some(attribute_definitions(
+            partition_key,
+            sort_key,
+            indexes,
+        )))
+        .set_global_secondary_indexes((!gsis.is_empty()).then_some(gsis))
+        .provisioned_throughput(provisioned_throughput())
         .send()
         .await
     {
@@ -88,36 +125,261 @@ async fn make_table(
     }
 }
 
+/// Add any of `indexes` that a table created by an older version of the app is missing
+async fn add_missing_indexes(
+    client: &Client,
+    table_name: &str,
+    indexes: &[SecondaryIndex],
+) -> Result<(), ApiError> {
+    let description = client
+        .describe_table()
+        .table_name(table_name)
+        .send()
+        .await
+        .map_err(|err| {
+            ApiError::Internal(format!(
+                "Could not describe the {table_name} table: {}",
+                DisplayErrorContext(&err)
+            ))
+        })?;
+    let existing: Vec<String> = description
+        .table
+        .and_then(|table| table.global_secondary_indexes)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|index| index.index_name)
+        .collect();
+    // DynamoDB only allows one index to be created per UpdateTable call
+    for index in indexes
+        .iter()
+        .filter(|index| !existing.iter().any(|name| name == index.name))
+    {
+        info!(
+            "Adding the {} index to the {} table.",
+            index.name, table_name
+        );
+        client
+            .update_table()
+            .table_name(table_name)
+            .set_attribute_definitions(Some(attribute_definitions(
+                index.partition_key,
+                index.sort_key,
+                &[],
+            )))
+            .global_secondary_index_updates(
+                GlobalSecondaryIndexUpdate::builder()
+                    .create(
+                        CreateGlobalSecondaryIndexAction::builder()
+                            .index_name(index.name)
+                            .set_key_schema(Some(key_schema(index.partition_key, index.sort_key)))
+                            .projection(
+                                Projection::builder()
+                                    .projection_type(ProjectionType::All)
+                                    .build(),
+                            )
+                            .provisioned_throughput(provisioned_throughput())
+                            .build()
+                            .map_err(|err| ApiError::Internal(err.to_string()))?,
+                    )
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|err| {
+                ApiError::Internal(format!(
+                    "Could not add the {} index to the {table_name} table: {}",
+                    index.name,
+                    DisplayErrorContext(&err)
+                ))
+            })?;
+    }
+    Ok(())
+}
+
+/// Create the table if it does not exist yet, or add the indexes it is missing if it does
 async fn check_and_create_table(
     client: &Client,
     table_name: &str,
     partition_key: &str,
     sort_key: Option<&str>,
-) {
-    // Check to see if the "competitions" table exists
-    let tables = client.list_tables().send().await.unwrap();
+    indexes: &[SecondaryIndex],
+) -> Result<(), ApiError> {
+    let tables = client.list_tables().send().await.map_err(|err| {
+        ApiError::Internal(format!(
+            "Could not list the tables while checking for {table_name}: {}",
+            DisplayErrorContext(&err)
+        ))
+    })?;
     if tables
         .table_names
-        .unwrap()
+        .unwrap_or_default()
         .contains(&String::from(table_name))
     {
         info!("{} table exists", table_name);
+        add_missing_indexes(client, table_name, indexes).await
     } else {
         /* Create table */
         info!("Creating the {} table.", table_name);
-        match make_table(client, table_name, partition_key, sort_key).await {
-            Err(e) => {
-                warn!("Got an error creating the table:");
-                warn!("{}", e);
-                std::process::exit(1);
-            }
-            Ok(_) => {
-                info!("Created the table.");
-            }
-        }
+        make_table(client, table_name, partition_key, sort_key, indexes)
+            .await
+            .map_err(|err| {
+                ApiError::Internal(format!(
+                    "Could not create the {table_name} table: {}",
+                    DisplayErrorContext(&err)
+                ))
+            })?;
+        info!("Created the table.");
+        Ok(())
     }
 }
 
+/// Make sure every table the app uses exists with all of its indexes
+async fn create_tables(client: &Client) -> Result<(), ApiError> {
+    check_and_create_table(
+        client,
+        competition::TABLE_NAME,
+        competition::ID_KEY,
+        None,
+        &[],
+    )
+    .await?;
+    check_and_create_table(
+        client,
+        athlete::TABLE_NAME,
+        athlete::ID_KEY,
+        None,
+        &[SecondaryIndex {
+            name: athlete::USER_INDEX,
+            partition_key: athlete::USER_ID_KEY,
+            sort_key: None,
+        }],
+    )
+    .await?;
+    check_and_create_table(
+        client,
+        athlete_claim::TABLE_NAME,
+        athlete_claim::ATHLETE_ID_KEY,
+        Some(athlete_claim::USER_ID_KEY),
+        &[],
+    )
+    .await?;
+    check_and_create_table(
+        client,
+        athlete_manager::TABLE_NAME,
+        athlete_manager::USER_ID_KEY,
+        None,
+        &[],
+    )
+    .await?;
+    check_and_create_table(
+        client,
+        event::TABLE_NAME,
+        event::ID_KEY,
+        None,
+        &[SecondaryIndex {
+            name: event::COMPETITION_INDEX,
+            partition_key: event::COMPETITION_ID_KEY,
+            sort_key: Some(event::DATE_TIME_KEY),
+        }],
+    )
+    .await?;
+    check_and_create_table(client, user::TABLE_NAME, user::ID_KEY, None, &[]).await?;
+    check_and_create_table(
+        client,
+        username::TABLE_NAME,
+        username::USERNAME_KEY,
+        None,
+        &[],
+    )
+    .await?;
+    check_and_create_table(
+        client,
+        result::TABLE_NAME,
+        result::ID_KEY,
+        None,
+        &[
+            SecondaryIndex {
+                name: result::EVENT_INDEX,
+                partition_key: result::EVENT_ID_KEY,
+                sort_key: None,
+            },
+            SecondaryIndex {
+                name: result::ATHLETE_INDEX,
+                partition_key: result::ATHLETE_ID_KEY,
+                sort_key: None,
+            },
+        ],
+    )
+    .await?;
+    check_and_create_table(
+        client,
+        result_entry::TABLE_NAME,
+        result_entry::ENTRY_KEY,
+        None,
+        &[],
+    )
+    .await?;
+    check_and_create_table(
+        client,
+        round::TABLE_NAME,
+        round::ID_KEY,
+        None,
+        &[SecondaryIndex {
+            name: round::EVENT_INDEX,
+            partition_key: round::EVENT_ID_KEY,
+            sort_key: None,
+        }],
+    )
+    .await?;
+    check_and_create_table(
+        client,
+        relay_team::TABLE_NAME,
+        relay_team::ID_KEY,
+        None,
+        &[],
+    )
+    .await?;
+    check_and_create_table(
+        client,
+        personal_best::TABLE_NAME,
+        personal_best::ATHLETE_ID_KEY,
+        Some(personal_best::DISCIPLINE_KEY),
+        &[],
+    )
+    .await?;
+    check_and_create_table(
+        client,
+        athlete_event::TABLE_NAME,
+        athlete_event::ATHLETE_ID_KEY,
+        Some(athlete_event::EVENT_ID_KEY),
+        &[],
+    )
+    .await?;
+    check_and_create_table(
+        client,
+        user_athlete::TABLE_NAME,
+        user_athlete::USER_ID_KEY,
+        Some(user_athlete::ATHLETE_ID_KEY),
+        &[SecondaryIndex {
+            name: user_athlete::ATHLETE_INDEX,
+            partition_key: user_athlete::ATHLETE_ID_KEY,
+            sort_key: Some(user_athlete::USER_ID_KEY),
+        }],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Give the user `username` the admin role
+async fn make_admin(db_client: &Client, username: &str) -> Result<(), ApiError> {
+    let user = routes::username::find_user(db_client, username)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("No user is called {username}")))?;
+    user::store_role(db_client, user.id, Role::Admin).await?;
+    info!("Made {} an admin", username);
+    Ok(())
+}
+
 async fn build_client() -> Client {
     let config = aws_config::defaults(BehaviorVersion::latest())
         .test_credentials()
@@ -140,25 +402,41 @@ async fn main() {
     tracing_subscriber::fmt::init();
     info!("Starting server");
     let client = build_client().await;
+    // `cargo run -- migrate <name>` runs a one-off migration instead of the server
+    // `cargo run -- make-admin <username>` makes a registered user an admin, since only admins hand out roles
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, command, name] = args.as_slice() {
+        if command == "migrate" {
+            if let Err(e) = migrations::run(&client, name).await {
+                warn!("Migration {} failed: {:?}", name, e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        if command == "make-admin" {
+            if let Err(e) = make_admin(&client, name).await {
+                warn!("Could not make {} an admin: {:?}", name, e);
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
+    // Tokens are signed with a secret that has to outlive the process, so it is never made up here
+    match std::env::var("JWT_SECRET") {
+        Ok(secret) if !secret.is_empty() => routes::auth::init_keys(secret.as_bytes()),
+        _ => {
+            error!("Set JWT_SECRET to the secret access and refresh tokens are signed with");
+            std::process::exit(1);
+        }
+    }
+    if let Err(e) = create_tables(&client).await {
+        error!("Could not set up the tables: {:?}", e);
+        std::process::exit(1);
+    }
     // Create Axum router
-    check_and_create_table(&client, competition::TABLE_NAME, competition::ID_KEY, None).await;
-    check_and_create_table(&client, athlete::TABLE_NAME, athlete::ID_KEY, None).await;
-    check_and_create_table(&client, event::TABLE_NAME, event::ID_KEY, None).await;
-    check_and_create_table(&client, user::TABLE_NAME, user::ID_KEY, None).await;
-    check_and_create_table(
-        &client,
-        user_athlete::TABLE_NAME,
-        user_athlete::USER_ID_KEY,
-        Some(user_athlete::ATHLETE_ID_KEY),
-    )
-    .await;
-
     let app = Router::new()
         .route("/tables", get(list_tables)) // TODO: Remove this route. Only used to test things
-        .nest("/competitions", competition::competition_routes())
-        .nest("/athletes", athlete::athlete_routes())
-        .nest("/events", event::event_routes())
-        .nest("/users", user::user_routes())
+        .merge(routes::router())
         .with_state(client);
 
     // Start the Axum server
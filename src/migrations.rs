@@ -0,0 +1,499 @@
+//! One-off data migrations, run with `cargo run -- migrate <name>` instead of starting the server
+
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::types::{AttributeValue, TransactWriteItem, Update};
+use aws_sdk_dynamodb::Client;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::routes::athlete::{self, Athlete};
+use crate::routes::athlete_event::AthleteEvent;
+use crate::routes::athlete_manager::{self, AthleteManager};
+use crate::routes::error::ApiError;
+use crate::routes::event::{self, Event};
+use crate::routes::personal_best;
+use crate::routes::result::{self, EventResult};
+use crate::routes::result_entry::{self, ResultEntry};
+use crate::routes::transaction::{self, transact, TransactionStep, MAX_TRANSACTION_STEPS};
+use crate::routes::user::{self, User};
+use crate::routes::user_athlete::UserAthlete;
+use crate::routes::username::{self, Username};
+use crate::routes::utils::{fetch_items, get_uuid, get_uuid_list, get_uuid_set, uuid_list, Item};
+
+/// Run the migration called `name`
+pub async fn run(db_client: &Client, name: &str) -> Result<(), ApiError> {
+    match name {
+        "event-entry-lists" => migrate_event_entry_lists(db_client).await,
+        "event-disciplines" => migrate_event_disciplines(db_client).await,
+        "usernames" => migrate_usernames(db_client).await,
+        "user-follows" => migrate_user_follows(db_client).await,
+        "result-entries" => migrate_result_entries(db_client).await,
+        "athlete-managers" => migrate_athlete_managers(db_client).await,
+        "personal-bests" => migrate_personal_bests(db_client).await,
+        _ => Err(ApiError::BadRequest(format!(
+            "Unknown migration {name}, expected one of: event-entry-lists, event-disciplines, usernames, user-follows, result-entries, athlete-managers, personal-bests"
+        ))),
+    }
+}
+
+/// Move the single `athlete_id` of events written before events held an entry list into `athlete_ids`
+/// Each event is rewritten in a transaction that also adds the athlete's calendar entry, so the two stay in
+/// sync. Running the migration again skips events that were already migrated.
+pub async fn migrate_event_entry_lists(db_client: &Client) -> Result<(), ApiError> {
+    let mut migrated = 0;
+    let mut exclusive_start_key = None;
+    loop {
+        let result = db_client
+            .scan()
+            .table_name(event::TABLE_NAME)
+            .filter_expression("attribute_exists(#legacy)")
+            .expression_attribute_names("#legacy", event::LEGACY_ATHLETE_ID_KEY)
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+        for row in result.items.unwrap_or_default() {
+            match migrate_event(db_client, &row).await {
+                Ok(()) => migrated += 1,
+                Err(err) => warn!(
+                    "Could not migrate event {:?}: {:?}",
+                    row.get(event::ID_KEY),
+                    err
+                ),
+            }
+        }
+        exclusive_start_key = result.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+    info!("Migrated the entry lists of {} events", migrated);
+    Ok(())
+}
+
+async fn migrate_event(
+    db_client: &Client,
+    row: &HashMap<String, AttributeValue>,
+) -> Result<(), ApiError> {
+    let event_id = get_uuid::<Event>(row, event::ID_KEY)?;
+    let athlete_id = get_uuid::<Event>(row, event::LEGACY_ATHLETE_ID_KEY)?;
+    // An athlete may have been entered through the calendar since, which already created the list
+    let mut athlete_ids: Vec<Uuid> = match row.contains_key(event::ATHLETE_IDS_KEY) {
+        true => get_uuid_list::<Event>(row, event::ATHLETE_IDS_KEY)?,
+        false => Vec::new(),
+    };
+    if !athlete_ids.contains(&athlete_id) {
+        athlete_ids.insert(0, athlete_id);
+    }
+    let update = Update::builder()
+        .table_name(event::TABLE_NAME)
+        .key(event::ID_KEY, AttributeValue::S(event_id.to_string()))
+        .condition_expression("attribute_exists(#legacy)")
+        .update_expression("SET #ids = :ids REMOVE #legacy")
+        .expression_attribute_names("#ids", event::ATHLETE_IDS_KEY)
+        .expression_attribute_names("#legacy", event::LEGACY_ATHLETE_ID_KEY)
+        .expression_attribute_values(":ids", uuid_list(&athlete_ids))
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    transact(
+        db_client,
+        vec![
+            TransactionStep::new(
+                TransactWriteItem::builder().update(update).build(),
+                Some(ApiError::Conflict(format!(
+                    "Event {event_id} was migrated by someone else"
+                ))),
+            ),
+            transaction::put(AthleteEvent::new(athlete_id, event_id))?,
+        ],
+    )
+    .await
+}
+
+/// Replace the free-text `name` of events written before events had a discipline with a discipline and category
+/// Names that do not match a discipline are logged, counted and left for someone to fix by hand. The bests of every
+/// athlete with a result in a migrated event are recomputed, since they are grouped by discipline.
+pub async fn migrate_event_disciplines(db_client: &Client) -> Result<(), ApiError> {
+    let mut migrated = 0;
+    let mut failed = 0;
+    let mut exclusive_start_key = None;
+    loop {
+        let result = db_client
+            .scan()
+            .table_name(event::TABLE_NAME)
+            .filter_expression("attribute_exists(#legacy) AND attribute_not_exists(#discipline)")
+            .expression_attribute_names("#legacy", event::LEGACY_NAME_KEY)
+            .expression_attribute_names("#discipline", event::DISCIPLINE_KEY)
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+        for row in result.items.unwrap_or_default() {
+            // Decoding reads the discipline and category out of the legacy name
+            let outcome = match Event::from_hashmap(row.clone()) {
+                Ok(event) => migrate_event_discipline(db_client, event).await,
+                Err(err) => Err(ApiError::from(err)),
+            };
+            match outcome {
+                Ok(()) => migrated += 1,
+                Err(err) => {
+                    failed += 1;
+                    warn!(
+                        "Could not migrate event {:?} named {:?}: {:?}",
+                        row.get(event::ID_KEY),
+                        row.get(event::LEGACY_NAME_KEY),
+                        err
+                    )
+                }
+            }
+        }
+        exclusive_start_key = result.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+    info!("Migrated the disciplines of {} events", migrated);
+    if failed > 0 {
+        warn!(
+            "{} events could not be migrated and need their discipline set by hand",
+            failed
+        );
+    }
+    Ok(())
+}
+
+async fn migrate_event_discipline(db_client: &Client, event: Event) -> Result<(), ApiError> {
+    let category = event.event_data.category;
+    db_client
+        .update_item()
+        .table_name(event::TABLE_NAME)
+        .key(event::ID_KEY, AttributeValue::S(event.id.to_string()))
+        .condition_expression("attribute_exists(#legacy)")
+        .update_expression(
+            "SET #discipline = :discipline, #gender = :gender, #age_group = :age_group REMOVE #legacy",
+        )
+        .expression_attribute_names("#legacy", event::LEGACY_NAME_KEY)
+        .expression_attribute_names("#discipline", event::DISCIPLINE_KEY)
+        .expression_attribute_names("#gender", event::GENDER_KEY)
+        .expression_attribute_names("#age_group", event::AGE_GROUP_KEY)
+        .expression_attribute_values(
+            ":discipline",
+            AttributeValue::S(event.event_data.discipline.code().to_string()),
+        )
+        .expression_attribute_values(
+            ":gender",
+            AttributeValue::S(category.gender.as_str().to_string()),
+        )
+        .expression_attribute_values(
+            ":age_group",
+            AttributeValue::S(category.age_group.as_str().to_string()),
+        )
+        .send()
+        .await?;
+    personal_best::refresh_event(db_client, event.id).await;
+    Ok(())
+}
+
+/// Reserve the username of every user written before usernames were unique
+/// Users whose username is already reserved by another user (in any case) are logged and have to be renamed by
+/// hand. Running the migration again skips users that already hold their reservation.
+pub async fn migrate_usernames(db_client: &Client) -> Result<(), ApiError> {
+    let mut migrated = 0;
+    let mut exclusive_start_key = None;
+    loop {
+        let result = db_client
+            .scan()
+            .table_name(user::TABLE_NAME)
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+        for row in result.items.unwrap_or_default() {
+            let outcome = match User::from_hashmap(row.clone()) {
+                Ok(user) => reserve_username(db_client, &user).await,
+                Err(err) => Err(ApiError::from(err)),
+            };
+            match outcome {
+                Ok(()) => migrated += 1,
+                Err(err) => warn!(
+                    "Could not reserve the username of user {:?}: {:?}",
+                    row.get(user::ID_KEY),
+                    err
+                ),
+            }
+        }
+        exclusive_start_key = result.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+    info!("Reserved the usernames of {} users", migrated);
+    Ok(())
+}
+
+async fn reserve_username(db_client: &Client, user: &User) -> Result<(), ApiError> {
+    db_client
+        .put_item()
+        .table_name(username::TABLE_NAME)
+        .set_item(Some(Username::new(user.username(), user.id).into_hashmap()))
+        .condition_expression("attribute_not_exists(#pk) OR #user = :user")
+        .expression_attribute_names("#pk", username::USERNAME_KEY)
+        .expression_attribute_names("#user", username::USER_ID_KEY)
+        .expression_attribute_values(":user", AttributeValue::S(user.id.to_string()))
+        .send()
+        .await
+        .map_err(|err| {
+            if err
+                .as_service_error()
+                .is_some_and(|err| err.is_conditional_check_failed_exception())
+            {
+                ApiError::Conflict(format!(
+                    "The username {} is reserved by another user",
+                    user.username()
+                ))
+            } else {
+                ApiError::from(err)
+            }
+        })?;
+    Ok(())
+}
+
+/// Move the `athletes_following` set of users written before follows moved to the `user_athlete` table into it
+/// Athletes that no longer exist are logged and dropped. The set is only removed once every follow is written,
+/// and writing a follow twice is harmless, so a migration that stopped half way can be run again.
+pub async fn migrate_user_follows(db_client: &Client) -> Result<(), ApiError> {
+    let mut migrated = 0;
+    let mut exclusive_start_key = None;
+    loop {
+        let result = db_client
+            .scan()
+            .table_name(user::TABLE_NAME)
+            .filter_expression("attribute_exists(#legacy)")
+            .expression_attribute_names("#legacy", user::LEGACY_ATHLETES_FOLLOWING_KEY)
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+        for row in result.items.unwrap_or_default() {
+            match migrate_user_follow_list(db_client, &row).await {
+                Ok(()) => migrated += 1,
+                Err(err) => warn!(
+                    "Could not migrate the follows of user {:?}: {:?}",
+                    row.get(user::ID_KEY),
+                    err
+                ),
+            }
+        }
+        exclusive_start_key = result.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+    info!("Migrated the follows of {} users", migrated);
+    Ok(())
+}
+
+async fn migrate_user_follow_list(
+    db_client: &Client,
+    row: &HashMap<String, AttributeValue>,
+) -> Result<(), ApiError> {
+    let user_id = get_uuid::<User>(row, user::ID_KEY)?;
+    let athlete_ids = get_uuid_set::<User>(row, user::LEGACY_ATHLETES_FOLLOWING_KEY)?;
+    let athletes = fetch_items::<Athlete>(db_client, &athlete_ids).await?;
+    for athlete_id in &athlete_ids {
+        if !athletes.iter().any(|athlete| athlete.id == *athlete_id) {
+            warn!(
+                "User {} follows athlete {}, which no longer exists",
+                user_id, athlete_id
+            );
+        }
+    }
+    let follows = athletes
+        .iter()
+        .map(|athlete| transaction::put(UserAthlete::new(user_id, athlete.id)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut follows = follows.into_iter().peekable();
+    while follows.peek().is_some() {
+        transact(
+            db_client,
+            follows.by_ref().take(MAX_TRANSACTION_STEPS).collect(),
+        )
+        .await?;
+    }
+    db_client
+        .update_item()
+        .table_name(user::TABLE_NAME)
+        .key(user::ID_KEY, AttributeValue::S(user_id.to_string()))
+        .condition_expression("attribute_exists(#pk)")
+        .update_expression("REMOVE #legacy")
+        .expression_attribute_names("#pk", user::ID_KEY)
+        .expression_attribute_names("#legacy", user::LEGACY_ATHLETES_FOLLOWING_KEY)
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// Reserve the entry of every result written before an athlete or team could only have one result per event
+/// Results whose entry is already reserved by another result are logged, and one of them has to be deleted by
+/// hand. Running the migration again skips results that already hold their reservation.
+pub async fn migrate_result_entries(db_client: &Client) -> Result<(), ApiError> {
+    let mut migrated = 0;
+    let mut exclusive_start_key = None;
+    loop {
+        let scan = db_client
+            .scan()
+            .table_name(result::TABLE_NAME)
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+        for row in scan.items.unwrap_or_default() {
+            let outcome = match EventResult::from_hashmap(row.clone()) {
+                Ok(result) => reserve_result_entry(db_client, &result).await,
+                Err(err) => Err(ApiError::from(err)),
+            };
+            match outcome {
+                Ok(()) => migrated += 1,
+                Err(err) => warn!(
+                    "Could not reserve the entry of result {:?}: {:?}",
+                    row.get(result::ID_KEY),
+                    err
+                ),
+            }
+        }
+        exclusive_start_key = scan.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+    info!("Reserved the entries of {} results", migrated);
+    Ok(())
+}
+
+async fn reserve_result_entry(db_client: &Client, result: &EventResult) -> Result<(), ApiError> {
+    let reservation = ResultEntry::new(result);
+    let entry = reservation.entry.clone();
+    db_client
+        .put_item()
+        .table_name(result_entry::TABLE_NAME)
+        .set_item(Some(reservation.into_hashmap()))
+        .condition_expression("attribute_not_exists(#pk) OR #result = :result")
+        .expression_attribute_names("#pk", result_entry::ENTRY_KEY)
+        .expression_attribute_names("#result", result_entry::RESULT_ID_KEY)
+        .expression_attribute_values(":result", AttributeValue::S(result.id.to_string()))
+        .send()
+        .await
+        .map_err(|err| {
+            if err
+                .as_service_error()
+                .is_some_and(|err| err.is_conditional_check_failed_exception())
+            {
+                ApiError::Conflict(format!("The entry {entry} is reserved by another result"))
+            } else {
+                ApiError::from(err)
+            }
+        })?;
+    Ok(())
+}
+
+/// Reserve the user of every athlete linked before a user could only be linked to one athlete
+/// A user linked to more than one athlete is logged; the extra links have to be removed by hand.
+pub async fn migrate_athlete_managers(db_client: &Client) -> Result<(), ApiError> {
+    let mut migrated = 0;
+    let mut exclusive_start_key = None;
+    loop {
+        let scan = db_client
+            .scan()
+            .table_name(athlete::TABLE_NAME)
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+        for row in scan.items.unwrap_or_default() {
+            let outcome = match Athlete::from_hashmap(row.clone()) {
+                Ok(athlete) => match athlete.user_id() {
+                    Some(user_id) => reserve_athlete_manager(db_client, user_id, athlete.id)
+                        .await
+                        .map(|()| 1),
+                    None => Ok(0),
+                },
+                Err(err) => Err(ApiError::from(err)),
+            };
+            match outcome {
+                Ok(reserved) => migrated += reserved,
+                Err(err) => warn!(
+                    "Could not reserve the user of athlete {:?}: {:?}",
+                    row.get(athlete::ID_KEY),
+                    err
+                ),
+            }
+        }
+        exclusive_start_key = scan.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+    info!("Reserved the users of {} athletes", migrated);
+    Ok(())
+}
+
+async fn reserve_athlete_manager(
+    db_client: &Client,
+    user_id: Uuid,
+    athlete_id: Uuid,
+) -> Result<(), ApiError> {
+    db_client
+        .put_item()
+        .table_name(athlete_manager::TABLE_NAME)
+        .set_item(Some(
+            AthleteManager {
+                user_id,
+                athlete_id,
+            }
+            .into_hashmap(),
+        ))
+        .condition_expression("attribute_not_exists(#pk) OR #athlete = :athlete")
+        .expression_attribute_names("#pk", athlete_manager::USER_ID_KEY)
+        .expression_attribute_names("#athlete", athlete_manager::ATHLETE_ID_KEY)
+        .expression_attribute_values(":athlete", AttributeValue::S(athlete_id.to_string()))
+        .send()
+        .await
+        .map_err(|err| {
+            if err
+                .as_service_error()
+                .is_some_and(|err| err.is_conditional_check_failed_exception())
+            {
+                ApiError::Conflict(format!("User {user_id} is linked to another athlete"))
+            } else {
+                ApiError::from(err)
+            }
+        })?;
+    Ok(())
+}
+
+/// Recompute the bests of every athlete, after the rules for which marks count changed
+/// Athletes whose bests could not be refreshed are logged by the refresh.
+pub async fn migrate_personal_bests(db_client: &Client) -> Result<(), ApiError> {
+    let mut refreshed = 0;
+    let mut exclusive_start_key = None;
+    loop {
+        let scan = db_client
+            .scan()
+            .table_name(athlete::TABLE_NAME)
+            .projection_expression("#id")
+            .expression_attribute_names("#id", athlete::ID_KEY)
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+        let mut athlete_ids = Vec::new();
+        for row in scan.items.unwrap_or_default() {
+            match get_uuid::<Athlete>(&row, athlete::ID_KEY) {
+                Ok(athlete_id) => athlete_ids.push(athlete_id),
+                Err(err) => warn!("Could not read an athlete id: {:?}", err),
+            }
+        }
+        personal_best::refresh(db_client, &athlete_ids).await;
+        refreshed += athlete_ids.len();
+        exclusive_start_key = scan.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+    info!("Refreshed the bests of {} athletes", refreshed);
+    Ok(())
+}
@@ -1,26 +1,47 @@
 use std::collections::HashMap;
 
 use aws_sdk_dynamodb::{self, types::AttributeValue, Client};
-use axum::routing::{delete, get, post};
+use axum::extract::{Path, State};
+use axum::routing::{delete, get, patch, post, put};
+use axum::Json;
+
+use super::batch::{batch_add_items, batch_delete_items};
+use super::filter::{Expression, ListFilter, ListQuery};
+use super::permission::{Caller, Guarded, Role};
+use super::user::User;
+use super::user_athlete::{self, UserAthlete};
+use super::utils::fetch_items;
 use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use super::utils::Item;
-use super::utils::{add_item, delete_item, get_item, get_items};
+use super::error::ApiError;
+use super::utils::{
+    add_item, delete_item, fetch_existing_item, get_item, get_items, patch_item, query_all,
+    update_item,
+};
+use super::utils::{get_date, get_string, get_uuid, Item, ItemDecodeError};
 pub const TABLE_NAME: &str = "athletes";
 pub const ID_KEY: &str = "Id";
 pub const FIRST_NAME_KEY: &str = "FirstName";
 pub const LAST_NAME_KEY: &str = "LastName";
 pub const BIO_KEY: &str = "Bio";
 pub const BIRTHDAY_KEY: &str = "Birthday";
+pub const USER_ID_KEY: &str = "UserId";
+const CREATED_BY_KEY: &str = "CreatedBy";
+
+/// Athletes keyed by the user account that manages them
+pub const USER_INDEX: &str = "UserId-index";
 
 // Define your Competition struct
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-struct Athlete {
-    id: Uuid,
+pub struct Athlete {
+    pub id: Uuid,
     #[serde(flatten)]
     athlete_data: AthleteData,
+    /// The meet director who created the athlete, who manages it until a user does
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    created_by: Option<Uuid>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -29,12 +50,108 @@ struct AthleteData {
     last_name: String,
     bio: String,
     birthday: NaiveDate,
+    /// The user account of the athlete, who can change the profile and calendar
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_id: Option<Uuid>,
+}
+
+impl Athlete {
+    pub fn user_id(&self) -> Option<Uuid> {
+        self.athlete_data.user_id
+    }
+
+    /// Check that the caller is an admin or the user account of the athlete
+    /// An athlete without a user account is managed by the meet director who created it.
+    pub fn authorize_owner(&self, caller: &Caller) -> Result<(), ApiError> {
+        let creator = caller.role == Role::MeetDirector
+            && self.user_id().is_none()
+            && self.created_by == Some(caller.user_id);
+        match caller.is_admin() || self.user_id() == Some(caller.user_id) || creator {
+            true => Ok(()),
+            false => Err(ApiError::Forbidden(format!(
+                "Only athlete {} can change their profile and calendar",
+                self.id
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Athlete {
+    /// An athlete without a user account, for the tests of other modules
+    pub fn sample(first_name: &str, last_name: &str) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            athlete_data: AthleteData {
+                first_name: first_name.to_string(),
+                last_name: last_name.to_string(),
+                bio: "A bio".to_string(),
+                birthday: NaiveDate::from_ymd_opt(1990, 1, 1).unwrap(),
+                user_id: None,
+            },
+            created_by: None,
+        }
+    }
+}
+
+/// The athlete profile the user manages, if any
+pub async fn find_by_user(db_client: &Client, user_id: Uuid) -> Result<Option<Athlete>, ApiError> {
+    Ok(
+        query_all::<Athlete>(db_client, Some(USER_INDEX), USER_ID_KEY, user_id)
+            .await?
+            .pop(),
+    )
 }
 
 impl From<AthleteData> for Athlete {
     fn from(athlete_data: AthleteData) -> Self {
         let id = Uuid::new_v4();
-        Self { id, athlete_data }
+        Self {
+            id,
+            athlete_data,
+            created_by: None,
+        }
+    }
+}
+
+/// The query-string filters accepted by `GET /athletes`
+#[derive(Debug, Default, Deserialize)]
+struct AthleteFilter {
+    last_name: Option<String>,
+    sort: Option<AthleteSort>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+enum AthleteSort {
+    #[serde(rename = "last_name")]
+    LastName,
+    #[serde(rename = "-last_name")]
+    LastNameDesc,
+}
+
+impl ListFilter<Athlete> for AthleteFilter {
+    fn list_query(&self) -> ListQuery {
+        let mut filter = Expression::new("f");
+        if let Some(last_name) = &self.last_name {
+            filter.equals(LAST_NAME_KEY, AttributeValue::S(last_name.clone()));
+        }
+        ListQuery::scan(filter)
+    }
+
+    fn sorts_within_page(&self) -> bool {
+        self.sort.is_some()
+    }
+
+    fn sort(&self, items: &mut [Athlete]) {
+        match self.sort {
+            None => {}
+            Some(AthleteSort::LastName) => {
+                items.sort_by(|a, b| a.athlete_data.last_name.cmp(&b.athlete_data.last_name))
+            }
+            Some(AthleteSort::LastNameDesc) => {
+                items.sort_by(|a, b| b.athlete_data.last_name.cmp(&a.athlete_data.last_name))
+            }
+        }
     }
 }
 
@@ -46,22 +163,30 @@ impl Item for Athlete {
         ID_KEY
     }
 
-    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Option<Self> {
-        let id = map.get(ID_KEY)?.as_s().unwrap();
-        let id = Uuid::parse_str(id).unwrap();
-        let first_name: String = map.get(FIRST_NAME_KEY)?.as_s().unwrap().to_string();
-        let last_name: String = map.get(LAST_NAME_KEY)?.as_s().unwrap().to_string();
-        let bio: String = map.get(BIO_KEY)?.as_s().unwrap().to_string();
-        let birthday_string: String = map.get(BIRTHDAY_KEY)?.as_s().unwrap().to_string();
-        let birthday = NaiveDate::parse_from_str(&birthday_string, "%Y-%m-%d").unwrap();
-        Some(Self {
+    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError> {
+        let id = get_uuid::<Self>(&map, ID_KEY)?;
+        let first_name = get_string::<Self>(&map, FIRST_NAME_KEY)?;
+        let last_name = get_string::<Self>(&map, LAST_NAME_KEY)?;
+        let bio = get_string::<Self>(&map, BIO_KEY)?;
+        let birthday = get_date::<Self>(&map, BIRTHDAY_KEY)?;
+        let user_id = match map.contains_key(USER_ID_KEY) {
+            true => Some(get_uuid::<Self>(&map, USER_ID_KEY)?),
+            false => None,
+        };
+        let created_by = match map.contains_key(CREATED_BY_KEY) {
+            true => Some(get_uuid::<Self>(&map, CREATED_BY_KEY)?),
+            false => None,
+        };
+        Ok(Self {
             id,
             athlete_data: AthleteData {
                 first_name,
                 last_name,
                 bio,
                 birthday,
+                user_id,
             },
+            created_by,
         })
     }
     fn into_hashmap(self) -> HashMap<String, AttributeValue> {
@@ -83,16 +208,91 @@ impl Item for Athlete {
             BIRTHDAY_KEY.to_string(),
             AttributeValue::S(self.athlete_data.birthday.to_string()),
         );
+        if let Some(user_id) = self.athlete_data.user_id {
+            map.insert(
+                USER_ID_KEY.to_string(),
+                AttributeValue::S(user_id.to_string()),
+            );
+        }
+        if let Some(created_by) = self.created_by {
+            map.insert(
+                CREATED_BY_KEY.to_string(),
+                AttributeValue::S(created_by.to_string()),
+            );
+        }
         map
     }
 }
 
+impl Guarded for Athlete {
+    /// Athletes create their own profile, and meet directors create profiles for the athletes they enter
+    /// A new athlete is never linked to a user: the user has to claim it, and is linked once an admin approves the
+    /// claim, so nobody can take over a profile without approval.
+    async fn authorize_create(
+        &mut self,
+        _db_client: &Client,
+        caller: &Caller,
+    ) -> Result<(), ApiError> {
+        caller.require_role(&[Role::MeetDirector, Role::Athlete], "create athletes")?;
+        if self.athlete_data.user_id.is_some() {
+            return Err(ApiError::UnprocessableEntity(
+                "An athlete is linked to a user by approving a claim (POST /athletes/:athlete_id/claims)"
+                    .to_string(),
+            ));
+        }
+        if caller.role == Role::MeetDirector {
+            self.created_by = Some(caller.user_id);
+        }
+        Ok(())
+    }
+
+    async fn authorize_modify(&self, _db_client: &Client, caller: &Caller) -> Result<(), ApiError> {
+        self.authorize_owner(caller)
+    }
+
+    /// The user that manages an athlete only changes by approving a claim, so the stored link is kept
+    async fn authorize_replace(
+        &mut self,
+        old: &Self,
+        db_client: &Client,
+        caller: &Caller,
+    ) -> Result<(), ApiError> {
+        old.authorize_modify(db_client, caller).await?;
+        self.created_by = old.created_by;
+        self.athlete_data.user_id = old.athlete_data.user_id;
+        Ok(())
+    }
+}
+
+/// Get every user that follows the athlete
+/// Followers are listed without the athletes they follow, which would take a query per follower.
+async fn get_followers(
+    State(db_client): State<Client>,
+    Path(athlete_id): Path<Uuid>,
+) -> Result<Json<Vec<User>>, ApiError> {
+    fetch_existing_item::<Athlete>(&db_client, athlete_id).await?;
+    let follows = query_all::<UserAthlete>(
+        &db_client,
+        Some(user_athlete::ATHLETE_INDEX),
+        user_athlete::ATHLETE_ID_KEY,
+        athlete_id,
+    )
+    .await?;
+    let user_ids: Vec<Uuid> = follows.iter().map(|follow| follow.user_id).collect();
+    fetch_items::<User>(&db_client, &user_ids).await.map(Json)
+}
+
 pub fn athlete_routes() -> axum::Router<Client> {
     axum::Router::new()
         .route("/", post(add_item::<Athlete, AthleteData>))
-        .route("/", get(get_items::<Athlete>))
-        .route("/:competition_id", get(get_item::<Athlete>))
-        .route("/:competition_id", delete(delete_item::<Athlete>))
+        .route("/", get(get_items::<Athlete, AthleteFilter>))
+        .route("/batch", post(batch_add_items::<Athlete, AthleteData>))
+        .route("/batch", delete(batch_delete_items::<Athlete>))
+        .route("/:athlete_id", get(get_item::<Athlete>))
+        .route("/:athlete_id", delete(delete_item::<Athlete>))
+        .route("/:athlete_id", put(update_item::<Athlete, AthleteData>))
+        .route("/:athlete_id", patch(patch_item::<Athlete, AthleteData>))
+        .route("/:athlete_id/followers", get(get_followers))
 }
 
 // Test that we can convert an Athlete into a hashmap and back
@@ -109,11 +309,31 @@ mod tests {
                 last_name: "Doe".to_string(),
                 bio: "A bio".to_string(),
                 birthday: NaiveDate::from_ymd_opt(1990, 1, 1).unwrap(),
+                user_id: Some(Uuid::new_v4()),
             },
+            created_by: Some(Uuid::new_v4()),
         };
         let cloned_athlete = athlete.clone();
         let hashmap = cloned_athlete.into_hashmap();
         let athlete2 = Athlete::from_hashmap(hashmap).unwrap();
         assert_eq!(athlete, athlete2);
     }
+
+    #[test]
+    fn test_creator_manages_athlete_until_claimed() {
+        let director = Caller {
+            user_id: Uuid::new_v4(),
+            role: Role::MeetDirector,
+        };
+        let other = Caller {
+            user_id: Uuid::new_v4(),
+            ..director
+        };
+        let mut athlete = Athlete::sample("John", "Doe");
+        athlete.created_by = Some(director.user_id);
+        assert!(athlete.authorize_owner(&director).is_ok());
+        assert!(athlete.authorize_owner(&other).is_err());
+        athlete.athlete_data.user_id = Some(Uuid::new_v4());
+        assert!(athlete.authorize_owner(&director).is_err());
+    }
 }
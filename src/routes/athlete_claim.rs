@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::{
+    self,
+    types::{AttributeValue, Delete, TransactWriteItem, Update},
+    Client,
+};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get, post};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::athlete::{self, find_by_user, Athlete};
+use super::athlete_manager;
+use super::error::ApiError;
+use super::filter::{Expression, ListFilter, ListQuery};
+use super::pagination::{Page, PageParams};
+use super::permission::{Caller, Role};
+use super::transaction::{exists_check, put_new, transact, TransactionStep};
+use super::user::{self, User};
+use super::utils::{
+    fetch_existing_item, get_date_time, get_items, get_uuid, Item, ItemDecodeError,
+};
+
+pub const TABLE_NAME: &str = "athlete_claims";
+pub const ATHLETE_ID_KEY: &str = "athlete_id";
+pub const USER_ID_KEY: &str = "user_id";
+const REQUESTED_AT_KEY: &str = "requested_at";
+
+/// A user's request to manage an athlete profile, waiting for an admin to approve or reject it
+/// Approving or rejecting a claim deletes it, so every stored claim is pending.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct AthleteClaim {
+    pub athlete_id: Uuid,
+    pub user_id: Uuid,
+    pub requested_at: DateTime<Utc>,
+}
+
+impl AthleteClaim {
+    pub fn new(athlete_id: Uuid, user_id: Uuid) -> Self {
+        Self {
+            athlete_id,
+            user_id,
+            requested_at: Utc::now(),
+        }
+    }
+
+    /// The full primary key of the claim (the partition key and the sort key)
+    pub fn key(athlete_id: Uuid, user_id: Uuid) -> HashMap<String, AttributeValue> {
+        HashMap::from([
+            (
+                ATHLETE_ID_KEY.to_string(),
+                AttributeValue::S(athlete_id.to_string()),
+            ),
+            (
+                USER_ID_KEY.to_string(),
+                AttributeValue::S(user_id.to_string()),
+            ),
+        ])
+    }
+}
+
+impl Item for AthleteClaim {
+    fn table_name() -> &'static str {
+        TABLE_NAME
+    }
+
+    fn partition_key_name() -> &'static str {
+        ATHLETE_ID_KEY
+    }
+
+    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError> {
+        Ok(Self {
+            athlete_id: get_uuid::<Self>(&map, ATHLETE_ID_KEY)?,
+            user_id: get_uuid::<Self>(&map, USER_ID_KEY)?,
+            requested_at: get_date_time::<Self>(&map, REQUESTED_AT_KEY)?,
+        })
+    }
+
+    fn into_hashmap(self) -> HashMap<String, AttributeValue> {
+        let mut map = Self::key(self.athlete_id, self.user_id);
+        map.insert(
+            REQUESTED_AT_KEY.to_string(),
+            AttributeValue::S(self.requested_at.to_rfc3339()),
+        );
+        map
+    }
+}
+
+/// The query-string filters accepted by `GET /athlete-claims`
+#[derive(Debug, Default, Deserialize)]
+struct ClaimFilter {
+    athlete_id: Option<Uuid>,
+    user_id: Option<Uuid>,
+}
+
+impl ListFilter<AthleteClaim> for ClaimFilter {
+    fn list_query(&self) -> ListQuery {
+        let mut filter = Expression::new("f");
+        if let Some(athlete_id) = self.athlete_id {
+            filter.equals(ATHLETE_ID_KEY, AttributeValue::S(athlete_id.to_string()));
+        }
+        if let Some(user_id) = self.user_id {
+            filter.equals(USER_ID_KEY, AttributeValue::S(user_id.to_string()));
+        }
+        ListQuery::scan(filter)
+    }
+}
+
+/// Check that the athlete has no user account yet and the user does not manage another athlete
+async fn check_unclaimed(
+    db_client: &Client,
+    athlete_id: Uuid,
+    user_id: Uuid,
+) -> Result<(), ApiError> {
+    let athlete = fetch_existing_item::<Athlete>(db_client, athlete_id).await?;
+    if athlete.user_id().is_some() {
+        return Err(ApiError::Conflict(format!(
+            "Athlete {athlete_id} is already managed by a user"
+        )));
+    }
+    if let Some(managed) = find_by_user(db_client, user_id).await? {
+        return Err(ApiError::Conflict(format!(
+            "User {user_id} already manages athlete {}",
+            managed.id
+        )));
+    }
+    Ok(())
+}
+
+/// Ask to manage the athlete profile as the signed in user
+async fn add_claim(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Path(athlete_id): Path<Uuid>,
+) -> Result<Json<AthleteClaim>, ApiError> {
+    check_unclaimed(&db_client, athlete_id, caller.user_id).await?;
+    let claim = AthleteClaim::new(athlete_id, caller.user_id);
+    transact(
+        &db_client,
+        vec![put_new(
+            claim.clone(),
+            ApiError::Conflict(format!("You have already claimed athlete {athlete_id}")),
+        )?],
+    )
+    .await?;
+    Ok(Json(claim))
+}
+
+/// Link the athlete to the user that claimed it, which only admins can do
+/// Fans are made athletes, and the claim is deleted in the same transaction that sets the link and reserves the
+/// user as the manager of the athlete, so a user is never linked to two athletes.
+async fn approve_claim(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Path((athlete_id, user_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Athlete>, ApiError> {
+    caller.require_role(&[], "approve athlete claims")?;
+    check_unclaimed(&db_client, athlete_id, user_id).await?;
+    let claimant = fetch_existing_item::<User>(&db_client, user_id).await?;
+    let reserve_manager = athlete_manager::reserve(&db_client, user_id, athlete_id).await?;
+    let remove_claim = Delete::builder()
+        .table_name(TABLE_NAME)
+        .set_key(Some(AthleteClaim::key(athlete_id, user_id)))
+        .condition_expression("attribute_exists(#pk)")
+        .expression_attribute_names("#pk", ATHLETE_ID_KEY)
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    let link_athlete = Update::builder()
+        .table_name(athlete::TABLE_NAME)
+        .key(athlete::ID_KEY, AttributeValue::S(athlete_id.to_string()))
+        .condition_expression("attribute_exists(#pk) AND attribute_not_exists(#user)")
+        .update_expression("SET #user = :user")
+        .expression_attribute_names("#pk", athlete::ID_KEY)
+        .expression_attribute_names("#user", athlete::USER_ID_KEY)
+        .expression_attribute_values(":user", AttributeValue::S(user_id.to_string()))
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    let user_missing = ApiError::NotFound(format!("User {user_id} does not exist"));
+    let user_step = match claimant.role() {
+        Role::Fan => {
+            let make_athlete = Update::builder()
+                .table_name(user::TABLE_NAME)
+                .key(user::ID_KEY, AttributeValue::S(user_id.to_string()))
+                .condition_expression("attribute_exists(#pk)")
+                .update_expression("SET #role = :role")
+                .expression_attribute_names("#pk", user::ID_KEY)
+                .expression_attribute_names("#role", user::ROLE_KEY)
+                .expression_attribute_values(
+                    ":role",
+                    AttributeValue::S(Role::Athlete.as_str().to_string()),
+                )
+                .build()
+                .map_err(|err| ApiError::Internal(err.to_string()))?;
+            TransactionStep::new(
+                TransactWriteItem::builder().update(make_athlete).build(),
+                Some(user_missing),
+            )
+        }
+        _ => exists_check::<User>(user_id, user_missing)?,
+    };
+    transact(
+        &db_client,
+        vec![
+            TransactionStep::new(
+                TransactWriteItem::builder().delete(remove_claim).build(),
+                Some(ApiError::NotFound(format!(
+                    "User {user_id} has not claimed athlete {athlete_id}"
+                ))),
+            ),
+            TransactionStep::new(
+                TransactWriteItem::builder().update(link_athlete).build(),
+                Some(ApiError::Conflict(format!(
+                    "Athlete {athlete_id} was deleted or claimed by another user"
+                ))),
+            ),
+            user_step,
+            reserve_manager,
+        ],
+    )
+    .await?;
+    fetch_existing_item::<Athlete>(&db_client, athlete_id)
+        .await
+        .map(Json)
+}
+
+/// List the pending claims, which only admins can do
+async fn get_claims(
+    caller: Caller,
+    db_client: State<Client>,
+    page: Query<PageParams>,
+    filter: Query<ClaimFilter>,
+) -> Result<Json<Page<AthleteClaim>>, ApiError> {
+    caller.require_role(&[], "review athlete claims")?;
+    get_items::<AthleteClaim, ClaimFilter>(db_client, page, filter).await
+}
+
+/// Reject a claim as an admin, or withdraw it as the user that made it
+/// Deleting a claim that does not exist still succeeds
+async fn delete_claim(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Path((athlete_id, user_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, ApiError> {
+    caller.require_self(user_id, "withdraw the claims of other users")?;
+    db_client
+        .delete_item()
+        .table_name(TABLE_NAME)
+        .set_key(Some(AthleteClaim::key(athlete_id, user_id)))
+        .send()
+        .await?;
+    Ok(StatusCode::OK)
+}
+
+/// Routes under `/athletes/:athlete_id`
+pub fn athlete_claim_routes() -> axum::Router<Client> {
+    axum::Router::new()
+        .route("/:athlete_id/claims", post(add_claim))
+        .route("/:athlete_id/claims/:user_id", delete(delete_claim))
+        .route("/:athlete_id/claims/:user_id/approve", post(approve_claim))
+}
+
+/// Routes under `/athlete-claims`, where admins find the claims to review
+pub fn claim_routes() -> axum::Router<Client> {
+    axum::Router::new().route("/", get(get_claims))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_athlete_claim_into_hashmap() {
+        let claim = AthleteClaim::new(Uuid::new_v4(), Uuid::new_v4());
+        let map = claim.clone().into_hashmap();
+        assert_eq!(map.len(), 3);
+        assert_eq!(AthleteClaim::from_hashmap(map).unwrap(), claim);
+    }
+}
@@ -1,7 +1,22 @@
-use super::utils::Item;
-use super::utils::{add_item, delete_item, get_item, get_items};
-use aws_sdk_dynamodb::{self, types::AttributeValue, Client};
-use axum::routing::{delete, get, post};
+use super::athlete::Athlete;
+use super::error::ApiError;
+use super::event::{self, Event};
+use super::permission::{Caller, Role};
+use super::transaction::{self, exists_check, put_new, transact, TransactionStep};
+use super::utils::fetch_items;
+use super::utils::{
+    fetch_existing_item, fetch_item, get_number, get_uuid, query_all, uuid_list, Item,
+    ItemDecodeError,
+};
+use aws_sdk_dynamodb::{
+    self,
+    types::{AttributeValue, TransactWriteItem, Update},
+    Client,
+};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::Json;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
@@ -9,11 +24,56 @@ use uuid::Uuid;
 pub const TABLE_NAME: &str = "athlete_events";
 pub const ATHLETE_ID_KEY: &str = "athlete_id";
 pub const EVENT_ID_KEY: &str = "event_id";
+const RELAY_TEAM_ID_KEY: &str = "relay_team_id";
+const LEG_KEY: &str = "leg";
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-struct AthleteEvent {
-    athlete_id: Uuid,
-    event_id: Uuid,
+pub struct AthleteEvent {
+    pub athlete_id: Uuid,
+    pub event_id: Uuid,
+    /// The team and leg the athlete runs, for relay events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relay: Option<RelayLeg>,
+}
+
+/// The leg an athlete runs for a relay team, starting at 1
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RelayLeg {
+    pub team_id: Uuid,
+    pub leg: u32,
+}
+
+impl AthleteEvent {
+    pub fn new(athlete_id: Uuid, event_id: Uuid) -> Self {
+        Self {
+            athlete_id,
+            event_id,
+            relay: None,
+        }
+    }
+
+    /// The calendar entry of an athlete running a leg of a relay team
+    pub fn relay(athlete_id: Uuid, event_id: Uuid, relay: RelayLeg) -> Self {
+        Self {
+            athlete_id,
+            event_id,
+            relay: Some(relay),
+        }
+    }
+
+    /// The full primary key of the entry (the partition key and the sort key)
+    pub fn key(&self) -> HashMap<String, AttributeValue> {
+        HashMap::from([
+            (
+                ATHLETE_ID_KEY.to_string(),
+                AttributeValue::S(self.athlete_id.to_string()),
+            ),
+            (
+                EVENT_ID_KEY.to_string(),
+                AttributeValue::S(self.event_id.to_string()),
+            ),
+        ])
+    }
 }
 
 impl Item for AthleteEvent {
@@ -25,16 +85,21 @@ impl Item for AthleteEvent {
         ATHLETE_ID_KEY
     }
 
-    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Option<Self> {
-        let athlete_id = map.get(ATHLETE_ID_KEY)?.as_s().ok()?;
-        let athlete_id = Uuid::parse_str(athlete_id).ok()?;
-
-        let event_id = map.get(EVENT_ID_KEY)?.as_s().ok()?;
-        let event_id = Uuid::parse_str(event_id).ok()?;
+    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError> {
+        let athlete_id = get_uuid::<Self>(&map, ATHLETE_ID_KEY)?;
+        let event_id = get_uuid::<Self>(&map, EVENT_ID_KEY)?;
+        let relay = match map.contains_key(RELAY_TEAM_ID_KEY) {
+            true => Some(RelayLeg {
+                team_id: get_uuid::<Self>(&map, RELAY_TEAM_ID_KEY)?,
+                leg: get_number::<Self, u32>(&map, LEG_KEY)?,
+            }),
+            false => None,
+        };
 
-        Some(Self {
+        Ok(Self {
             athlete_id,
             event_id,
+            relay,
         })
     }
 
@@ -48,10 +113,186 @@ impl Item for AthleteEvent {
             EVENT_ID_KEY.to_string(),
             AttributeValue::S(self.event_id.to_string()),
         );
+        if let Some(relay) = self.relay {
+            map.insert(
+                RELAY_TEAM_ID_KEY.to_string(),
+                AttributeValue::S(relay.team_id.to_string()),
+            );
+            map.insert(
+                LEG_KEY.to_string(),
+                AttributeValue::N(relay.leg.to_string()),
+            );
+        }
         map
     }
 }
 
+/// Athletes are put on the calendar of a relay event by entering their team, not one by one
+fn reject_relay(event: Option<&Event>) -> Result<(), ApiError> {
+    match event {
+        Some(event) if event.event_data.is_relay() => Err(ApiError::UnprocessableEntity(format!(
+            "Event {} is a relay, enter or withdraw the team through /events/{}/teams",
+            event.id, event.id
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Check that the caller may change the calendar of the athlete
+/// The athlete manages their own calendar, and the meet director of the event can enter or scratch athletes.
+async fn authorize_calendar(
+    db_client: &Client,
+    caller: &Caller,
+    athlete_id: Uuid,
+    event: Option<&Event>,
+) -> Result<(), ApiError> {
+    if caller.is_admin() {
+        return Ok(());
+    }
+    let athlete = fetch_existing_item::<Athlete>(db_client, athlete_id).await?;
+    let owner_check = athlete.authorize_owner(caller);
+    match (owner_check, event) {
+        (Ok(()), _) => Ok(()),
+        (Err(_), Some(event)) if caller.role == Role::MeetDirector => {
+            caller
+                .require_director(db_client, event.event_data.competition_id)
+                .await
+        }
+        (Err(err), _) => Err(err),
+    }
+}
+
+/// Add an event to the athlete's calendar
+/// The athlete is checked and added to the event's entry list in the same transaction that adds the entry
+async fn add_athlete_event(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Path((athlete_id, event_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<AthleteEvent>, ApiError> {
+    let event = fetch_item::<Event>(&db_client, event_id).await?;
+    reject_relay(event.as_ref())?;
+    authorize_calendar(&db_client, &caller, athlete_id, event.as_ref()).await?;
+    let athlete_event = AthleteEvent::new(athlete_id, event_id);
+    let enter_athlete = Update::builder()
+        .table_name(event::TABLE_NAME)
+        .key(event::ID_KEY, AttributeValue::S(event_id.to_string()))
+        .condition_expression("attribute_exists(#pk)")
+        .update_expression("SET #ids = list_append(if_not_exists(#ids, :empty), :athlete)")
+        .expression_attribute_names("#pk", event::ID_KEY)
+        .expression_attribute_names("#ids", event::ATHLETE_IDS_KEY)
+        .expression_attribute_values(":empty", uuid_list(&[]))
+        .expression_attribute_values(":athlete", uuid_list(&[athlete_id]))
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    transact(
+        &db_client,
+        vec![
+            exists_check::<Athlete>(
+                athlete_id,
+                ApiError::NotFound(format!("Athlete {athlete_id} does not exist")),
+            )?,
+            TransactionStep::new(
+                TransactWriteItem::builder().update(enter_athlete).build(),
+                Some(ApiError::NotFound(format!(
+                    "Event {event_id} does not exist"
+                ))),
+            ),
+            put_new(
+                athlete_event.clone(),
+                ApiError::Conflict(format!(
+                    "Event {event_id} is already on the calendar of athlete {athlete_id}"
+                )),
+            )?,
+        ],
+    )
+    .await?;
+    Ok(Json(athlete_event))
+}
+
+/// Remove an event from the athlete's calendar, dropping the athlete from the event's entry list
+/// Removing an event that is not on the calendar still succeeds
+async fn remove_athlete_event(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Path((athlete_id, event_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, ApiError> {
+    let athlete_event = AthleteEvent::new(athlete_id, event_id);
+    let mut steps = vec![transaction::delete::<AthleteEvent>(athlete_event.key())?];
+    let event = fetch_item::<Event>(&db_client, event_id).await?;
+    reject_relay(event.as_ref())?;
+    authorize_calendar(&db_client, &caller, athlete_id, event.as_ref()).await?;
+    let entry = event.and_then(|event| {
+        event
+            .event_data
+            .athlete_ids
+            .iter()
+            .position(|id| *id == athlete_id)
+    });
+    if let Some(index) = entry {
+        // Lists can only be edited by index, so make sure the athlete is still at that index
+        let leave_event = Update::builder()
+            .table_name(event::TABLE_NAME)
+            .key(event::ID_KEY, AttributeValue::S(event_id.to_string()))
+            .condition_expression(format!("#ids[{index}] = :athlete"))
+            .update_expression(format!("REMOVE #ids[{index}]"))
+            .expression_attribute_names("#ids", event::ATHLETE_IDS_KEY)
+            .expression_attribute_values(":athlete", AttributeValue::S(athlete_id.to_string()))
+            .build()
+            .map_err(|err| ApiError::Internal(err.to_string()))?;
+        steps.push(TransactionStep::new(
+            TransactWriteItem::builder().update(leave_event).build(),
+            Some(ApiError::Conflict(format!(
+                "The entry list of event {event_id} changed, please retry the request"
+            ))),
+        ));
+    }
+    transact(&db_client, steps).await?;
+    Ok(StatusCode::OK)
+}
+
+/// An event on an athlete's calendar, with the leg the athlete runs if it is a relay
+#[derive(Clone, Debug, Serialize)]
+struct CalendarEntry {
+    #[serde(flatten)]
+    event: Event,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    relay: Option<RelayLeg>,
+}
+
+/// Get every event on the athlete's calendar, ordered by `date_time`
+async fn get_athlete_events(
+    State(db_client): State<Client>,
+    Path(athlete_id): Path<Uuid>,
+) -> Result<Json<Vec<CalendarEntry>>, ApiError> {
+    fetch_existing_item::<Athlete>(&db_client, athlete_id).await?;
+    let entries = query_all::<AthleteEvent>(&db_client, None, ATHLETE_ID_KEY, athlete_id).await?;
+    let event_ids: Vec<Uuid> = entries.iter().map(|entry| entry.event_id).collect();
+    let relays: HashMap<Uuid, Option<RelayLeg>> = entries
+        .into_iter()
+        .map(|entry| (entry.event_id, entry.relay))
+        .collect();
+    let mut calendar: Vec<CalendarEntry> = fetch_items::<Event>(&db_client, &event_ids)
+        .await?
+        .into_iter()
+        .map(|event| CalendarEntry {
+            relay: relays.get(&event.id).copied().flatten(),
+            event,
+        })
+        .collect();
+    calendar.sort_by_key(|entry| entry.event.event_data.date_time);
+    Ok(Json(calendar))
+}
+
+/// The calendar routes, nested under `/athletes`
+pub fn athlete_event_routes() -> axum::Router<Client> {
+    axum::Router::new()
+        .route("/:athlete_id/events", get(get_athlete_events))
+        .route(
+            "/:athlete_id/events/:event_id",
+            post(add_athlete_event).delete(remove_athlete_event),
+        )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -61,10 +302,22 @@ mod tests {
         let athlete_event = AthleteEvent {
             athlete_id: Uuid::new_v4(),
             event_id: Uuid::new_v4(),
+            relay: None,
         };
         let cloned_athlete_event = athlete_event.clone();
         let hashmap = cloned_athlete_event.into_hashmap();
         let athlete_event2 = AthleteEvent::from_hashmap(hashmap).unwrap();
         assert_eq!(athlete_event, athlete_event2);
     }
+
+    #[test]
+    fn test_relay_leg_into_hashmap() {
+        let relay = RelayLeg {
+            team_id: Uuid::new_v4(),
+            leg: 4,
+        };
+        let athlete_event = AthleteEvent::relay(Uuid::new_v4(), Uuid::new_v4(), relay);
+        let hashmap = athlete_event.clone().into_hashmap();
+        assert_eq!(AthleteEvent::from_hashmap(hashmap).unwrap(), athlete_event);
+    }
 }
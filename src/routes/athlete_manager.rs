@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::{
+    self,
+    types::{AttributeValue, Delete, Put, TransactWriteItem, Update},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::athlete::{self, find_by_user, Athlete};
+use super::error::ApiError;
+use super::transaction::TransactionStep;
+use super::utils::{fetch_item, get_uuid, Item, ItemDecodeError};
+
+pub const TABLE_NAME: &str = "athlete_managers";
+pub const USER_ID_KEY: &str = "user_id";
+pub const ATHLETE_ID_KEY: &str = "athlete_id";
+
+/// The reservation of the one athlete a user can manage
+/// Reservations are keyed by the user, so linking a second athlete to the same user fails the transaction that
+/// writes the link.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct AthleteManager {
+    pub user_id: Uuid,
+    pub athlete_id: Uuid,
+}
+
+impl Item for AthleteManager {
+    fn table_name() -> &'static str {
+        TABLE_NAME
+    }
+
+    fn partition_key_name() -> &'static str {
+        USER_ID_KEY
+    }
+
+    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError> {
+        Ok(Self {
+            user_id: get_uuid::<Self>(&map, USER_ID_KEY)?,
+            athlete_id: get_uuid::<Self>(&map, ATHLETE_ID_KEY)?,
+        })
+    }
+
+    fn into_hashmap(self) -> HashMap<String, AttributeValue> {
+        HashMap::from([
+            (
+                USER_ID_KEY.to_string(),
+                AttributeValue::S(self.user_id.to_string()),
+            ),
+            (
+                ATHLETE_ID_KEY.to_string(),
+                AttributeValue::S(self.athlete_id.to_string()),
+            ),
+        ])
+    }
+}
+
+/// A step that reserves the user as the manager of the athlete, failing with a 409 if the user manages another one
+/// A reservation left behind by an athlete that was deleted, or no longer links to the user, is taken over as long
+/// as it still holds that athlete when the transaction runs.
+pub async fn reserve(
+    db_client: &Client,
+    user_id: Uuid,
+    athlete_id: Uuid,
+) -> Result<TransactionStep, ApiError> {
+    let put = Put::builder().table_name(TABLE_NAME).set_item(Some(
+        AthleteManager {
+            user_id,
+            athlete_id,
+        }
+        .into_hashmap(),
+    ));
+    let put = match fetch_item::<AthleteManager>(db_client, user_id).await? {
+        None => put
+            .condition_expression("attribute_not_exists(#pk)")
+            .expression_attribute_names("#pk", USER_ID_KEY),
+        Some(reservation) => {
+            let managed = fetch_item::<Athlete>(db_client, reservation.athlete_id).await?;
+            if managed.is_some_and(|athlete| athlete.user_id() == Some(user_id)) {
+                return Err(ApiError::Conflict(format!(
+                    "User {user_id} already manages athlete {}",
+                    reservation.athlete_id
+                )));
+            }
+            put.condition_expression("#athlete = :stale")
+                .expression_attribute_names("#athlete", ATHLETE_ID_KEY)
+                .expression_attribute_values(
+                    ":stale",
+                    AttributeValue::S(reservation.athlete_id.to_string()),
+                )
+        }
+    }
+    .build()
+    .map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok(TransactionStep::new(
+        TransactWriteItem::builder().put(put).build(),
+        Some(ApiError::Conflict(format!(
+            "User {user_id} was linked to another athlete, please retry the request"
+        ))),
+    ))
+}
+
+/// The steps that part a user being deleted from the athlete it manages
+/// The reservation of the user is freed and the athlete loses its link, so the athlete can be claimed again.
+/// Athletes linked before links were reserved are found through their user instead.
+pub async fn release(db_client: &Client, user_id: Uuid) -> Result<Vec<TransactionStep>, ApiError> {
+    let mut steps = Vec::new();
+    let reservation = fetch_item::<AthleteManager>(db_client, user_id).await?;
+    if let Some(reservation) = &reservation {
+        let delete = Delete::builder()
+            .table_name(TABLE_NAME)
+            .key(USER_ID_KEY, AttributeValue::S(user_id.to_string()))
+            .condition_expression("attribute_not_exists(#pk) OR #athlete = :athlete")
+            .expression_attribute_names("#pk", USER_ID_KEY)
+            .expression_attribute_names("#athlete", ATHLETE_ID_KEY)
+            .expression_attribute_values(
+                ":athlete",
+                AttributeValue::S(reservation.athlete_id.to_string()),
+            )
+            .build()
+            .map_err(|err| ApiError::Internal(err.to_string()))?;
+        steps.push(TransactionStep::new(
+            TransactWriteItem::builder().delete(delete).build(),
+            Some(ApiError::Conflict(format!(
+                "User {user_id} was linked to another athlete, please retry the request"
+            ))),
+        ));
+    }
+    let managed = match reservation {
+        Some(reservation) => fetch_item::<Athlete>(db_client, reservation.athlete_id)
+            .await?
+            .filter(|athlete| athlete.user_id() == Some(user_id)),
+        None => find_by_user(db_client, user_id).await?,
+    };
+    if let Some(managed) = managed {
+        let unlink = Update::builder()
+            .table_name(athlete::TABLE_NAME)
+            .key(athlete::ID_KEY, AttributeValue::S(managed.id.to_string()))
+            .condition_expression("#user = :user")
+            .update_expression("REMOVE #user")
+            .expression_attribute_names("#user", athlete::USER_ID_KEY)
+            .expression_attribute_values(":user", AttributeValue::S(user_id.to_string()))
+            .build()
+            .map_err(|err| ApiError::Internal(err.to_string()))?;
+        steps.push(TransactionStep::new(
+            TransactWriteItem::builder().update(unlink).build(),
+            Some(ApiError::Conflict(format!(
+                "Athlete {} was deleted or linked to another user, please retry the request",
+                managed.id
+            ))),
+        ));
+    }
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_athlete_manager_into_hashmap() {
+        let reservation = AthleteManager {
+            user_id: Uuid::new_v4(),
+            athlete_id: Uuid::new_v4(),
+        };
+        let map = reservation.clone().into_hashmap();
+        assert_eq!(
+            map[USER_ID_KEY],
+            AttributeValue::S(reservation.user_id.to_string())
+        );
+        assert_eq!(AthleteManager::from_hashmap(map).unwrap(), reservation);
+    }
+}
@@ -0,0 +1,478 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use super::discipline::Discipline;
+use super::error::ApiError;
+use super::result::{Mark, MarkType};
+use super::utils::{get_number, get_string, Item, ItemDecodeError};
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::{Deserialize, Serialize};
+
+const TYPE_KEY: &str = "type";
+const METERS_KEY: &str = "meters";
+const WIND_KEY: &str = "wind";
+const HEIGHT_KEY: &str = "height";
+const OUTCOME_KEY: &str = "outcome";
+/// Every athlete gets at most six attempts in the horizontal jumps and throws
+pub const MAX_HORIZONTAL_ATTEMPTS: usize = 6;
+/// Three failures in a row end the competition of a high jumper or pole vaulter
+pub const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+
+/// One attempt in a field event
+/// The horizontal jumps and throws have marks, fouls and passes; the vertical jumps have jumps at a bar.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Attempt {
+    Mark {
+        meters: f64,
+        /// The wind reading of the attempt, in the long jump and triple jump
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        wind: Option<f64>,
+    },
+    Foul,
+    Pass,
+    Bar {
+        height: f64,
+        outcome: BarOutcome,
+    },
+}
+
+/// The outcome of a jump at a bar, written O, X or - on the scoresheet
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum BarOutcome {
+    #[serde(rename = "o")]
+    Cleared,
+    #[serde(rename = "x")]
+    Failed,
+    /// The athlete passes the rest of their attempts at the height
+    #[serde(rename = "-")]
+    Passed,
+}
+
+impl BarOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Cleared => "o",
+            Self::Failed => "x",
+            Self::Passed => "-",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "o" => Some(Self::Cleared),
+            "x" => Some(Self::Failed),
+            "-" => Some(Self::Passed),
+            _ => None,
+        }
+    }
+}
+
+impl Attempt {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Self::Mark { .. } => "mark",
+            Self::Foul => "foul",
+            Self::Pass => "pass",
+            Self::Bar { .. } => "bar",
+        }
+    }
+}
+
+/// How the attempts of a field event are made
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeriesKind {
+    /// A fixed number of attempts, each measured (long jump, triple jump and the throws)
+    Horizontal,
+    /// Attempts at a rising bar (high jump and pole vault)
+    Vertical,
+}
+
+impl SeriesKind {
+    /// The kind of series results in the discipline are built from, or `None` if it has no attempts
+    pub fn of(discipline: Discipline) -> Option<Self> {
+        match discipline.mark_type() {
+            MarkType::Distance => Some(Self::Horizontal),
+            MarkType::Height => Some(Self::Vertical),
+            MarkType::Time | MarkType::Points => None,
+        }
+    }
+}
+
+/// Store attempts as a list of maps
+pub fn attempts_into_attribute(attempts: &[Attempt]) -> AttributeValue {
+    AttributeValue::L(
+        attempts
+            .iter()
+            .map(|attempt| {
+                let mut map = HashMap::from([(
+                    TYPE_KEY.to_string(),
+                    AttributeValue::S(attempt.type_name().to_string()),
+                )]);
+                match attempt {
+                    Attempt::Mark { meters, wind } => {
+                        map.insert(
+                            METERS_KEY.to_string(),
+                            AttributeValue::N(meters.to_string()),
+                        );
+                        if let Some(wind) = wind {
+                            map.insert(WIND_KEY.to_string(), AttributeValue::N(wind.to_string()));
+                        }
+                    }
+                    Attempt::Foul | Attempt::Pass => {}
+                    Attempt::Bar { height, outcome } => {
+                        map.insert(
+                            HEIGHT_KEY.to_string(),
+                            AttributeValue::N(height.to_string()),
+                        );
+                        map.insert(
+                            OUTCOME_KEY.to_string(),
+                            AttributeValue::S(outcome.as_str().to_string()),
+                        );
+                    }
+                }
+                AttributeValue::M(map)
+            })
+            .collect(),
+    )
+}
+
+/// Read the attempts stored by `attempts_into_attribute` from a row of `T`
+pub fn get_attempts<T: Item>(
+    map: &HashMap<String, AttributeValue>,
+    attribute: &str,
+) -> Result<Vec<Attempt>, ItemDecodeError> {
+    let invalid = |reason: String| ItemDecodeError::invalid::<T>(map, attribute, reason);
+    map.get(attribute)
+        .ok_or_else(|| invalid("is missing".to_string()))?
+        .as_l()
+        .map_err(|_| invalid("is not a list".to_string()))?
+        .iter()
+        .map(|attempt| {
+            let attempt = attempt
+                .as_m()
+                .map_err(|_| invalid("holds an attempt that is not a map".to_string()))?;
+            Ok(match get_string::<T>(attempt, TYPE_KEY)?.as_str() {
+                "mark" => Attempt::Mark {
+                    meters: get_number::<T, f64>(attempt, METERS_KEY)?,
+                    wind: match attempt.contains_key(WIND_KEY) {
+                        true => Some(get_number::<T, f64>(attempt, WIND_KEY)?),
+                        false => None,
+                    },
+                },
+                "foul" => Attempt::Foul,
+                "pass" => Attempt::Pass,
+                "bar" => {
+                    let outcome = get_string::<T>(attempt, OUTCOME_KEY)?;
+                    Attempt::Bar {
+                        height: get_number::<T, f64>(attempt, HEIGHT_KEY)?,
+                        outcome: BarOutcome::parse(&outcome)
+                            .ok_or_else(|| invalid(format!("unknown bar outcome {outcome}")))?,
+                    }
+                }
+                other => return Err(invalid(format!("unknown attempt type {other}"))),
+            })
+        })
+        .collect()
+}
+
+/// Check that a series of attempts could have happened in the discipline
+pub fn validate_series(discipline: Discipline, attempts: &[Attempt]) -> Result<(), ApiError> {
+    match SeriesKind::of(discipline) {
+        None => Err(ApiError::UnprocessableEntity(format!(
+            "Results in the {} have no attempts",
+            discipline.name()
+        ))),
+        Some(SeriesKind::Horizontal) => validate_horizontal(discipline, attempts),
+        Some(SeriesKind::Vertical) => validate_vertical(discipline, attempts),
+    }
+}
+
+fn validate_horizontal(discipline: Discipline, attempts: &[Attempt]) -> Result<(), ApiError> {
+    if attempts.len() > MAX_HORIZONTAL_ATTEMPTS {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "An athlete has at most {MAX_HORIZONTAL_ATTEMPTS} attempts"
+        )));
+    }
+    for attempt in attempts {
+        match attempt {
+            Attempt::Mark { meters, wind } => {
+                if !(meters.is_finite() && *meters > 0.0) {
+                    return Err(ApiError::UnprocessableEntity(format!(
+                        "A mark must be a positive number of meters, got {meters}"
+                    )));
+                }
+                match wind {
+                    Some(_) if !discipline.wind_applies() => {
+                        return Err(ApiError::UnprocessableEntity(format!(
+                            "No wind reading is taken in the {}",
+                            discipline.name()
+                        )))
+                    }
+                    Some(wind) if !wind.is_finite() => {
+                        return Err(ApiError::UnprocessableEntity(format!(
+                            "The wind must be a number of m/s, got {wind}"
+                        )))
+                    }
+                    _ => {}
+                }
+            }
+            Attempt::Foul | Attempt::Pass => {}
+            Attempt::Bar { .. } => {
+                return Err(ApiError::UnprocessableEntity(format!(
+                    "The {} has no bar, record a mark, foul or pass",
+                    discipline.name()
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn validate_vertical(discipline: Discipline, attempts: &[Attempt]) -> Result<(), ApiError> {
+    let mut bar: Option<f64> = None;
+    // Whether the athlete cleared or passed the current height, so has no attempts left at it
+    let mut done_at_bar = false;
+    let mut consecutive_failures = 0;
+    for attempt in attempts {
+        let Attempt::Bar { height, outcome } = *attempt else {
+            return Err(ApiError::UnprocessableEntity(format!(
+                "The {} is jumped at a bar, record a height with o, x or -",
+                discipline.name()
+            )));
+        };
+        if !(height.is_finite() && height > 0.0) {
+            return Err(ApiError::UnprocessableEntity(format!(
+                "A bar must be a positive number of meters, got {height}"
+            )));
+        }
+        if consecutive_failures == MAX_CONSECUTIVE_FAILURES {
+            return Err(ApiError::UnprocessableEntity(format!(
+                "The athlete is out after {MAX_CONSECUTIVE_FAILURES} failures in a row"
+            )));
+        }
+        match bar {
+            Some(current) if height < current => {
+                return Err(ApiError::UnprocessableEntity(format!(
+                    "The bar cannot go down from {current} to {height}"
+                )))
+            }
+            Some(current) if height == current && done_at_bar => {
+                return Err(ApiError::UnprocessableEntity(format!(
+                    "The athlete has no attempts left at {height}"
+                )))
+            }
+            Some(current) if height == current => {}
+            _ => {
+                bar = Some(height);
+                done_at_bar = false;
+            }
+        }
+        match outcome {
+            BarOutcome::Cleared => {
+                consecutive_failures = 0;
+                done_at_bar = true;
+            }
+            BarOutcome::Failed => consecutive_failures += 1,
+            BarOutcome::Passed => done_at_bar = true,
+        }
+    }
+    Ok(())
+}
+
+/// The fair marks of a horizontal series, best first
+fn horizontal_marks(attempts: &[Attempt]) -> Vec<f64> {
+    let mut marks: Vec<f64> = attempts
+        .iter()
+        .filter_map(|attempt| match attempt {
+            Attempt::Mark { meters, .. } => Some(*meters),
+            _ => None,
+        })
+        .collect();
+    marks.sort_by(|a, b| b.total_cmp(a));
+    marks
+}
+
+/// The highest bar cleared in a vertical series
+fn best_height(attempts: &[Attempt]) -> Option<f64> {
+    attempts
+        .iter()
+        .filter_map(|attempt| match attempt {
+            Attempt::Bar {
+                height,
+                outcome: BarOutcome::Cleared,
+            } => Some(*height),
+            _ => None,
+        })
+        .max_by(f64::total_cmp)
+}
+
+/// The failures at the bars `counts` picks out
+fn failures(attempts: &[Attempt], counts: impl Fn(f64) -> bool) -> usize {
+    attempts
+        .iter()
+        .filter(|attempt| {
+            matches!(attempt, Attempt::Bar { height, outcome: BarOutcome::Failed } if counts(*height))
+        })
+        .count()
+}
+
+/// The mark of a series and the wind of the attempt that set it, or `None` without a fair attempt ("NM")
+/// An equal mark set later does not replace the first one.
+pub fn best_attempt(attempts: &[Attempt]) -> Option<(Mark, Option<f64>)> {
+    let mut best: Option<(f64, Option<f64>)> = None;
+    for attempt in attempts {
+        if let Attempt::Mark { meters, wind } = *attempt {
+            if best.is_none_or(|(best, _)| meters > best) {
+                best = Some((meters, wind));
+            }
+        }
+    }
+    if let Some((meters, wind)) = best {
+        return Some((Mark::Distance { meters }, wind));
+    }
+    best_height(attempts).map(|meters| (Mark::Height { meters }, None))
+}
+
+/// Order two series of the same field event, the better one first
+/// Horizontal series are compared by their best mark, then their second best and so on. Vertical series are
+/// compared by the highest bar cleared, then by the fewest failures at that bar, then by the fewest failures up to
+/// it. Series that are still equal are tied.
+pub fn countback(a: &[Attempt], b: &[Attempt]) -> Ordering {
+    let (marks_a, marks_b) = (horizontal_marks(a), horizontal_marks(b));
+    if !marks_a.is_empty() || !marks_b.is_empty() {
+        for i in 0..marks_a.len().max(marks_b.len()) {
+            match (marks_a.get(i), marks_b.get(i)) {
+                (Some(x), Some(y)) if x != y => return y.total_cmp(x),
+                (Some(_), None) => return Ordering::Less,
+                (None, Some(_)) => return Ordering::Greater,
+                _ => {}
+            }
+        }
+        return Ordering::Equal;
+    }
+    match (best_height(a), best_height(b)) {
+        (None, None) => Ordering::Equal,
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(x), Some(y)) if x != y => y.total_cmp(&x),
+        (Some(best), Some(_)) => failures(a, |height| height == best)
+            .cmp(&failures(b, |height| height == best))
+            .then_with(|| {
+                failures(a, |height| height <= best).cmp(&failures(b, |height| height <= best))
+            }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::result::EventResult;
+
+    fn bars(series: &[(f64, &str)]) -> Vec<Attempt> {
+        series
+            .iter()
+            .flat_map(|(height, outcomes)| {
+                outcomes.chars().map(move |outcome| Attempt::Bar {
+                    height: *height,
+                    outcome: BarOutcome::parse(&outcome.to_string()).unwrap(),
+                })
+            })
+            .collect()
+    }
+
+    fn mark(meters: f64) -> Attempt {
+        Attempt::Mark { meters, wind: None }
+    }
+
+    #[test]
+    fn test_attempts_round_trip() {
+        let mut attempts = bars(&[(2.20, "xo"), (2.25, "-")]);
+        attempts.extend([
+            Attempt::Mark {
+                meters: 8.1,
+                wind: Some(1.2),
+            },
+            Attempt::Foul,
+            Attempt::Pass,
+        ]);
+        let map = HashMap::from([("attempts".to_string(), attempts_into_attribute(&attempts))]);
+        assert_eq!(
+            get_attempts::<EventResult>(&map, "attempts").unwrap(),
+            attempts
+        );
+    }
+
+    #[test]
+    fn test_validate_vertical() {
+        let high_jump = Discipline::HighJump;
+        assert!(validate_series(
+            high_jump,
+            &bars(&[(2.20, "xo"), (2.25, "-"), (2.28, "xxx")])
+        )
+        .is_ok());
+        // Failures carry over a pass, so the third in a row ends the competition
+        assert!(
+            validate_series(high_jump, &bars(&[(2.20, "xx-"), (2.25, "x"), (2.28, "o")])).is_err()
+        );
+        assert!(validate_series(high_jump, &bars(&[(2.20, "o"), (2.20, "o")])).is_err());
+        assert!(validate_series(high_jump, &bars(&[(2.20, "x"), (2.15, "o")])).is_err());
+        assert!(validate_series(high_jump, &[Attempt::Foul]).is_err());
+    }
+
+    #[test]
+    fn test_validate_horizontal() {
+        let shot_put = Discipline::ShotPut;
+        assert!(validate_series(shot_put, &[mark(20.1), Attempt::Foul, Attempt::Pass]).is_ok());
+        assert!(validate_series(shot_put, &[mark(20.1); 7]).is_err());
+        let windy = Attempt::Mark {
+            meters: 20.1,
+            wind: Some(1.0),
+        };
+        assert!(validate_series(shot_put, &[windy]).is_err());
+        assert!(validate_series(Discipline::LongJump, &[windy]).is_ok());
+        assert!(validate_series(Discipline::Sprint100m, &[]).is_err());
+    }
+
+    #[test]
+    fn test_best_attempt() {
+        let jumps = [
+            Attempt::Foul,
+            Attempt::Mark {
+                meters: 8.2,
+                wind: Some(2.4),
+            },
+            Attempt::Mark {
+                meters: 8.2,
+                wind: Some(0.3),
+            },
+        ];
+        assert_eq!(
+            best_attempt(&jumps),
+            Some((Mark::Distance { meters: 8.2 }, Some(2.4)))
+        );
+        assert_eq!(best_attempt(&[Attempt::Foul, Attempt::Pass]), None);
+        assert_eq!(
+            best_attempt(&bars(&[(2.20, "o"), (2.25, "xo"), (2.28, "xxx")])),
+            Some((Mark::Height { meters: 2.25 }, None))
+        );
+    }
+
+    #[test]
+    fn test_countback() {
+        // Horizontal ties go to the better second mark
+        let a = [mark(8.1), mark(7.9), Attempt::Foul];
+        let b = [mark(8.1), mark(8.0)];
+        assert_eq!(countback(&b, &a), Ordering::Less);
+        assert_eq!(countback(&[mark(8.1)], &[Attempt::Foul]), Ordering::Less);
+
+        // Vertical ties go to the fewest failures at the best bar, then the fewest overall
+        let clean = bars(&[(2.20, "xo"), (2.25, "o"), (2.28, "xxx")]);
+        let late = bars(&[(2.20, "o"), (2.25, "xo"), (2.28, "xxx")]);
+        let messy = bars(&[(2.20, "xxo"), (2.25, "o"), (2.28, "xxx")]);
+        assert_eq!(countback(&clean, &late), Ordering::Less);
+        assert_eq!(countback(&clean, &messy), Ordering::Less);
+        assert_eq!(countback(&clean, &clean.clone()), Ordering::Equal);
+        assert_eq!(countback(&bars(&[(2.30, "xxo")]), &clean), Ordering::Less);
+    }
+}
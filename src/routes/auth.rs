@@ -0,0 +1,377 @@
+use std::sync::OnceLock;
+
+use super::error::ApiError;
+use super::permission::Role;
+use super::user::{self, User};
+use super::username;
+use super::utils::fetch_item;
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, Salt, SaltString};
+use argon2::{Argon2, PasswordVerifier};
+use aws_sdk_dynamodb::Client;
+use axum::extract::{FromRequestParts, MatchedPath, Request, State};
+use axum::http::{header, request::Parts, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use axum::routing::post;
+use axum::{async_trait, Json};
+use chrono::Utc;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How long an access token is accepted for, in seconds
+pub const ACCESS_TOKEN_SECONDS: i64 = 15 * 60;
+/// How long a refresh token can be traded for new tokens, in seconds
+pub const REFRESH_TOKEN_SECONDS: i64 = 30 * 24 * 60 * 60;
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Routes that take a POST body without changing anything, so they stay open to everyone
+const READ_ONLY_POSTS: &[&str] = &["/competitions/batch-get"];
+
+/// The keys tokens are signed and checked with, made from the `JWT_SECRET` the server is started with
+struct Keys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+}
+
+static KEYS: OnceLock<Keys> = OnceLock::new();
+
+/// Set the secret tokens are signed with
+/// Only the first call has any effect, so every token the server issues can be checked for its whole run.
+pub fn init_keys(secret: &[u8]) {
+    KEYS.get_or_init(|| Keys {
+        encoding: EncodingKey::from_secret(secret),
+        decoding: DecodingKey::from_secret(secret),
+    });
+}
+
+fn keys() -> Result<&'static Keys, ApiError> {
+    KEYS.get()
+        .ok_or_else(|| ApiError::Internal("The token signing keys were never set".to_string()))
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum TokenKind {
+    Access,
+    Refresh,
+}
+
+/// The claims of the tokens the server issues
+/// `kind` keeps a refresh token from being used as an access token and the other way around, and `ver` is the
+/// token version of the user when the token was issued.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Claims {
+    sub: Uuid,
+    iat: i64,
+    exp: i64,
+    kind: TokenKind,
+    #[serde(default)]
+    ver: u32,
+}
+
+fn issue_token(
+    user_id: Uuid,
+    version: u32,
+    kind: TokenKind,
+    issued_at: i64,
+) -> Result<String, ApiError> {
+    let lifetime = match kind {
+        TokenKind::Access => ACCESS_TOKEN_SECONDS,
+        TokenKind::Refresh => REFRESH_TOKEN_SECONDS,
+    };
+    let claims = Claims {
+        sub: user_id,
+        iat: issued_at,
+        exp: issued_at + lifetime,
+        kind,
+        ver: version,
+    };
+    encode(&Header::default(), &claims, &keys()?.encoding)
+        .map_err(|err| ApiError::Internal(err.to_string()))
+}
+
+/// Check the signature, expiry and kind of a token and return its claims
+fn verify_token(token: &str, kind: TokenKind) -> Result<Claims, ApiError> {
+    let claims = decode::<Claims>(token, &keys()?.decoding, &Validation::default())
+        .map_err(|err| ApiError::Unauthorized(format!("The token is not valid: {err}")))?
+        .claims;
+    if claims.kind != kind {
+        return Err(ApiError::Unauthorized(format!(
+            "Expected an {} token",
+            match kind {
+                TokenKind::Access => "access",
+                TokenKind::Refresh => "refresh",
+            }
+        )));
+    }
+    Ok(claims)
+}
+
+/// Hash a password with Argon2id and a random salt, in the PHC string format
+pub fn hash_password(password: &str) -> Result<String, ApiError> {
+    let salt = SaltString::generate(&mut OsRng);
+    hash_password_with_salt(password, salt.as_salt())
+}
+
+fn hash_password_with_salt(password: &str, salt: Salt) -> Result<String, ApiError> {
+    Argon2::default()
+        .hash_password(password.as_bytes(), salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| ApiError::Internal(err.to_string()))
+}
+
+/// A hash no password is checked against except when the user is unknown or has no password, so that signing in
+/// with a wrong username takes as long as signing in with a wrong password
+fn dummy_hash() -> &'static str {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_HASH.get_or_init(|| hash_password("not the password of any user").unwrap_or_default())
+}
+
+fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+/// The user making a request, read from the bearer access token in its `Authorization` header
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AuthUser {
+    pub user_id: Uuid,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // `require_auth` has already checked the token of mutating requests
+        if let Some(user) = parts.extensions.get::<AuthUser>() {
+            return Ok(*user);
+        }
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| {
+                ApiError::Unauthorized("Sign in and send a bearer access token".to_string())
+            })?;
+        let user_id = verify_token(token.trim(), TokenKind::Access)?.sub;
+        Ok(Self { user_id })
+    }
+}
+
+/// Reject requests that change data unless they carry a valid access token
+/// The signed in user is stored in the request extensions for the handler to use.
+pub async fn require_auth(request: Request, next: Next) -> Result<Response, ApiError> {
+    let read_only = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) || request
+        .extensions()
+        .get::<MatchedPath>()
+        .is_some_and(|path| READ_ONLY_POSTS.contains(&path.as_str()));
+    if read_only {
+        return Ok(next.run(request).await);
+    }
+    let (mut parts, body) = request.into_parts();
+    let user = AuthUser::from_request_parts(&mut parts, &()).await?;
+    parts.extensions.insert(user);
+    Ok(next.run(Request::from_parts(parts, body)).await)
+}
+
+#[derive(Debug, Deserialize)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+/// The body of `POST /auth/register`
+/// New users can only choose to be a fan or an athlete; an admin hands out the other roles.
+#[derive(Debug, Deserialize)]
+struct Registration {
+    #[serde(flatten)]
+    credentials: Credentials,
+    #[serde(default)]
+    role: Role,
+}
+
+#[derive(Debug, Deserialize)]
+struct RefreshRequest {
+    refresh_token: String,
+}
+
+/// A signed in user and the tokens it makes requests with
+#[derive(Debug, Serialize)]
+struct Session {
+    user: User,
+    access_token: String,
+    refresh_token: String,
+    token_type: &'static str,
+    /// Seconds until the access token expires
+    expires_in: i64,
+}
+
+impl Session {
+    fn new(user: User) -> Result<Self, ApiError> {
+        let now = Utc::now().timestamp();
+        let version = user.token_version();
+        Ok(Self {
+            access_token: issue_token(user.id, version, TokenKind::Access, now)?,
+            refresh_token: issue_token(user.id, version, TokenKind::Refresh, now)?,
+            user,
+            token_type: "Bearer",
+            expires_in: ACCESS_TOKEN_SECONDS,
+        })
+    }
+}
+
+/// Create a user that signs in with a password, and sign it in
+async fn register(
+    State(db_client): State<Client>,
+    Json(registration): Json<Registration>,
+) -> Result<Json<Session>, ApiError> {
+    let Registration { credentials, role } = registration;
+    if !matches!(role, Role::Fan | Role::Athlete) {
+        return Err(ApiError::Forbidden(format!(
+            "Only an admin can make a user a {}",
+            role.as_str()
+        )));
+    }
+    let username = username::validate(&credentials.username)?;
+    if credentials.password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "The password must be at least {MIN_PASSWORD_LENGTH} characters long"
+        )));
+    }
+    let user = User::with_password(username, hash_password(&credentials.password)?, role);
+    let user = user::store_new_user(&db_client, user).await?;
+    Ok(Json(Session::new(user)?))
+}
+
+/// Trade a username and password for a new pair of tokens
+async fn login(
+    State(db_client): State<Client>,
+    Json(credentials): Json<Credentials>,
+) -> Result<Json<Session>, ApiError> {
+    let user = username::find_user(&db_client, &credentials.username).await?;
+    // Users without a password were created before sign in existed and cannot sign in
+    let verified = match user.as_ref().and_then(User::password_hash) {
+        Some(hash) => verify_password(&credentials.password, hash),
+        None => verify_password(&credentials.password, dummy_hash()),
+    };
+    match user {
+        Some(user) if verified && user.password_hash().is_some() => {
+            let user = user::with_following(&db_client, user).await?;
+            Ok(Json(Session::new(user)?))
+        }
+        _ => Err(ApiError::Unauthorized(
+            "The username or password is wrong".to_string(),
+        )),
+    }
+}
+
+/// Trade a refresh token for a new pair of tokens
+/// A refresh token can only be used once: the token version of the user moves on, so the old refresh token and
+/// any other issued before it are rejected from then on.
+async fn refresh(
+    State(db_client): State<Client>,
+    Json(request): Json<RefreshRequest>,
+) -> Result<Json<Session>, ApiError> {
+    let claims = verify_token(&request.refresh_token, TokenKind::Refresh)?;
+    let user_id = claims.sub;
+    let user = fetch_item::<User>(&db_client, user_id)
+        .await?
+        .ok_or_else(|| ApiError::Unauthorized(format!("User {user_id} no longer exists")))?;
+    let revoked =
+        || ApiError::Unauthorized("The refresh token was already used or revoked".to_string());
+    if claims.ver != user.token_version() {
+        return Err(revoked());
+    }
+    let user = user::next_token_version(&db_client, user_id, claims.ver)
+        .await?
+        .ok_or_else(revoked)?;
+    let user = user::with_following(&db_client, user).await?;
+    Ok(Json(Session::new(user)?))
+}
+
+/// Revoke every refresh token of the signed in user
+/// Access tokens already issued last until they expire.
+async fn logout(
+    auth_user: AuthUser,
+    State(db_client): State<Client>,
+) -> Result<StatusCode, ApiError> {
+    let user_id = auth_user.user_id;
+    let user = fetch_item::<User>(&db_client, user_id)
+        .await?
+        .ok_or_else(|| ApiError::Unauthorized(format!("User {user_id} no longer exists")))?;
+    // If the version moved on in between, the tokens issued before it are revoked all the same
+    user::next_token_version(&db_client, user_id, user.token_version()).await?;
+    Ok(StatusCode::OK)
+}
+
+pub fn auth_routes() -> axum::Router<Client> {
+    axum::Router::new()
+        .route("/register", post(register))
+        .route("/login", post(login))
+        .route("/refresh", post(refresh))
+        .route("/logout", post(logout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_hash_round_trip() {
+        let salt = SaltString::encode_b64(b"a fixed test salt").unwrap();
+        let hash = hash_password_with_salt("correct horse", salt.as_salt()).unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse", &hash));
+        assert!(!verify_password("battery staple", &hash));
+        assert!(!verify_password("correct horse", "not a hash"));
+        assert!(dummy_hash().starts_with("$argon2id$"));
+    }
+
+    #[test]
+    fn test_tokens_are_checked() {
+        init_keys(b"test secret");
+        let user_id = Uuid::new_v4();
+        let now = Utc::now().timestamp();
+        let access = issue_token(user_id, 0, TokenKind::Access, now).unwrap();
+        let refresh = issue_token(user_id, 4, TokenKind::Refresh, now).unwrap();
+        assert_eq!(
+            verify_token(&access, TokenKind::Access).map(|claims| claims.sub),
+            Ok(user_id)
+        );
+        let claims = verify_token(&refresh, TokenKind::Refresh).unwrap();
+        assert_eq!((claims.sub, claims.ver), (user_id, 4));
+        // A refresh token is not an access token
+        assert!(verify_token(&refresh, TokenKind::Access).is_err());
+        // Expired tokens are rejected
+        let expired = issue_token(
+            user_id,
+            0,
+            TokenKind::Access,
+            now - 2 * ACCESS_TOKEN_SECONDS,
+        );
+        assert!(verify_token(&expired.unwrap(), TokenKind::Access).is_err());
+        // So are tokens signed with another secret
+        let forged = encode(
+            &Header::default(),
+            &Claims {
+                sub: user_id,
+                iat: now,
+                exp: now + ACCESS_TOKEN_SECONDS,
+                kind: TokenKind::Access,
+                ver: 0,
+            },
+            &EncodingKey::from_secret(b"another secret"),
+        )
+        .unwrap();
+        assert!(verify_token(&forged, TokenKind::Access).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use std::{collections::HashMap, fmt::Debug};
+
+use aws_sdk_dynamodb::{
+    self,
+    types::{AttributeValue, DeleteRequest, PutRequest, WriteRequest},
+    Client,
+};
+use axum::{extract::State, Json};
+use serde::Serialize;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use super::error::ApiError;
+use super::permission::{Caller, Guarded};
+use super::utils::{
+    backoff, check_batch_size, fetch_items, partition_key_value, BatchIds, Item, MAX_BATCH_ATTEMPTS,
+};
+
+/// The most keys DynamoDB accepts in a single `BatchWriteItem` call
+const BATCH_WRITE_CHUNK: usize = 25;
+
+/// The outcome of writing one entry of a batch write request
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BatchWriteResult<T> {
+    Created { item: T },
+    Deleted { id: Uuid },
+    Failed { id: Uuid, error: String },
+}
+
+/// The primary key of the item a write request puts or deletes
+fn write_request_key<T: Item>(request: &WriteRequest) -> Option<Uuid> {
+    let attributes = match (request.put_request(), request.delete_request()) {
+        (Some(put), _) => put.item(),
+        (_, Some(delete)) => delete.key(),
+        _ => return None,
+    };
+    Uuid::parse_str(&partition_key_value::<T>(attributes)?).ok()
+}
+
+/// Send write requests for `T` with `BatchWriteItem`, 25 at a time
+/// Unprocessed requests are retried with backoff.
+/// Returns the reason each key that could not be written failed; every other key was written.
+async fn batch_write<T: Item>(
+    db_client: &Client,
+    requests: Vec<WriteRequest>,
+) -> HashMap<Uuid, String> {
+    let mut failures = HashMap::new();
+    for chunk in requests.chunks(BATCH_WRITE_CHUNK) {
+        let mut pending = chunk.to_vec();
+        let mut attempt = 0;
+        while !pending.is_empty() {
+            if attempt == MAX_BATCH_ATTEMPTS {
+                for request in &pending {
+                    failures.extend(write_request_key::<T>(request).map(|id| {
+                        (
+                            id,
+                            "The database was too busy to write this item".to_string(),
+                        )
+                    }));
+                }
+                break;
+            }
+            if attempt > 0 {
+                tokio::time::sleep(backoff(attempt)).await;
+            }
+            attempt += 1;
+            let result = db_client
+                .batch_write_item()
+                .request_items(T::table_name(), pending.clone())
+                .send()
+                .await;
+            pending = match result {
+                Ok(result) => result
+                    .unprocessed_items
+                    .and_then(|mut unprocessed| unprocessed.remove(T::table_name()))
+                    .unwrap_or_default(),
+                Err(err) => {
+                    warn!("Batch write to {} failed: {}", T::table_name(), err);
+                    let error = match ApiError::from(err) {
+                        ApiError::ServiceUnavailable(detail) => detail,
+                        _ => "The database rejected the batch holding this item".to_string(),
+                    };
+                    for request in &pending {
+                        failures
+                            .extend(write_request_key::<T>(request).map(|id| (id, error.clone())));
+                    }
+                    break;
+                }
+            };
+        }
+    }
+    failures
+}
+
+/// Add a list of items to the database
+/// The items are passed in the request body as a JSON array, and each one gets its own result in the same order
+///
+/// `T` is the type of the item that will be stored in the database
+///
+/// `U` is the type of the item that is passed in the request body
+///
+/// Items the caller is not allowed to create fail without being written.
+///
+#[instrument(skip(db_client))]
+pub async fn batch_add_items<T, U>(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Json(items): Json<Vec<U>>,
+) -> Result<Json<Vec<BatchWriteResult<T>>>, ApiError>
+where
+    T: Serialize + Clone + Guarded + From<U>,
+    U: Debug,
+{
+    info!("Batch adding items to table {}", T::table_name());
+    check_batch_size(items.len())?;
+    let mut items: Vec<T> = items.into_iter().map(T::from).collect();
+    let mut ids = Vec::with_capacity(items.len());
+    let mut requests = Vec::with_capacity(items.len());
+    let mut denied = HashMap::new();
+    for item in &mut items {
+        let attributes = item.clone().into_hashmap();
+        let id = partition_key_value::<T>(&attributes)
+            .and_then(|id| Uuid::parse_str(&id).ok())
+            .ok_or_else(|| ApiError::Internal("New item has no UUID key".to_string()))?;
+        ids.push(id);
+        if let Err(err) = item.authorize_create(&db_client, &caller).await {
+            denied.insert(id, err.detail().unwrap_or_default());
+            continue;
+        }
+        // Authorizing can fill in the owner of the item
+        let attributes = item.clone().into_hashmap();
+        requests.push(
+            WriteRequest::builder()
+                .put_request(
+                    PutRequest::builder()
+                        .set_item(Some(attributes))
+                        .build()
+                        .map_err(|err| ApiError::Internal(err.to_string()))?,
+                )
+                .build(),
+        );
+    }
+    let mut failures = batch_write::<T>(&db_client, requests).await;
+    failures.extend(denied);
+    let results = items
+        .into_iter()
+        .zip(ids)
+        .map(|(item, id)| match failures.remove(&id) {
+            Some(error) => BatchWriteResult::Failed { id, error },
+            None => BatchWriteResult::Created { item },
+        })
+        .collect();
+    Ok(Json(results))
+}
+
+/// Delete every item whose primary key is in the list of ids in the request body
+/// Each id gets its own result in the same order; deleting an id that has no item still succeeds
+/// Items the caller is not allowed to change fail without being deleted.
+///
+#[instrument(skip(db_client))]
+pub async fn batch_delete_items<T: Serialize + Guarded>(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Json(batch): Json<BatchIds>,
+) -> Result<Json<Vec<BatchWriteResult<T>>>, ApiError> {
+    info!("Batch deleting items from table {}", T::table_name());
+    batch.validate()?;
+    // BatchWriteItem rejects a call that touches the same key twice
+    let mut ids: Vec<Uuid> = Vec::with_capacity(batch.ids.len());
+    for id in batch.ids {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    let mut denied = HashMap::new();
+    for item in fetch_items::<T>(&db_client, &ids).await? {
+        if let Err(err) = item.authorize_modify(&db_client, &caller).await {
+            let id = partition_key_value::<T>(&item.into_hashmap())
+                .and_then(|id| Uuid::parse_str(&id).ok())
+                .ok_or_else(|| ApiError::Internal("Stored item has no UUID key".to_string()))?;
+            denied.insert(id, err.detail().unwrap_or_default());
+        }
+    }
+    let mut requests = Vec::with_capacity(ids.len());
+    for id in ids.iter().filter(|id| !denied.contains_key(id)) {
+        requests.push(
+            WriteRequest::builder()
+                .delete_request(
+                    DeleteRequest::builder()
+                        .key(T::partition_key_name(), AttributeValue::S(id.to_string()))
+                        .build()
+                        .map_err(|err| ApiError::Internal(err.to_string()))?,
+                )
+                .build(),
+        );
+    }
+    let mut failures = batch_write::<T>(&db_client, requests).await;
+    failures.extend(denied);
+    let results = ids
+        .into_iter()
+        .map(|id| match failures.remove(&id) {
+            Some(error) => BatchWriteResult::Failed { id, error },
+            None => BatchWriteResult::Deleted { id },
+        })
+        .collect();
+    Ok(Json(results))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_write_result_json() {
+        let id = Uuid::new_v4();
+        let result: BatchWriteResult<()> = BatchWriteResult::Failed {
+            id,
+            error: "busy".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(result).unwrap(),
+            serde_json::json!({ "status": "failed", "id": id, "error": "busy" })
+        );
+    }
+}
@@ -2,9 +2,15 @@ use std::collections::HashMap;
 
 use aws_sdk_dynamodb::{self, types::AttributeValue, Client};
 
-use super::utils::Item;
-use super::utils::{add_item, delete_item, get_item, get_items};
-use axum::routing::{delete, get, post};
+use super::batch::{batch_add_items, batch_delete_items};
+use super::error::ApiError;
+use super::filter::{Expression, ListFilter, ListQuery};
+use super::permission::{Caller, Guarded, Role};
+use super::utils::{
+    add_item, batch_get_items, delete_item, get_item, get_items, patch_item, update_item,
+};
+use super::utils::{get_date, get_string, get_uuid, Item, ItemDecodeError};
+use axum::routing::{delete, get, patch, post, put};
 use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -15,21 +21,31 @@ const NAME_KEY: &str = "Name";
 const LOCATION_KEY: &str = "Location";
 const START_DATE_KEY: &str = "StartDate";
 const END_DATE_KEY: &str = "EndDate";
+const DIRECTOR_ID_KEY: &str = "DirectorId";
 
 // Define your Competition struct
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-struct Competition {
-    id: Uuid,
+pub struct Competition {
+    pub id: Uuid,
     #[serde(flatten)]
-    competition_data: CompetitionData,
+    pub competition_data: CompetitionData,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-struct CompetitionData {
-    name: String,
-    location: String,
-    start_date: NaiveDate,
-    end_date: NaiveDate,
+pub struct CompetitionData {
+    pub name: String,
+    pub location: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    /// The meet director that runs the competition, set to whoever creates it unless an admin does
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub director_id: Option<Uuid>,
+}
+
+impl Competition {
+    pub fn director_id(&self) -> Option<Uuid> {
+        self.competition_data.director_id
+    }
 }
 
 impl From<CompetitionData> for Competition {
@@ -42,6 +58,75 @@ impl From<CompetitionData> for Competition {
     }
 }
 
+/// The query-string filters accepted by `GET /competitions`
+/// These always scan: an index keyed by `start_date` would need one partition for every competition, and the
+/// competitions table is small enough that a filtered scan reads few pages.
+#[derive(Debug, Default, Deserialize)]
+struct CompetitionFilter {
+    start_date_from: Option<NaiveDate>,
+    start_date_to: Option<NaiveDate>,
+    location: Option<String>,
+    name_contains: Option<String>,
+    sort: Option<CompetitionSort>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+enum CompetitionSort {
+    #[serde(rename = "start_date")]
+    StartDate,
+    #[serde(rename = "-start_date")]
+    StartDateDesc,
+    #[serde(rename = "name")]
+    Name,
+    #[serde(rename = "-name")]
+    NameDesc,
+}
+
+impl ListFilter<Competition> for CompetitionFilter {
+    fn list_query(&self) -> ListQuery {
+        // Dates are stored as %Y-%m-%d strings, so comparing the strings compares the dates
+        let mut filter = Expression::new("f");
+        if let Some(start_date_from) = self.start_date_from {
+            filter.at_least(
+                START_DATE_KEY,
+                AttributeValue::S(start_date_from.to_string()),
+            );
+        }
+        if let Some(start_date_to) = self.start_date_to {
+            filter.at_most(START_DATE_KEY, AttributeValue::S(start_date_to.to_string()));
+        }
+        if let Some(location) = &self.location {
+            filter.equals(LOCATION_KEY, AttributeValue::S(location.clone()));
+        }
+        if let Some(name_contains) = &self.name_contains {
+            filter.contains(NAME_KEY, AttributeValue::S(name_contains.clone()));
+        }
+        ListQuery::scan(filter)
+    }
+
+    fn sorts_within_page(&self) -> bool {
+        self.sort.is_some()
+    }
+
+    fn sort(&self, items: &mut [Competition]) {
+        match self.sort {
+            None => {}
+            Some(CompetitionSort::StartDate) => {
+                items.sort_by_key(|c| c.competition_data.start_date)
+            }
+            Some(CompetitionSort::StartDateDesc) => {
+                items.sort_by_key(|c| std::cmp::Reverse(c.competition_data.start_date))
+            }
+            Some(CompetitionSort::Name) => {
+                items.sort_by(|a, b| a.competition_data.name.cmp(&b.competition_data.name))
+            }
+            Some(CompetitionSort::NameDesc) => {
+                items.sort_by(|a, b| b.competition_data.name.cmp(&a.competition_data.name))
+            }
+        }
+    }
+}
+
 impl Item for Competition {
     fn table_name() -> &'static str {
         TABLE_NAME
@@ -50,22 +135,25 @@ impl Item for Competition {
         ID_KEY
     }
 
-    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Option<Self> {
-        let id = map.get(ID_KEY)?.as_s().unwrap();
-        let id = Uuid::parse_str(id).unwrap();
-        let name: String = map.get(NAME_KEY)?.as_s().unwrap().to_string();
-        let location: String = map.get(LOCATION_KEY)?.as_s().unwrap().to_string();
-        let start_date_string: String = map.get(START_DATE_KEY)?.as_s().unwrap().to_string();
-        let start_date = NaiveDate::parse_from_str(&start_date_string, "%Y-%m-%d").unwrap();
-        let end_date_string: String = map.get(END_DATE_KEY)?.as_s().unwrap().to_string();
-        let end_date = NaiveDate::parse_from_str(&end_date_string, "%Y-%m-%d").unwrap();
-        Some(Self {
+    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError> {
+        let id = get_uuid::<Self>(&map, ID_KEY)?;
+        let name = get_string::<Self>(&map, NAME_KEY)?;
+        let location = get_string::<Self>(&map, LOCATION_KEY)?;
+        let start_date = get_date::<Self>(&map, START_DATE_KEY)?;
+        let end_date = get_date::<Self>(&map, END_DATE_KEY)?;
+        // Competitions created before meet directors existed can only be changed by admins
+        let director_id = match map.contains_key(DIRECTOR_ID_KEY) {
+            true => Some(get_uuid::<Self>(&map, DIRECTOR_ID_KEY)?),
+            false => None,
+        };
+        Ok(Self {
             id,
             competition_data: CompetitionData {
                 name,
                 location,
                 start_date,
                 end_date,
+                director_id,
             },
         })
     }
@@ -89,16 +177,55 @@ impl Item for Competition {
             END_DATE_KEY.to_string(),
             AttributeValue::S(self.competition_data.end_date.to_string()),
         );
+        if let Some(director_id) = self.competition_data.director_id {
+            map.insert(
+                DIRECTOR_ID_KEY.to_string(),
+                AttributeValue::S(director_id.to_string()),
+            );
+        }
         map
     }
 }
 
+impl Guarded for Competition {
+    async fn authorize_create(
+        &mut self,
+        _db_client: &Client,
+        caller: &Caller,
+    ) -> Result<(), ApiError> {
+        if caller.is_admin() {
+            return Ok(());
+        }
+        caller.require_role(&[Role::MeetDirector], "create competitions")?;
+        self.competition_data.director_id = Some(caller.user_id);
+        Ok(())
+    }
+
+    async fn authorize_modify(&self, db_client: &Client, caller: &Caller) -> Result<(), ApiError> {
+        caller.require_director(db_client, self.id).await
+    }
+}
+
 pub fn competition_routes() -> axum::Router<Client> {
     axum::Router::new()
         .route("/", post(add_item::<Competition, CompetitionData>))
-        .route("/", get(get_items::<Competition>))
+        .route("/", get(get_items::<Competition, CompetitionFilter>))
+        .route(
+            "/batch",
+            post(batch_add_items::<Competition, CompetitionData>),
+        )
+        .route("/batch", delete(batch_delete_items::<Competition>))
+        .route("/batch-get", post(batch_get_items::<Competition>))
         .route("/:competition_id", get(get_item::<Competition>))
         .route("/:competition_id", delete(delete_item::<Competition>))
+        .route(
+            "/:competition_id",
+            put(update_item::<Competition, CompetitionData>),
+        )
+        .route(
+            "/:competition_id",
+            patch(patch_item::<Competition, CompetitionData>),
+        )
 }
 
 #[cfg(test)]
@@ -114,6 +241,7 @@ mod tests {
                 location: "Test Location".to_string(),
                 start_date: NaiveDate::from_ymd_opt(2021, 1, 1).unwrap(),
                 end_date: NaiveDate::from_ymd_opt(2021, 1, 2).unwrap(),
+                director_id: Some(Uuid::new_v4()),
             },
         };
         let cloned_competition = competition.clone();
@@ -121,4 +249,55 @@ mod tests {
         let competition2 = Competition::from_hashmap(map).unwrap();
         assert_eq!(cloned_competition, competition2);
     }
+
+    #[test]
+    fn test_competition_filter() {
+        let filter: CompetitionFilter = serde_json::from_value(serde_json::json!({
+            "start_date_from": "2024-01-01",
+            "location": "Eugene",
+            "sort": "-start_date",
+        }))
+        .unwrap();
+        let query = filter.list_query();
+        assert_eq!(query.index, None);
+        assert_eq!(
+            query.filter.condition().unwrap(),
+            "#f0 >= :f0 AND #f1 = :f1"
+        );
+        assert_eq!(query.filter.names()["#f0"], START_DATE_KEY);
+        assert_eq!(filter.sort, Some(CompetitionSort::StartDateDesc));
+    }
+
+    #[test]
+    fn test_competition_from_hashmap_bad_date() {
+        let id = Uuid::new_v4();
+        let competition = Competition {
+            id,
+            competition_data: CompetitionData {
+                name: "Test Competition".to_string(),
+                location: "Test Location".to_string(),
+                start_date: NaiveDate::from_ymd_opt(2021, 1, 1).unwrap(),
+                end_date: NaiveDate::from_ymd_opt(2021, 1, 2).unwrap(),
+                director_id: None,
+            },
+        };
+        let mut map = competition.into_hashmap();
+        map.insert(
+            END_DATE_KEY.to_string(),
+            AttributeValue::S("2020-01-022".to_string()),
+        );
+        match Competition::from_hashmap(map) {
+            Err(ItemDecodeError::InvalidValue {
+                table,
+                key,
+                attribute,
+                ..
+            }) => {
+                assert_eq!(table, TABLE_NAME);
+                assert_eq!(key, Some(id.to_string()));
+                assert_eq!(attribute, END_DATE_KEY);
+            }
+            other => panic!("expected an invalid value error, got {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,428 @@
+use std::fmt;
+
+use aws_sdk_dynamodb::Client;
+use axum::routing::get;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use super::result::{Mark, MarkType};
+
+/// An athletics discipline
+/// Disciplines are sent and stored as their code (i.e. `"110m_hurdles"`), see `Discipline::code`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(into = "&'static str", try_from = "String")]
+pub enum Discipline {
+    Sprint60m,
+    Sprint100m,
+    Sprint200m,
+    Sprint400m,
+    Hurdles60m,
+    Hurdles100m,
+    Hurdles110m,
+    Hurdles400m,
+    Run800m,
+    Run1000m,
+    Run1500m,
+    Mile,
+    Run3000m,
+    Steeplechase3000m,
+    Run5000m,
+    Run10000m,
+    HalfMarathon,
+    Marathon,
+    Relay4x100m,
+    Relay4x400m,
+    HighJump,
+    PoleVault,
+    LongJump,
+    TripleJump,
+    ShotPut,
+    DiscusThrow,
+    HammerThrow,
+    JavelinThrow,
+    Pentathlon,
+    Heptathlon,
+    Decathlon,
+}
+
+/// The families disciplines are grouped into in the catalog
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DisciplineGroup {
+    Sprints,
+    Hurdles,
+    Distance,
+    Relays,
+    Jumps,
+    Throws,
+    CombinedEvents,
+}
+
+impl Discipline {
+    pub const ALL: [Discipline; 31] = [
+        Self::Sprint60m,
+        Self::Sprint100m,
+        Self::Sprint200m,
+        Self::Sprint400m,
+        Self::Hurdles60m,
+        Self::Hurdles100m,
+        Self::Hurdles110m,
+        Self::Hurdles400m,
+        Self::Run800m,
+        Self::Run1000m,
+        Self::Run1500m,
+        Self::Mile,
+        Self::Run3000m,
+        Self::Steeplechase3000m,
+        Self::Run5000m,
+        Self::Run10000m,
+        Self::HalfMarathon,
+        Self::Marathon,
+        Self::Relay4x100m,
+        Self::Relay4x400m,
+        Self::HighJump,
+        Self::PoleVault,
+        Self::LongJump,
+        Self::TripleJump,
+        Self::ShotPut,
+        Self::DiscusThrow,
+        Self::HammerThrow,
+        Self::JavelinThrow,
+        Self::Pentathlon,
+        Self::Heptathlon,
+        Self::Decathlon,
+    ];
+
+    /// The code the discipline is sent and stored as
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Sprint60m => "60m",
+            Self::Sprint100m => "100m",
+            Self::Sprint200m => "200m",
+            Self::Sprint400m => "400m",
+            Self::Hurdles60m => "60m_hurdles",
+            Self::Hurdles100m => "100m_hurdles",
+            Self::Hurdles110m => "110m_hurdles",
+            Self::Hurdles400m => "400m_hurdles",
+            Self::Run800m => "800m",
+            Self::Run1000m => "1000m",
+            Self::Run1500m => "1500m",
+            Self::Mile => "mile",
+            Self::Run3000m => "3000m",
+            Self::Steeplechase3000m => "3000m_steeplechase",
+            Self::Run5000m => "5000m",
+            Self::Run10000m => "10000m",
+            Self::HalfMarathon => "half_marathon",
+            Self::Marathon => "marathon",
+            Self::Relay4x100m => "4x100m_relay",
+            Self::Relay4x400m => "4x400m_relay",
+            Self::HighJump => "high_jump",
+            Self::PoleVault => "pole_vault",
+            Self::LongJump => "long_jump",
+            Self::TripleJump => "triple_jump",
+            Self::ShotPut => "shot_put",
+            Self::DiscusThrow => "discus_throw",
+            Self::HammerThrow => "hammer_throw",
+            Self::JavelinThrow => "javelin_throw",
+            Self::Pentathlon => "pentathlon",
+            Self::Heptathlon => "heptathlon",
+            Self::Decathlon => "decathlon",
+        }
+    }
+
+    /// The display name of the discipline
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sprint60m => "60m",
+            Self::Sprint100m => "100m",
+            Self::Sprint200m => "200m",
+            Self::Sprint400m => "400m",
+            Self::Hurdles60m => "60m Hurdles",
+            Self::Hurdles100m => "100m Hurdles",
+            Self::Hurdles110m => "110m Hurdles",
+            Self::Hurdles400m => "400m Hurdles",
+            Self::Run800m => "800m",
+            Self::Run1000m => "1000m",
+            Self::Run1500m => "1500m",
+            Self::Mile => "Mile",
+            Self::Run3000m => "3000m",
+            Self::Steeplechase3000m => "3000m Steeplechase",
+            Self::Run5000m => "5000m",
+            Self::Run10000m => "10,000m",
+            Self::HalfMarathon => "Half Marathon",
+            Self::Marathon => "Marathon",
+            Self::Relay4x100m => "4x100m Relay",
+            Self::Relay4x400m => "4x400m Relay",
+            Self::HighJump => "High Jump",
+            Self::PoleVault => "Pole Vault",
+            Self::LongJump => "Long Jump",
+            Self::TripleJump => "Triple Jump",
+            Self::ShotPut => "Shot Put",
+            Self::DiscusThrow => "Discus Throw",
+            Self::HammerThrow => "Hammer Throw",
+            Self::JavelinThrow => "Javelin Throw",
+            Self::Pentathlon => "Pentathlon",
+            Self::Heptathlon => "Heptathlon",
+            Self::Decathlon => "Decathlon",
+        }
+    }
+
+    pub fn group(&self) -> DisciplineGroup {
+        match self {
+            Self::Sprint60m | Self::Sprint100m | Self::Sprint200m | Self::Sprint400m => {
+                DisciplineGroup::Sprints
+            }
+            Self::Hurdles60m | Self::Hurdles100m | Self::Hurdles110m | Self::Hurdles400m => {
+                DisciplineGroup::Hurdles
+            }
+            Self::Run800m
+            | Self::Run1000m
+            | Self::Run1500m
+            | Self::Mile
+            | Self::Run3000m
+            | Self::Steeplechase3000m
+            | Self::Run5000m
+            | Self::Run10000m
+            | Self::HalfMarathon
+            | Self::Marathon => DisciplineGroup::Distance,
+            Self::Relay4x100m | Self::Relay4x400m => DisciplineGroup::Relays,
+            Self::HighJump | Self::PoleVault | Self::LongJump | Self::TripleJump => {
+                DisciplineGroup::Jumps
+            }
+            Self::ShotPut | Self::DiscusThrow | Self::HammerThrow | Self::JavelinThrow => {
+                DisciplineGroup::Throws
+            }
+            Self::Pentathlon | Self::Heptathlon | Self::Decathlon => {
+                DisciplineGroup::CombinedEvents
+            }
+        }
+    }
+
+    /// The kind of mark results in the discipline are recorded with
+    pub fn mark_type(&self) -> MarkType {
+        match self {
+            Self::HighJump | Self::PoleVault => MarkType::Height,
+            Self::LongJump
+            | Self::TripleJump
+            | Self::ShotPut
+            | Self::DiscusThrow
+            | Self::HammerThrow
+            | Self::JavelinThrow => MarkType::Distance,
+            Self::Pentathlon | Self::Heptathlon | Self::Decathlon => MarkType::Points,
+            _ => MarkType::Time,
+        }
+    }
+
+    /// Whether a wind reading is taken, so a mark only counts for records with a legal wind
+    pub fn wind_applies(&self) -> bool {
+        matches!(
+            self,
+            Self::Sprint100m
+                | Self::Sprint200m
+                | Self::Hurdles100m
+                | Self::Hurdles110m
+                | Self::LongJump
+                | Self::TripleJump
+        )
+    }
+
+    /// Whether `mark` is the kind of mark this discipline is decided by
+    pub fn accepts(&self, mark: &Mark) -> bool {
+        mark.mark_type() == self.mark_type()
+    }
+
+    /// Find the discipline for a code, or for a display name in any case
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|discipline| {
+            discipline.code() == value || discipline.name().eq_ignore_ascii_case(value)
+        })
+    }
+}
+
+impl fmt::Display for Discipline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl From<Discipline> for &'static str {
+    fn from(discipline: Discipline) -> Self {
+        discipline.code()
+    }
+}
+
+impl TryFrom<String> for Discipline {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value).ok_or_else(|| format!("unknown discipline {value}"))
+    }
+}
+
+/// Who an event is for
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Gender {
+    Men,
+    Women,
+    Mixed,
+}
+
+/// The age group of an event
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AgeGroup {
+    #[default]
+    Open,
+    U23,
+    U20,
+    U18,
+    U16,
+    Masters,
+}
+
+/// The gender and age group an event is for
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Category {
+    pub gender: Gender,
+    #[serde(default)]
+    pub age_group: AgeGroup,
+}
+
+impl Gender {
+    /// The name the gender is stored under, the same as its JSON name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Men => "men",
+            Self::Women => "women",
+            Self::Mixed => "mixed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        [Self::Men, Self::Women, Self::Mixed]
+            .into_iter()
+            .find(|gender| gender.as_str() == value)
+    }
+}
+
+impl AgeGroup {
+    /// The name the age group is stored under, the same as its JSON name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::U23 => "u23",
+            Self::U20 => "u20",
+            Self::U18 => "u18",
+            Self::U16 => "u16",
+            Self::Masters => "masters",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        [
+            Self::Open,
+            Self::U23,
+            Self::U20,
+            Self::U18,
+            Self::U16,
+            Self::Masters,
+        ]
+        .into_iter()
+        .find(|age_group| age_group.as_str() == value)
+    }
+}
+
+/// Read the discipline and category out of a free-text event name such as "Men's 200m"
+/// Events used to be named this way; names that do not say who the event is for are treated as open and mixed.
+pub fn parse_legacy_name(name: &str) -> Option<(Discipline, Category)> {
+    let mut gender = Gender::Mixed;
+    let mut rest = Vec::new();
+    for word in name.split_whitespace() {
+        match word.to_lowercase().as_str() {
+            "men's" | "mens" | "men" | "boys'" | "boys" => gender = Gender::Men,
+            "women's" | "womens" | "women" | "girls'" | "girls" => gender = Gender::Women,
+            "mixed" => gender = Gender::Mixed,
+            _ => rest.push(word),
+        }
+    }
+    let discipline = Discipline::parse(&rest.join(" "))?;
+    Some((
+        discipline,
+        Category {
+            gender,
+            age_group: AgeGroup::Open,
+        },
+    ))
+}
+
+/// One entry of the discipline catalog
+#[derive(Debug, Serialize)]
+struct DisciplineInfo {
+    code: Discipline,
+    name: &'static str,
+    group: DisciplineGroup,
+    mark_type: MarkType,
+    wind_applies: bool,
+}
+
+/// Get every discipline an event can be held in
+async fn get_disciplines() -> Json<Vec<DisciplineInfo>> {
+    Json(
+        Discipline::ALL
+            .into_iter()
+            .map(|discipline| DisciplineInfo {
+                code: discipline,
+                name: discipline.name(),
+                group: discipline.group(),
+                mark_type: discipline.mark_type(),
+                wind_applies: discipline.wind_applies(),
+            })
+            .collect(),
+    )
+}
+
+pub fn discipline_routes() -> axum::Router<Client> {
+    axum::Router::new().route("/", get(get_disciplines))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discipline_codes_round_trip() {
+        for discipline in Discipline::ALL {
+            let json = serde_json::to_value(discipline).unwrap();
+            assert_eq!(json, discipline.code());
+            assert_eq!(
+                serde_json::from_value::<Discipline>(json).unwrap(),
+                discipline
+            );
+        }
+        assert!(serde_json::from_value::<Discipline>("100 yards".into()).is_err());
+    }
+
+    #[test]
+    fn test_parse_legacy_name() {
+        assert_eq!(
+            parse_legacy_name("Men's 200m"),
+            Some((
+                Discipline::Sprint200m,
+                Category {
+                    gender: Gender::Men,
+                    age_group: AgeGroup::Open
+                }
+            ))
+        );
+        assert_eq!(
+            parse_legacy_name("WOMENS 100m hurdles").map(|(discipline, _)| discipline),
+            Some(Discipline::Hurdles100m)
+        );
+        assert_eq!(
+            parse_legacy_name("Long Jump").map(|(_, category)| category.gender),
+            Some(Gender::Mixed)
+        );
+        assert_eq!(parse_legacy_name("Egg and spoon race"), None);
+    }
+}
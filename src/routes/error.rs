@@ -0,0 +1,182 @@
+use std::fmt::Debug;
+
+use aws_sdk_dynamodb::error::{ProvideErrorMetadata, SdkError};
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use tracing::error;
+
+use super::utils::ItemDecodeError;
+
+/// The error type returned by every endpoint
+/// It is rendered as an RFC 7807 `application/problem+json` body so clients get something they can parse
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    BadRequest(String),
+    /// The request has no valid access token
+    Unauthorized(String),
+    /// The signed in user is not allowed to do this
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    UnprocessableEntity(String),
+    ServiceUnavailable(String),
+    /// The detail of an internal error is only logged, never sent to the client
+    Internal(String),
+}
+
+/// The body of an RFC 7807 problem response
+#[derive(Serialize)]
+struct Problem {
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The detail sent to the client, if any
+    pub fn detail(&self) -> Option<String> {
+        match self {
+            Self::BadRequest(detail)
+            | Self::Unauthorized(detail)
+            | Self::Forbidden(detail)
+            | Self::NotFound(detail)
+            | Self::Conflict(detail)
+            | Self::UnprocessableEntity(detail)
+            | Self::ServiceUnavailable(detail) => Some(detail.clone()),
+            Self::Internal(_) => None,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if let Self::Internal(detail) = &self {
+            error!("Internal error: {}", detail);
+        }
+        let status = self.status();
+        let problem = Problem {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or("Unknown Error"),
+            status: status.as_u16(),
+            detail: self.detail(),
+        };
+        let mut response = (
+            status,
+            [(header::CONTENT_TYPE, "application/problem+json")],
+            Json(problem),
+        )
+            .into_response();
+        // Tell the client which scheme to authenticate with
+        if status == StatusCode::UNAUTHORIZED {
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                header::HeaderValue::from_static("Bearer"),
+            );
+        }
+        response
+    }
+}
+
+impl<E, R> From<SdkError<E, R>> for ApiError
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+    R: Debug,
+{
+    fn from(err: SdkError<E, R>) -> Self {
+        match err.code() {
+            Some("ConditionalCheckFailedException") => Self::Conflict(
+                "The request conflicts with the current state of the item".to_string(),
+            ),
+            Some("ResourceNotFoundException") => {
+                Self::NotFound("The requested resource does not exist".to_string())
+            }
+            Some(
+                "ProvisionedThroughputExceededException"
+                | "ThrottlingException"
+                | "RequestLimitExceeded",
+            ) => Self::ServiceUnavailable(
+                "The database is busy, please retry the request later".to_string(),
+            ),
+            _ => match err {
+                SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) => {
+                    error!("Could not reach the database: {:?}", err);
+                    Self::ServiceUnavailable("The database could not be reached".to_string())
+                }
+                err => Self::Internal(format!(
+                    "{}",
+                    aws_sdk_dynamodb::error::DisplayErrorContext(&err)
+                )),
+            },
+        }
+    }
+}
+
+impl From<ItemDecodeError> for ApiError {
+    fn from(err: ItemDecodeError) -> Self {
+        Self::Internal(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+
+    #[tokio::test]
+    async fn test_api_error_problem_body() {
+        let response =
+            ApiError::NotFound("competition 1 does not exist".to_string()).into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/problem+json"
+        );
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Not Found",
+                "status": 404,
+                "detail": "competition 1 does not exist",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_internal_error_hides_detail() {
+        let response = ApiError::Internal("raw AWS error text".to_string()).into_response();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body.get("detail"), None);
+        assert_eq!(body["status"], 500);
+    }
+
+    #[test]
+    fn test_unauthorized_asks_for_a_bearer_token() {
+        let response = ApiError::Unauthorized("no token".to_string()).into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+    }
+}
@@ -1,35 +1,90 @@
 use std::collections::HashMap;
 
-use super::utils::Item;
-use super::utils::{add_item, delete_item, get_item, get_items};
-use aws_sdk_dynamodb::{self, types::AttributeValue, Client};
-use axum::routing::{delete, get, post};
+use super::athlete::Athlete;
+use super::athlete_event::AthleteEvent;
+use super::batch::BatchWriteResult;
+use super::discipline::{
+    parse_legacy_name, AgeGroup, Category, Discipline, DisciplineGroup, Gender,
+};
+use super::error::ApiError;
+use super::filter::{Expression, IndexQuery, ListFilter, ListQuery};
+use super::permission::{Caller, Guarded};
+use super::personal_best;
+use super::relay_team::RelayTeam;
+use super::scoring::components;
+use super::transaction::{self, exists_check, transact, TransactionStep, MAX_TRANSACTION_STEPS};
+use super::utils::{apply_patch, fetch_existing_item, fetch_item, get_item, get_items};
+use super::utils::{check_batch_size, fetch_items, fetch_row, not_found, BatchIds, SetAll};
+use super::utils::{
+    get_date_time, get_number, get_string, get_uuid, get_uuid_list, uuid_list, Item,
+    ItemDecodeError,
+};
+use aws_sdk_dynamodb::{
+    self,
+    types::{AttributeValue, ConditionCheck, Put, TransactWriteItem},
+    Client,
+};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get, patch, post, put};
+use axum::Json;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use uuid::Uuid;
 
 pub const TABLE_NAME: &str = "events";
 pub const ID_KEY: &str = "id";
-const COMPETITION_ID_KEY: &str = "competition_id";
-const ATHLETE_ID_KEY: &str = "athlete_id";
-const NAME_KEY: &str = "name";
-const DATE_TIME_KEY: &str = "date_time";
+pub const COMPETITION_ID_KEY: &str = "competition_id";
+pub const ATHLETE_IDS_KEY: &str = "athlete_ids";
+pub const TEAM_IDS_KEY: &str = "team_ids";
+/// Events used to hold a single athlete in this attribute, see `migrations::migrate_event_entry_lists`
+pub const LEGACY_ATHLETE_ID_KEY: &str = "athlete_id";
+pub const DISCIPLINE_KEY: &str = "discipline";
+pub const GENDER_KEY: &str = "gender";
+pub const AGE_GROUP_KEY: &str = "age_group";
+/// Events used to have a free-text name here instead of a discipline and category, see
+/// `migrations::migrate_event_disciplines`
+pub const LEGACY_NAME_KEY: &str = "name";
+pub const DATE_TIME_KEY: &str = "date_time";
+pub const COMBINED_EVENT_ID_KEY: &str = "combined_event_id";
+pub const ROUND_COUNT_KEY: &str = "round_count";
+/// Events of a competition, ordered by start time
+pub const COMPETITION_INDEX: &str = "competition_id-date_time-index";
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-struct Event {
-    id: Uuid,
+pub struct Event {
+    pub id: Uuid,
     #[serde(flatten)]
-    event_data: EventData,
+    pub event_data: EventData,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-struct EventData {
-    competition_id: Uuid,
-    // TODO: Change this to a list of athlete_ids
-    athlete_id: Uuid,
-    name: String,
-    date_time: DateTime<Utc>,
+pub struct EventData {
+    pub competition_id: Uuid,
+    /// The entry list of the event, kept in sync with the `athlete_events` table
+    #[serde(default)]
+    pub athlete_ids: Vec<Uuid>,
+    /// The relay teams entered in the event, managed through `/events/:event_id/teams`
+    #[serde(default)]
+    pub team_ids: Vec<Uuid>,
+    pub discipline: Discipline,
+    pub category: Category,
+    pub date_time: DateTime<Utc>,
+    /// The decathlon, heptathlon or pentathlon this event is a component of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub combined_event_id: Option<Uuid>,
+    /// How many rounds the event is held in, counted up by `/events/:event_id/rounds` as rounds are added
+    #[serde(default)]
+    pub round_count: u32,
+}
+
+impl EventData {
+    /// Relay events are entered by teams rather than by athletes
+    pub fn is_relay(&self) -> bool {
+        self.discipline.group() == DisciplineGroup::Relays
+    }
 }
 
 impl From<EventData> for Event {
@@ -39,6 +94,132 @@ impl From<EventData> for Event {
     }
 }
 
+/// The query-string filters accepted by `GET /events`
+/// Filtering by `competition_id` reads the competition index instead of scanning the table.
+#[derive(Debug, Default, Deserialize)]
+struct EventFilter {
+    competition_id: Option<Uuid>,
+    discipline: Option<Discipline>,
+    gender: Option<Gender>,
+    age_group: Option<AgeGroup>,
+    date_time_from: Option<DateTime<Utc>>,
+    date_time_to: Option<DateTime<Utc>>,
+    sort: Option<EventSort>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+enum EventSort {
+    #[serde(rename = "date_time")]
+    DateTime,
+    #[serde(rename = "-date_time")]
+    DateTimeDesc,
+    #[serde(rename = "discipline")]
+    Discipline,
+    #[serde(rename = "-discipline")]
+    DisciplineDesc,
+}
+
+impl ListFilter<Event> for EventFilter {
+    fn list_query(&self) -> ListQuery {
+        // Datetimes are stored as UTC RFC3339 strings, so comparing the strings compares the times
+        let date_time_from = self
+            .date_time_from
+            .map(|date_time| AttributeValue::S(date_time.to_rfc3339()));
+        let date_time_to = self
+            .date_time_to
+            .map(|date_time| AttributeValue::S(date_time.to_rfc3339()));
+        let mut filter = Expression::new("f");
+        if let Some(discipline) = self.discipline {
+            filter.equals(
+                DISCIPLINE_KEY,
+                AttributeValue::S(discipline.code().to_string()),
+            );
+        }
+        if let Some(gender) = self.gender {
+            filter.equals(GENDER_KEY, AttributeValue::S(gender.as_str().to_string()));
+        }
+        if let Some(age_group) = self.age_group {
+            filter.equals(
+                AGE_GROUP_KEY,
+                AttributeValue::S(age_group.as_str().to_string()),
+            );
+        }
+        let Some(competition_id) = self.competition_id else {
+            if let Some(date_time_from) = date_time_from {
+                filter.at_least(DATE_TIME_KEY, date_time_from);
+            }
+            if let Some(date_time_to) = date_time_to {
+                filter.at_most(DATE_TIME_KEY, date_time_to);
+            }
+            return ListQuery::scan(filter);
+        };
+        // The index is sorted by date_time, so the time range becomes part of the key condition
+        let mut key_condition = Expression::new("k");
+        key_condition.equals(
+            COMPETITION_ID_KEY,
+            AttributeValue::S(competition_id.to_string()),
+        );
+        match (date_time_from, date_time_to) {
+            (Some(from), Some(to)) => key_condition.between(DATE_TIME_KEY, from, to),
+            (Some(from), None) => key_condition.at_least(DATE_TIME_KEY, from),
+            (None, Some(to)) => key_condition.at_most(DATE_TIME_KEY, to),
+            (None, None) => &mut key_condition,
+        };
+        ListQuery {
+            index: Some(IndexQuery {
+                index_name: COMPETITION_INDEX,
+                key_condition,
+                ascending: self.sort != Some(EventSort::DateTimeDesc),
+            }),
+            filter,
+        }
+    }
+
+    /// Sorting by `date_time` follows the competition index when there is one
+    fn sorts_within_page(&self) -> bool {
+        match self.sort {
+            None => false,
+            Some(EventSort::DateTime | EventSort::DateTimeDesc) => self.competition_id.is_none(),
+            Some(EventSort::Discipline | EventSort::DisciplineDesc) => true,
+        }
+    }
+
+    fn sort(&self, items: &mut [Event]) {
+        match self.sort {
+            None => {}
+            Some(EventSort::DateTime) => items.sort_by_key(|e| e.event_data.date_time),
+            Some(EventSort::DateTimeDesc) => {
+                items.sort_by_key(|e| std::cmp::Reverse(e.event_data.date_time))
+            }
+            Some(EventSort::Discipline) => items.sort_by_key(|e| e.event_data.discipline),
+            Some(EventSort::DisciplineDesc) => {
+                items.sort_by_key(|e| std::cmp::Reverse(e.event_data.discipline))
+            }
+        }
+    }
+}
+
+fn get_discipline(map: &HashMap<String, AttributeValue>) -> Result<Discipline, ItemDecodeError> {
+    let code = get_string::<Event>(map, DISCIPLINE_KEY)?;
+    Discipline::parse(&code).ok_or_else(|| {
+        ItemDecodeError::invalid::<Event>(map, DISCIPLINE_KEY, format!("unknown discipline {code}"))
+    })
+}
+
+fn get_gender(map: &HashMap<String, AttributeValue>) -> Result<Gender, ItemDecodeError> {
+    let value = get_string::<Event>(map, GENDER_KEY)?;
+    Gender::parse(&value).ok_or_else(|| {
+        ItemDecodeError::invalid::<Event>(map, GENDER_KEY, format!("unknown gender {value}"))
+    })
+}
+
+fn get_age_group(map: &HashMap<String, AttributeValue>) -> Result<AgeGroup, ItemDecodeError> {
+    let value = get_string::<Event>(map, AGE_GROUP_KEY)?;
+    AgeGroup::parse(&value).ok_or_else(|| {
+        ItemDecodeError::invalid::<Event>(map, AGE_GROUP_KEY, format!("unknown age group {value}"))
+    })
+}
+
 impl Item for Event {
     fn table_name() -> &'static str {
         TABLE_NAME
@@ -47,26 +228,58 @@ impl Item for Event {
         ID_KEY
     }
 
-    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Option<Self> {
-        // TODO: There should be a better way to handle unwrap() here
-        let id = map.get(ID_KEY)?.as_s().unwrap();
-        let id = Uuid::parse_str(id).unwrap();
-        let competition_id = map.get(COMPETITION_ID_KEY)?.as_s().unwrap();
-        let competition_id = Uuid::parse_str(competition_id).unwrap();
-        let athlete_id = map.get(ATHLETE_ID_KEY)?.as_s().unwrap();
-        let athlete_id = Uuid::parse_str(athlete_id).unwrap();
-        let event_name: String = map.get(NAME_KEY)?.as_s().unwrap().to_string();
-        let event_date_time_string: String = map.get(DATE_TIME_KEY)?.as_s().unwrap().to_string();
-        let event_date_time = DateTime::parse_from_rfc3339(&event_date_time_string)
-            .unwrap()
-            .with_timezone(&Utc);
-        Some(Self {
+    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError> {
+        let id = get_uuid::<Self>(&map, ID_KEY)?;
+        let competition_id = get_uuid::<Self>(&map, COMPETITION_ID_KEY)?;
+        // Rows written before events held an entry list have a single athlete instead
+        let team_ids = match map.contains_key(TEAM_IDS_KEY) {
+            true => get_uuid_list::<Self>(&map, TEAM_IDS_KEY)?,
+            false => Vec::new(),
+        };
+        let athlete_ids = match map.contains_key(ATHLETE_IDS_KEY) {
+            true => get_uuid_list::<Self>(&map, ATHLETE_IDS_KEY)?,
+            false => vec![get_uuid::<Self>(&map, LEGACY_ATHLETE_ID_KEY)?],
+        };
+        // Rows written before events had a discipline have a free-text name instead
+        let (discipline, category) = match map.contains_key(DISCIPLINE_KEY) {
+            true => (
+                get_discipline(&map)?,
+                Category {
+                    gender: get_gender(&map)?,
+                    age_group: get_age_group(&map)?,
+                },
+            ),
+            false => {
+                let name = get_string::<Self>(&map, LEGACY_NAME_KEY)?;
+                parse_legacy_name(&name).ok_or_else(|| {
+                    ItemDecodeError::invalid::<Self>(
+                        &map,
+                        LEGACY_NAME_KEY,
+                        format!("no discipline named {name}"),
+                    )
+                })?
+            }
+        };
+        let event_date_time = get_date_time::<Self>(&map, DATE_TIME_KEY)?;
+        let combined_event_id = match map.contains_key(COMBINED_EVENT_ID_KEY) {
+            true => Some(get_uuid::<Self>(&map, COMBINED_EVENT_ID_KEY)?),
+            false => None,
+        };
+        let round_count = match map.contains_key(ROUND_COUNT_KEY) {
+            true => get_number::<Self, u32>(&map, ROUND_COUNT_KEY)?,
+            false => 0,
+        };
+        Ok(Self {
             id,
             event_data: EventData {
                 competition_id,
-                athlete_id,
-                name: event_name,
+                athlete_ids,
+                team_ids,
+                discipline,
+                category,
                 date_time: event_date_time,
+                combined_event_id,
+                round_count,
             },
         })
     }
@@ -79,27 +292,444 @@ impl Item for Event {
             AttributeValue::S(self.event_data.competition_id.to_string()),
         );
         map.insert(
-            ATHLETE_ID_KEY.to_string(),
-            AttributeValue::S(self.event_data.athlete_id.to_string()),
+            ATHLETE_IDS_KEY.to_string(),
+            uuid_list(&self.event_data.athlete_ids),
+        );
+        map.insert(
+            TEAM_IDS_KEY.to_string(),
+            uuid_list(&self.event_data.team_ids),
+        );
+        map.insert(
+            DISCIPLINE_KEY.to_string(),
+            AttributeValue::S(self.event_data.discipline.code().to_string()),
         );
         map.insert(
-            NAME_KEY.to_string(),
-            AttributeValue::S(self.event_data.name),
+            GENDER_KEY.to_string(),
+            AttributeValue::S(self.event_data.category.gender.as_str().to_string()),
+        );
+        map.insert(
+            AGE_GROUP_KEY.to_string(),
+            AttributeValue::S(self.event_data.category.age_group.as_str().to_string()),
         );
         map.insert(
             DATE_TIME_KEY.to_string(),
             AttributeValue::S(self.event_data.date_time.to_rfc3339()),
         );
+        if let Some(combined_event_id) = self.event_data.combined_event_id {
+            map.insert(
+                COMBINED_EVENT_ID_KEY.to_string(),
+                AttributeValue::S(combined_event_id.to_string()),
+            );
+        }
+        map.insert(
+            ROUND_COUNT_KEY.to_string(),
+            AttributeValue::N(self.event_data.round_count.to_string()),
+        );
         map
     }
 }
 
+/// Events are run by the meet director of the competition they are held at
+impl Guarded for Event {
+    async fn authorize_create(
+        &mut self,
+        db_client: &Client,
+        caller: &Caller,
+    ) -> Result<(), ApiError> {
+        caller
+            .require_director(db_client, self.event_data.competition_id)
+            .await
+    }
+
+    async fn authorize_modify(&self, db_client: &Client, caller: &Caller) -> Result<(), ApiError> {
+        caller
+            .require_director(db_client, self.event_data.competition_id)
+            .await
+    }
+}
+
+/// The most athletes an event can be created with
+/// Every new entry needs two writes in the same transaction, one to check the athlete and one for the calendar.
+pub const MAX_ENTRIES: usize = (MAX_TRANSACTION_STEPS - 1) / 2;
+
+/// Reject entry lists that name an athlete twice or are too long to write in one transaction
+fn validate_entries(event_data: &EventData) -> Result<(), ApiError> {
+    let athlete_ids = &event_data.athlete_ids;
+    if event_data.is_relay() && !athlete_ids.is_empty() {
+        return Err(ApiError::UnprocessableEntity(
+            "Athletes are entered in relay events through their teams".to_string(),
+        ));
+    }
+    if athlete_ids.len() > MAX_ENTRIES {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "An event can have at most {MAX_ENTRIES} athletes, got {}",
+            athlete_ids.len()
+        )));
+    }
+    for (i, athlete_id) in athlete_ids.iter().enumerate() {
+        if athlete_ids[..i].contains(athlete_id) {
+            return Err(ApiError::UnprocessableEntity(format!(
+                "Athlete {athlete_id} is entered in the event twice"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The writes that bring the `athlete_events` table in line with a change to an event's entry list
+fn entry_steps(
+    event_id: Uuid,
+    old_athlete_ids: &[Uuid],
+    new_athlete_ids: &[Uuid],
+) -> Result<Vec<TransactionStep>, ApiError> {
+    let mut steps = Vec::new();
+    for athlete_id in new_athlete_ids
+        .iter()
+        .filter(|id| !old_athlete_ids.contains(id))
+    {
+        steps.push(exists_check::<Athlete>(
+            *athlete_id,
+            ApiError::NotFound(format!("Athlete {athlete_id} does not exist")),
+        )?);
+        steps.push(transaction::put(AthleteEvent::new(*athlete_id, event_id))?);
+    }
+    for athlete_id in old_athlete_ids
+        .iter()
+        .filter(|id| !new_athlete_ids.contains(id))
+    {
+        steps.push(transaction::delete::<AthleteEvent>(
+            AthleteEvent::new(*athlete_id, event_id).key(),
+        )?);
+    }
+    Ok(steps)
+}
+
+/// A step that checks the athlete (or team, with `TEAM_IDS_KEY`) is still entered in the event
+/// `entries` is the attribute holding the entry list: `ATHLETE_IDS_KEY` or `TEAM_IDS_KEY`.
+pub fn entered_check(
+    event_id: Uuid,
+    entries: &str,
+    entrant_id: Uuid,
+    on_condition_failed: ApiError,
+) -> Result<TransactionStep, ApiError> {
+    let check = ConditionCheck::builder()
+        .table_name(TABLE_NAME)
+        .key(ID_KEY, AttributeValue::S(event_id.to_string()))
+        .condition_expression("contains(#ids, :entrant)")
+        .expression_attribute_names("#ids", entries)
+        .expression_attribute_values(":entrant", AttributeValue::S(entrant_id.to_string()))
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok(TransactionStep::new(
+        TransactWriteItem::builder().condition_check(check).build(),
+        Some(on_condition_failed),
+    ))
+}
+
+/// Check that the combined event an event names as its parent can score it
+/// The parent must be a decathlon, heptathlon or pentathlon of the same competition with the discipline of the
+/// event among its components. The returned step keeps the parent from being deleted while the event is written.
+async fn combined_event_checks(
+    db_client: &Client,
+    event_data: &EventData,
+) -> Result<Vec<TransactionStep>, ApiError> {
+    let Some(combined_event_id) = event_data.combined_event_id else {
+        return Ok(Vec::new());
+    };
+    let combined = fetch_item::<Event>(db_client, combined_event_id)
+        .await?
+        .ok_or_else(|| {
+            ApiError::UnprocessableEntity(format!(
+                "Combined event {combined_event_id} does not exist"
+            ))
+        })?;
+    let combined_data = &combined.event_data;
+    let Some(components) = components(combined_data.discipline, combined_data.category.gender)
+    else {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "Event {combined_event_id} is not a combined event that can be scored"
+        )));
+    };
+    if combined_data.competition_id != event_data.competition_id {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "Combined event {combined_event_id} is in a different competition"
+        )));
+    }
+    if !components.contains(&event_data.discipline) {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "{} is not a component of combined event {combined_event_id}",
+            event_data.discipline
+        )));
+    }
+    Ok(vec![exists_check::<Event>(
+        combined_event_id,
+        ApiError::UnprocessableEntity(format!("Combined event {combined_event_id} does not exist")),
+    )?])
+}
+
+/// Write a new event and put it on the calendar of every entered athlete
+async fn create_event(db_client: &Client, event: Event) -> Result<(), ApiError> {
+    validate_entries(&event.event_data)?;
+    if !event.event_data.team_ids.is_empty() {
+        return Err(ApiError::UnprocessableEntity(
+            "Teams are entered once the event exists, through /events/:event_id/teams".to_string(),
+        ));
+    }
+    if event.event_data.round_count != 0 {
+        return Err(ApiError::UnprocessableEntity(
+            "Rounds are added once the event exists, through /events/:event_id/rounds".to_string(),
+        ));
+    }
+    let mut steps = entry_steps(event.id, &[], &event.event_data.athlete_ids)?;
+    steps.extend(combined_event_checks(db_client, &event.event_data).await?);
+    steps.push(transaction::put(event)?);
+    transact(db_client, steps).await
+}
+
+/// A step that writes `new` over the stored row `stored`, failing if the event was deleted or changed since
+/// `stored` was read
+/// The condition is the one `SetAll::replacing` builds, so every attribute of the event, the entry list, the
+/// entered teams and the round count included, must still hold the value it was read with. The whole row is
+/// replaced, so attributes left over from legacy rows are dropped; a legacy row holds its single athlete in
+/// `athlete_id`, which is checked too.
+fn put_event(
+    stored: HashMap<String, AttributeValue>,
+    new: Event,
+) -> Result<TransactionStep, ApiError> {
+    let id = new.id;
+    let legacy_athlete = stored.get(LEGACY_ATHLETE_ID_KEY).cloned();
+    let replacing = SetAll::replacing(stored, new.clone())?;
+    // Only the stored values are compared; the new ones are written by the put itself
+    let values = replacing
+        .values
+        .into_iter()
+        .filter(|(placeholder, _)| !placeholder.starts_with(":v"))
+        .collect();
+    let mut condition = replacing.condition;
+    let mut put = Put::builder()
+        .table_name(TABLE_NAME)
+        .set_item(Some(new.into_hashmap()))
+        .set_expression_attribute_names(Some(replacing.names))
+        .set_expression_attribute_values(Some(values));
+    if let Some(athlete) = legacy_athlete {
+        condition.push_str(" AND #legacy_athlete = :legacy_athlete");
+        put = put
+            .expression_attribute_names("#legacy_athlete", LEGACY_ATHLETE_ID_KEY)
+            .expression_attribute_values(":legacy_athlete", athlete);
+    }
+    let put = put
+        .condition_expression(condition)
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok(TransactionStep::new(
+        TransactWriteItem::builder().put(put).build(),
+        Some(ApiError::Conflict(format!(
+            "Event {id} was changed or deleted since it was read, please retry the request"
+        ))),
+    ))
+}
+
+/// The stored row of an event and the event read from it, or a 404
+async fn fetch_event_row(
+    db_client: &Client,
+    event_id: Uuid,
+) -> Result<(HashMap<String, AttributeValue>, Event), ApiError> {
+    let stored = fetch_row::<Event>(db_client, event_id)
+        .await?
+        .ok_or_else(|| not_found::<Event>(event_id))?;
+    let event = Event::from_hashmap(stored.clone())?;
+    Ok((stored, event))
+}
+
+/// Overwrite an event, moving the calendar entries of athletes added to or dropped from the entry list
+/// The entered teams and the round count are kept, since they are only changed through
+/// `/events/:event_id/teams` and `/events/:event_id/rounds`
+async fn replace_event(
+    db_client: &Client,
+    stored: HashMap<String, AttributeValue>,
+    old: Event,
+    new: Event,
+) -> Result<Event, ApiError> {
+    let new = Event {
+        id: old.id,
+        event_data: EventData {
+            team_ids: old.event_data.team_ids.clone(),
+            round_count: old.event_data.round_count,
+            ..new.event_data
+        },
+    };
+    validate_entries(&new.event_data)?;
+    if !new.event_data.is_relay() && !new.event_data.team_ids.is_empty() {
+        return Err(ApiError::UnprocessableEntity(
+            "Withdraw the entered teams before making this event an individual event".to_string(),
+        ));
+    }
+    let mut steps = entry_steps(
+        old.id,
+        &old.event_data.athlete_ids,
+        &new.event_data.athlete_ids,
+    )?;
+    steps.extend(combined_event_checks(db_client, &new.event_data).await?);
+    steps.push(put_event(stored, new.clone())?);
+    transact(db_client, steps).await?;
+    // The bests of an athlete are grouped by discipline and season, which both come from the event
+    if old.event_data.discipline != new.event_data.discipline
+        || old.event_data.date_time != new.event_data.date_time
+    {
+        personal_best::refresh_event(db_client, old.id).await;
+    }
+    Ok(new)
+}
+
+/// Delete an event and take it off the calendar of every entered athlete, including relay legs
+async fn remove_event(db_client: &Client, caller: &Caller, event_id: Uuid) -> Result<(), ApiError> {
+    let Some(event) = fetch_item::<Event>(db_client, event_id).await? else {
+        return Ok(());
+    };
+    event.authorize_modify(db_client, caller).await?;
+    let mut steps = entry_steps(event_id, &event.event_data.athlete_ids, &[])?;
+    for team in fetch_items::<RelayTeam>(db_client, &event.event_data.team_ids).await? {
+        for leg in team.calendar_entries(event_id) {
+            steps.push(transaction::delete::<AthleteEvent>(leg.key())?);
+        }
+    }
+    steps.push(transaction::delete::<Event>(HashMap::from([(
+        ID_KEY.to_string(),
+        AttributeValue::S(event_id.to_string()),
+    )]))?);
+    transact(db_client, steps).await?;
+    personal_best::refresh_event(db_client, event_id).await;
+    Ok(())
+}
+
+async fn add_event(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Json(event_data): Json<EventData>,
+) -> Result<Json<Event>, ApiError> {
+    let mut event = Event::from(event_data);
+    event.authorize_create(&db_client, &caller).await?;
+    create_event(&db_client, event.clone()).await?;
+    Ok(Json(event))
+}
+
+async fn update_event(
+    caller: Caller,
+    Path(event_id): Path<Uuid>,
+    State(db_client): State<Client>,
+    Json(event_data): Json<EventData>,
+) -> Result<Json<Event>, ApiError> {
+    let (stored, old) = fetch_event_row(&db_client, event_id).await?;
+    let mut new = Event::from(event_data);
+    new.authorize_replace(&old, &db_client, &caller).await?;
+    replace_event(&db_client, stored, old, new).await.map(Json)
+}
+
+async fn patch_event(
+    caller: Caller,
+    Path(event_id): Path<Uuid>,
+    State(db_client): State<Client>,
+    Json(patch): Json<Value>,
+) -> Result<Json<Event>, ApiError> {
+    let (stored, old) = fetch_event_row(&db_client, event_id).await?;
+    let mut new = apply_patch::<Event, EventData>(old.clone(), patch)?;
+    new.authorize_replace(&old, &db_client, &caller).await?;
+    replace_event(&db_client, stored, old, new).await.map(Json)
+}
+
+async fn delete_event(
+    caller: Caller,
+    Path(event_id): Path<Uuid>,
+    State(db_client): State<Client>,
+) -> Result<StatusCode, ApiError> {
+    remove_event(&db_client, &caller, event_id).await?;
+    Ok(StatusCode::OK)
+}
+
+/// The failed result of one event of a batch
+fn batch_failure<T>(id: Uuid, err: ApiError) -> BatchWriteResult<T> {
+    BatchWriteResult::Failed {
+        id,
+        error: err
+            .detail()
+            .unwrap_or_else(|| "An unexpected error occurred".to_string()),
+    }
+}
+
+/// Add a list of events
+/// Unlike the other batch routes, each event is written in its own transaction so the athlete calendars stay in
+/// sync with the entry lists.
+async fn batch_add_events(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Json(events): Json<Vec<EventData>>,
+) -> Result<Json<Vec<BatchWriteResult<Event>>>, ApiError> {
+    check_batch_size(events.len())?;
+    let mut results = Vec::with_capacity(events.len());
+    for event_data in events {
+        let mut event = Event::from(event_data);
+        let created = match event.authorize_create(&db_client, &caller).await {
+            Ok(()) => create_event(&db_client, event.clone()).await,
+            Err(err) => Err(err),
+        };
+        results.push(match created {
+            Ok(()) => BatchWriteResult::Created { item: event },
+            Err(err) => batch_failure(event.id, err),
+        });
+    }
+    Ok(Json(results))
+}
+
+/// Delete a list of events, each in its own transaction
+async fn batch_delete_events(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Json(batch): Json<BatchIds>,
+) -> Result<Json<Vec<BatchWriteResult<Event>>>, ApiError> {
+    batch.validate()?;
+    let mut results = Vec::with_capacity(batch.ids.len());
+    for id in batch.ids {
+        results.push(match remove_event(&db_client, &caller, id).await {
+            Ok(()) => BatchWriteResult::Deleted { id },
+            Err(err) => batch_failure(id, err),
+        });
+    }
+    Ok(Json(results))
+}
+
+/// Get every athlete entered in the event, in entry list order
+async fn get_event_athletes(
+    Path(event_id): Path<Uuid>,
+    State(db_client): State<Client>,
+) -> Result<Json<Vec<Athlete>>, ApiError> {
+    let event = fetch_existing_item::<Event>(&db_client, event_id).await?;
+    let mut athletes: HashMap<Uuid, Athlete> =
+        fetch_items::<Athlete>(&db_client, &event.event_data.athlete_ids)
+            .await?
+            .into_iter()
+            .map(|athlete| (athlete.id, athlete))
+            .collect();
+    Ok(Json(
+        event
+            .event_data
+            .athlete_ids
+            .iter()
+            .filter_map(|athlete_id| athletes.remove(athlete_id))
+            .collect(),
+    ))
+}
+
 pub fn event_routes() -> axum::Router<Client> {
     axum::Router::new()
-        .route("/", post(add_item::<Event, EventData>))
-        .route("/", get(get_items::<Event>))
-        .route("/:competition_id", get(get_item::<Event>))
-        .route("/:competition_id", delete(delete_item::<Event>))
+        .route("/", post(add_event))
+        .route("/", get(get_items::<Event, EventFilter>))
+        .route("/batch", post(batch_add_events))
+        .route("/batch", delete(batch_delete_events))
+        .route("/:event_id", get(get_item::<Event>))
+        .route("/:event_id", delete(delete_event))
+        .route("/:event_id", put(update_event))
+        .route("/:event_id", patch(patch_event))
+        .route("/:event_id/athletes", get(get_event_athletes))
 }
 
 #[cfg(test)]
@@ -112,9 +742,16 @@ mod tests {
             id: Uuid::new_v4(),
             event_data: EventData {
                 competition_id: Uuid::new_v4(),
-                athlete_id: Uuid::new_v4(),
-                name: "100m".to_string(),
+                athlete_ids: vec![Uuid::new_v4(), Uuid::new_v4()],
+                team_ids: vec![],
+                discipline: Discipline::Sprint100m,
+                category: Category {
+                    gender: Gender::Women,
+                    age_group: AgeGroup::U20,
+                },
                 date_time: Utc::now(),
+                combined_event_id: Some(Uuid::new_v4()),
+                round_count: 2,
             },
         };
         let cloned_event = event.clone();
@@ -122,4 +759,156 @@ mod tests {
         let event2 = Event::from_hashmap(hashmap).unwrap();
         assert_eq!(event, event2);
     }
+
+    #[test]
+    fn test_put_event_checks_entries() {
+        let old = Event {
+            id: Uuid::new_v4(),
+            event_data: EventData {
+                competition_id: Uuid::new_v4(),
+                athlete_ids: vec![Uuid::new_v4()],
+                team_ids: vec![],
+                discipline: Discipline::Sprint100m,
+                category: Category {
+                    gender: Gender::Women,
+                    age_group: AgeGroup::Open,
+                },
+                date_time: Utc::now(),
+                combined_event_id: None,
+                round_count: 0,
+            },
+        };
+        let mut new = old.clone();
+        new.event_data.discipline = Discipline::Sprint200m;
+        let step = put_event(old.clone().into_hashmap(), new.clone()).unwrap();
+        let put = step.item.put().unwrap();
+        // Every attribute is checked against the value it was read with, not only the entries
+        let condition = put.condition_expression().unwrap();
+        assert!(condition.starts_with("attribute_exists(#pk)"));
+        let names = put.expression_attribute_names().unwrap();
+        let values = put.expression_attribute_values().unwrap();
+        for (name, value) in old.clone().into_hashmap() {
+            if name == ID_KEY {
+                continue;
+            }
+            let (placeholder, _) = names.iter().find(|(_, n)| **n == name).unwrap();
+            let stored = condition
+                .split(" AND ")
+                .find_map(|clause| clause.strip_prefix(&format!("{placeholder} = ")))
+                .unwrap();
+            assert_eq!(values[stored], value, "{name}");
+        }
+        assert!(values
+            .keys()
+            .all(|placeholder| !placeholder.starts_with(":v")));
+        assert!(matches!(
+            step.on_condition_failed,
+            Some(ApiError::Conflict(_))
+        ));
+
+        // A legacy row holds its athlete in `athlete_id`, which is checked and dropped
+        let mut legacy = old.clone().into_hashmap();
+        legacy.remove(ATHLETE_IDS_KEY);
+        legacy.insert(
+            LEGACY_ATHLETE_ID_KEY.to_string(),
+            AttributeValue::S(old.event_data.athlete_ids[0].to_string()),
+        );
+        let step = put_event(legacy, new).unwrap();
+        let put = step.item.put().unwrap();
+        assert!(put
+            .condition_expression()
+            .unwrap()
+            .ends_with(" AND #legacy_athlete = :legacy_athlete"));
+        assert!(!put.item().contains_key(LEGACY_ATHLETE_ID_KEY));
+    }
+
+    #[test]
+    fn test_event_filter_uses_competition_index() {
+        let competition_id = Uuid::new_v4();
+        let filter = EventFilter {
+            competition_id: Some(competition_id),
+            discipline: Some(Discipline::Sprint200m),
+            date_time_from: Some(Utc::now()),
+            date_time_to: Some(Utc::now()),
+            ..Default::default()
+        };
+        let query = filter.list_query();
+        let index = query.index.unwrap();
+        assert_eq!(index.index_name, COMPETITION_INDEX);
+        assert_eq!(
+            index.key_condition.condition().unwrap(),
+            "#k0 = :k0 AND #k1 BETWEEN :k1 AND :k2"
+        );
+        assert_eq!(query.filter.condition().unwrap(), "#f0 = :f0");
+        // The index keeps the events in date_time order across pages
+        assert!(!EventFilter {
+            sort: Some(EventSort::DateTimeDesc),
+            ..filter
+        }
+        .sorts_within_page());
+        assert!(EventFilter {
+            sort: Some(EventSort::DateTime),
+            ..Default::default()
+        }
+        .sorts_within_page());
+    }
+
+    #[test]
+    fn test_event_from_hashmap_missing_attribute() {
+        let event = Event {
+            id: Uuid::new_v4(),
+            event_data: EventData {
+                competition_id: Uuid::new_v4(),
+                athlete_ids: vec![Uuid::new_v4(), Uuid::new_v4()],
+                team_ids: vec![],
+                discipline: Discipline::Sprint100m,
+                category: Category {
+                    gender: Gender::Women,
+                    age_group: AgeGroup::U20,
+                },
+                date_time: Utc::now(),
+                combined_event_id: None,
+                round_count: 0,
+            },
+        };
+        let mut hashmap = event.clone().into_hashmap();
+        hashmap.remove(GENDER_KEY);
+        assert_eq!(
+            Event::from_hashmap(hashmap),
+            Err(ItemDecodeError::MissingAttribute {
+                table: TABLE_NAME,
+                key: Some(event.id.to_string()),
+                attribute: GENDER_KEY.to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_event_from_hashmap_legacy_name() {
+        let event = Event {
+            id: Uuid::new_v4(),
+            event_data: EventData {
+                competition_id: Uuid::new_v4(),
+                athlete_ids: vec![],
+                team_ids: vec![],
+                discipline: Discipline::Hurdles110m,
+                category: Category {
+                    gender: Gender::Men,
+                    age_group: AgeGroup::Open,
+                },
+                date_time: Utc::now(),
+                combined_event_id: None,
+                round_count: 0,
+            },
+        };
+        let mut hashmap = event.clone().into_hashmap();
+        for key in [DISCIPLINE_KEY, GENDER_KEY, AGE_GROUP_KEY] {
+            hashmap.remove(key);
+        }
+        hashmap.insert(
+            LEGACY_NAME_KEY.to_string(),
+            AttributeValue::S("Men's 110m Hurdles".to_string()),
+        );
+        assert_eq!(Event::from_hashmap(hashmap), Ok(event));
+    }
 }
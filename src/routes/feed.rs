@@ -0,0 +1,299 @@
+use std::collections::{BTreeMap, HashMap};
+
+use aws_sdk_dynamodb::Client;
+use axum::extract::{Path, Query, State};
+use axum::Json;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+use uuid::Uuid;
+
+use super::athlete::Athlete;
+use super::athlete_event::{self, AthleteEvent};
+use super::competition::Competition;
+use super::discipline::{Category, Discipline};
+use super::error::ApiError;
+use super::event::Event;
+use super::user::User;
+use super::user_athlete::{self, UserAthlete};
+use super::utils::fetch_items;
+use super::utils::{fetch_existing_item, query_all};
+
+const DEFAULT_DAYS: i64 = 30;
+const MAX_DAYS: i64 = 365;
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 100;
+
+/// The `?days=` and `?limit=` query parameters of the feed
+/// `days` is how far ahead to look, and `limit` is the most competitions (summed over every day) to return
+#[derive(Debug, Default, Deserialize)]
+pub struct FeedParams {
+    days: Option<i64>,
+    limit: Option<usize>,
+}
+
+impl FeedParams {
+    fn days(&self) -> Result<i64, ApiError> {
+        match self.days {
+            None => Ok(DEFAULT_DAYS),
+            Some(days) if (1..=MAX_DAYS).contains(&days) => Ok(days),
+            Some(days) => Err(ApiError::BadRequest(format!(
+                "days must be between 1 and {MAX_DAYS}, got {days}"
+            ))),
+        }
+    }
+
+    fn limit(&self) -> Result<usize, ApiError> {
+        match self.limit {
+            None => Ok(DEFAULT_LIMIT),
+            Some(limit) if (1..=MAX_LIMIT).contains(&limit) => Ok(limit),
+            Some(limit) => Err(ApiError::BadRequest(format!(
+                "limit must be between 1 and {MAX_LIMIT}, got {limit}"
+            ))),
+        }
+    }
+}
+
+/// A followed athlete competing in an event
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct FeedEntry {
+    athlete: Athlete,
+    event_id: Uuid,
+    discipline: Discipline,
+    category: Category,
+    date_time: DateTime<Utc>,
+}
+
+/// A competition with the followed athletes competing at it on one day
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct FeedCompetition {
+    #[serde(flatten)]
+    competition: Competition,
+    entries: Vec<FeedEntry>,
+}
+
+/// Every competition with a followed athlete competing on one day
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct FeedDay {
+    date: NaiveDate,
+    competitions: Vec<FeedCompetition>,
+}
+
+/// Group the entries of followed athletes into competitions by day, earliest first
+/// Entries are dated by their event, so a competition that spans several days shows up once for each day.
+/// Entries at competitions missing from `competitions` are left out.
+/// At most `limit` competitions are returned in total.
+fn build_feed(
+    mut entries: Vec<(Athlete, Event)>,
+    competitions: &HashMap<Uuid, Competition>,
+    limit: usize,
+) -> Vec<FeedDay> {
+    entries.sort_by_key(|(_, event)| event.event_data.date_time);
+    let mut days: BTreeMap<NaiveDate, Vec<FeedCompetition>> = BTreeMap::new();
+    for (athlete, event) in entries {
+        let Some(competition) = competitions.get(&event.event_data.competition_id) else {
+            continue;
+        };
+        let day = days
+            .entry(event.event_data.date_time.date_naive())
+            .or_default();
+        let entry = FeedEntry {
+            athlete,
+            event_id: event.id,
+            discipline: event.event_data.discipline,
+            category: event.event_data.category,
+            date_time: event.event_data.date_time,
+        };
+        match day.iter_mut().find(|c| c.competition.id == competition.id) {
+            Some(feed_competition) => feed_competition.entries.push(entry),
+            None => day.push(FeedCompetition {
+                competition: competition.clone(),
+                entries: vec![entry],
+            }),
+        }
+    }
+    let mut remaining = limit;
+    let mut feed = Vec::new();
+    for (date, mut competitions) in days {
+        if remaining == 0 {
+            break;
+        }
+        competitions.truncate(remaining);
+        remaining -= competitions.len();
+        feed.push(FeedDay { date, competitions });
+    }
+    feed
+}
+
+/// Whether the competition has not ended yet and starts within the next `days` days
+fn is_upcoming(competition: &Competition, today: NaiveDate, days: i64) -> bool {
+    let data = &competition.competition_data;
+    data.end_date >= today && data.start_date <= today + Duration::days(days)
+}
+
+/// Get the upcoming competitions of every athlete the user follows
+/// Follows `user_athlete` → `athlete_events` → `events` → `competitions`, keeping competitions that have not
+/// ended and start within `?days=` days. Every entry of a followed athlete at such a competition is listed.
+#[instrument(skip(db_client))]
+pub async fn get_feed(
+    State(db_client): State<Client>,
+    Path(user_id): Path<Uuid>,
+    Query(params): Query<FeedParams>,
+) -> Result<Json<Vec<FeedDay>>, ApiError> {
+    let days = params.days()?;
+    let limit = params.limit()?;
+    fetch_existing_item::<User>(&db_client, user_id).await?;
+    let follows =
+        query_all::<UserAthlete>(&db_client, None, user_athlete::USER_ID_KEY, user_id).await?;
+
+    let mut athlete_events: Vec<AthleteEvent> = Vec::new();
+    for follow in &follows {
+        athlete_events.extend(
+            query_all::<AthleteEvent>(
+                &db_client,
+                None,
+                athlete_event::ATHLETE_ID_KEY,
+                follow.athlete_id,
+            )
+            .await?,
+        );
+    }
+
+    let event_ids: Vec<Uuid> = athlete_events.iter().map(|entry| entry.event_id).collect();
+    let events: HashMap<Uuid, Event> = fetch_items::<Event>(&db_client, &event_ids)
+        .await?
+        .into_iter()
+        .map(|event| (event.id, event))
+        .collect();
+
+    let today = Utc::now().date_naive();
+    let competition_ids: Vec<Uuid> = events
+        .values()
+        .map(|event| event.event_data.competition_id)
+        .collect();
+    let competitions: HashMap<Uuid, Competition> =
+        fetch_items::<Competition>(&db_client, &competition_ids)
+            .await?
+            .into_iter()
+            .filter(|competition| is_upcoming(competition, today, days))
+            .map(|competition| (competition.id, competition))
+            .collect();
+
+    let athlete_ids: Vec<Uuid> = follows.iter().map(|follow| follow.athlete_id).collect();
+    let athletes: HashMap<Uuid, Athlete> = fetch_items::<Athlete>(&db_client, &athlete_ids)
+        .await?
+        .into_iter()
+        .map(|athlete| (athlete.id, athlete))
+        .collect();
+
+    let entries = athlete_events
+        .into_iter()
+        .filter_map(|entry| {
+            let athlete = athletes.get(&entry.athlete_id)?.clone();
+            let event = events.get(&entry.event_id)?.clone();
+            Some((athlete, event))
+        })
+        .collect();
+    Ok(Json(build_feed(entries, &competitions, limit)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::competition::CompetitionData;
+    use crate::routes::discipline::{AgeGroup, Gender};
+    use crate::routes::event::EventData;
+    use chrono::TimeZone;
+
+    fn competition(start_date: NaiveDate, end_date: NaiveDate) -> Competition {
+        Competition {
+            id: Uuid::new_v4(),
+            competition_data: CompetitionData {
+                name: "Penn Relays".to_string(),
+                location: "Philadelphia".to_string(),
+                start_date,
+                end_date,
+                director_id: None,
+            },
+        }
+    }
+
+    fn penn_relays() -> Competition {
+        competition(
+            NaiveDate::from_ymd_opt(2030, 4, 25).unwrap(),
+            NaiveDate::from_ymd_opt(2030, 4, 27).unwrap(),
+        )
+    }
+
+    fn event(competition: &Competition, athlete: &Athlete, day: u32, hour: u32) -> Event {
+        Event {
+            id: Uuid::new_v4(),
+            event_data: EventData {
+                competition_id: competition.id,
+                athlete_ids: vec![athlete.id],
+                team_ids: Vec::new(),
+                discipline: Discipline::Sprint200m,
+                category: Category {
+                    gender: Gender::Men,
+                    age_group: AgeGroup::default(),
+                },
+                date_time: Utc.with_ymd_and_hms(2030, 4, day, hour, 0, 0).unwrap(),
+                combined_event_id: None,
+                round_count: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn test_build_feed_groups_by_day_and_competition() {
+        let (jane, john) = (
+            Athlete::sample("Jane", "Doe"),
+            Athlete::sample("John", "Doe"),
+        );
+        let penn = penn_relays();
+        let competitions = HashMap::from([(penn.id, penn.clone())]);
+        let entries = vec![
+            (jane.clone(), event(&penn, &jane, 26, 15)),
+            (john.clone(), event(&penn, &john, 25, 12)),
+            (jane.clone(), event(&penn, &jane, 25, 10)),
+        ];
+
+        let feed = build_feed(entries, &competitions, 10);
+        assert_eq!(feed.len(), 2);
+        assert_eq!(feed[0].date, NaiveDate::from_ymd_opt(2030, 4, 25).unwrap());
+        assert_eq!(feed[0].competitions.len(), 1);
+        let athletes: Vec<Uuid> = feed[0].competitions[0]
+            .entries
+            .iter()
+            .map(|entry| entry.athlete.id)
+            .collect();
+        assert_eq!(athletes, vec![jane.id, john.id]);
+        assert_eq!(feed[1].competitions[0].entries.len(), 1);
+    }
+
+    #[test]
+    fn test_build_feed_limit() {
+        let jane = Athlete::sample("Jane", "Doe");
+        let (penn, drake) = (penn_relays(), penn_relays());
+        let competitions = HashMap::from([(penn.id, penn.clone()), (drake.id, drake.clone())]);
+        let entries = vec![
+            (jane.clone(), event(&penn, &jane, 25, 10)),
+            (jane.clone(), event(&drake, &jane, 25, 12)),
+            (jane.clone(), event(&penn, &jane, 26, 10)),
+        ];
+
+        let feed = build_feed(entries, &competitions, 2);
+        assert_eq!(feed.len(), 1);
+        assert_eq!(feed[0].competitions.len(), 2);
+    }
+
+    #[test]
+    fn test_upcoming_competitions() {
+        let day = |day| NaiveDate::from_ymd_opt(2030, 4, day).unwrap();
+        let today = day(10);
+        assert!(is_upcoming(&competition(day(8), day(10)), today, 30));
+        assert!(is_upcoming(&competition(day(12), day(13)), today, 2));
+        assert!(!is_upcoming(&competition(day(8), day(9)), today, 30));
+        assert!(!is_upcoming(&competition(day(13), day(14)), today, 2));
+    }
+}
@@ -0,0 +1,165 @@
+use std::{collections::HashMap, fmt::Debug};
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::de::DeserializeOwned;
+
+/// A DynamoDB condition expression, built one comparison at a time
+/// Every comparison is joined with `AND`.
+/// Placeholders are prefixed so a key condition and a filter can be sent in the same request without clashing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expression {
+    prefix: &'static str,
+    conditions: Vec<String>,
+    names: HashMap<String, String>,
+    values: HashMap<String, AttributeValue>,
+}
+
+impl Expression {
+    pub fn new(prefix: &'static str) -> Self {
+        Self {
+            prefix,
+            conditions: Vec::new(),
+            names: HashMap::new(),
+            values: HashMap::new(),
+        }
+    }
+
+    /// The placeholder for an attribute name, reusing it if the attribute was already used
+    fn name(&mut self, attribute: &str) -> String {
+        if let Some((placeholder, _)) = self.names.iter().find(|(_, name)| *name == attribute) {
+            return placeholder.clone();
+        }
+        let placeholder = format!("#{}{}", self.prefix, self.names.len());
+        self.names
+            .insert(placeholder.clone(), attribute.to_string());
+        placeholder
+    }
+
+    fn value(&mut self, value: AttributeValue) -> String {
+        let placeholder = format!(":{}{}", self.prefix, self.values.len());
+        self.values.insert(placeholder.clone(), value);
+        placeholder
+    }
+
+    fn compare(&mut self, attribute: &str, operator: &str, value: AttributeValue) -> &mut Self {
+        let name = self.name(attribute);
+        let value = self.value(value);
+        self.conditions.push(format!("{name} {operator} {value}"));
+        self
+    }
+
+    pub fn equals(&mut self, attribute: &str, value: AttributeValue) -> &mut Self {
+        self.compare(attribute, "=", value)
+    }
+
+    pub fn at_least(&mut self, attribute: &str, value: AttributeValue) -> &mut Self {
+        self.compare(attribute, ">=", value)
+    }
+
+    pub fn at_most(&mut self, attribute: &str, value: AttributeValue) -> &mut Self {
+        self.compare(attribute, "<=", value)
+    }
+
+    pub fn between(
+        &mut self,
+        attribute: &str,
+        low: AttributeValue,
+        high: AttributeValue,
+    ) -> &mut Self {
+        let name = self.name(attribute);
+        let low = self.value(low);
+        let high = self.value(high);
+        self.conditions
+            .push(format!("{name} BETWEEN {low} AND {high}"));
+        self
+    }
+
+    pub fn contains(&mut self, attribute: &str, value: AttributeValue) -> &mut Self {
+        let name = self.name(attribute);
+        let value = self.value(value);
+        self.conditions.push(format!("contains({name}, {value})"));
+        self
+    }
+
+    /// The expression string, or `None` if no comparisons were added
+    pub fn condition(&self) -> Option<String> {
+        if self.conditions.is_empty() {
+            None
+        } else {
+            Some(self.conditions.join(" AND "))
+        }
+    }
+
+    pub fn names(&self) -> &HashMap<String, String> {
+        &self.names
+    }
+
+    pub fn values(&self) -> &HashMap<String, AttributeValue> {
+        &self.values
+    }
+}
+
+/// A query against a global secondary index
+/// `key_condition` must constrain the partition key of the index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexQuery {
+    pub index_name: &'static str,
+    pub key_condition: Expression,
+    /// Whether to walk the sort key of the index in ascending order
+    pub ascending: bool,
+}
+
+/// How a list request is sent to DynamoDB
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListQuery {
+    /// When present the list is read with a `Query` on the index, otherwise with a `Scan` of the table
+    pub index: Option<IndexQuery>,
+    pub filter: Expression,
+}
+
+impl ListQuery {
+    pub fn scan(filter: Expression) -> Self {
+        Self {
+            index: None,
+            filter,
+        }
+    }
+}
+
+/// The typed query-string filters for a list of `T`
+pub trait ListFilter<T>: DeserializeOwned + Debug + Send {
+    /// Compile the filters into a DynamoDB request
+    fn list_query(&self) -> ListQuery;
+
+    /// Sort a page of items
+    /// Lists read from an index are already ordered by its sort key across pages; other lists are only sorted
+    /// within each page.
+    fn sort(&self, _items: &mut [T]) {}
+
+    /// Whether `sort` reorders the items of a page rather than following the order of an index
+    /// Such a sort only orders one page, so it cannot be carried on to the next page with a cursor.
+    fn sorts_within_page(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expression_reuses_names() {
+        let mut expression = Expression::new("f");
+        expression
+            .at_least("StartDate", AttributeValue::S("2024-01-01".to_string()))
+            .at_most("StartDate", AttributeValue::S("2024-12-31".to_string()))
+            .contains("Name", AttributeValue::S("Relays".to_string()));
+        assert_eq!(
+            expression.condition().unwrap(),
+            "#f0 >= :f0 AND #f0 <= :f1 AND contains(#f1, :f2)"
+        );
+        assert_eq!(expression.names().len(), 2);
+        assert_eq!(expression.values().len(), 3);
+        assert_eq!(Expression::new("k").condition(), None);
+    }
+}
@@ -1,7 +1,70 @@
 pub mod athlete;
+pub mod athlete_claim;
 pub mod athlete_event;
+pub mod athlete_manager;
+pub mod attempt;
+pub mod auth;
+pub mod batch;
 pub mod competition;
+pub mod discipline;
+pub mod error;
 pub mod event;
+pub mod feed;
+pub mod filter;
+pub mod pagination;
+pub mod permission;
+pub mod personal_best;
+pub mod relay_team;
+pub mod result;
+pub mod result_entry;
+pub mod round;
+pub mod scoring;
+pub mod transaction;
 pub mod user;
 pub mod user_athlete;
+pub mod username;
 pub mod utils;
+
+use aws_sdk_dynamodb::Client;
+use axum::{middleware, Router};
+
+/// Every route of the app, nested under the collection it belongs to
+/// Requests that change data need an access token from `/auth`, which is open to everyone.
+pub fn router() -> Router<Client> {
+    Router::new()
+        .nest("/athlete-claims", athlete_claim::claim_routes())
+        .nest("/competitions", competition::competition_routes())
+        .nest("/disciplines", discipline::discipline_routes())
+        .nest(
+            "/athletes",
+            athlete::athlete_routes()
+                .merge(athlete_claim::athlete_claim_routes())
+                .merge(athlete_event::athlete_event_routes())
+                .merge(personal_best::personal_best_routes()),
+        )
+        .nest(
+            "/events",
+            event::event_routes()
+                .merge(result::event_result_routes())
+                .merge(round::event_round_routes())
+                .merge(relay_team::event_team_routes())
+                .merge(scoring::event_scoring_routes()),
+        )
+        .nest("/relay-teams", relay_team::relay_team_routes())
+        .nest("/results", result::result_routes())
+        .nest("/rounds", round::round_routes())
+        .nest("/users", user::user_routes())
+        .route_layer(middleware::from_fn(auth::require_auth))
+        .nest("/auth", auth::auth_routes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes_do_not_conflict() {
+        // axum panics when two routes conflict, so building the router is enough
+        let _ = router();
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+
+use super::error::ApiError;
+
+/// The page size used when the client does not pass `?limit=`
+pub const DEFAULT_LIMIT: i32 = 50;
+/// The largest page size a client can ask for
+pub const MAX_LIMIT: i32 = 100;
+
+/// The `?limit=` and `?cursor=` query parameters accepted by every list route
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct PageParams {
+    pub limit: Option<i32>,
+    pub cursor: Option<String>,
+}
+
+impl PageParams {
+    /// The validated page size
+    pub fn limit(&self) -> Result<i32, ApiError> {
+        match self.limit {
+            None => Ok(DEFAULT_LIMIT),
+            Some(limit) if (1..=MAX_LIMIT).contains(&limit) => Ok(limit),
+            Some(limit) => Err(ApiError::BadRequest(format!(
+                "limit must be between 1 and {MAX_LIMIT}, got {limit}"
+            ))),
+        }
+    }
+
+    /// The key to resume the scan or query from, decoded from the cursor
+    pub fn exclusive_start_key(&self) -> Result<Option<HashMap<String, AttributeValue>>, ApiError> {
+        self.cursor.as_deref().map(decode_cursor).transpose()
+    }
+}
+
+/// A single page of a list response
+/// `next_cursor` is only present when there are more items to fetch
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    /// Why each row of the page that could not be read as an item was left out, i.e. a legacy event whose name
+    /// matches no discipline
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unreadable: Vec<String>,
+}
+
+/// Encode a DynamoDB `LastEvaluatedKey` as an opaque cursor
+/// Every key in this app is a string, so the key is stored as a JSON object of strings
+pub fn encode_cursor(key: &HashMap<String, AttributeValue>) -> Result<String, ApiError> {
+    let key = key
+        .iter()
+        .map(|(name, value)| {
+            value
+                .as_s()
+                .map(|value| (name.clone(), value.clone()))
+                .map_err(|_| ApiError::Internal(format!("key attribute {name} is not a string")))
+        })
+        .collect::<Result<HashMap<String, String>, ApiError>>()?;
+    let json = serde_json::to_vec(&key).map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok(URL_SAFE_NO_PAD.encode(json))
+}
+
+/// Decode a cursor created by `encode_cursor` back into an `ExclusiveStartKey`
+pub fn decode_cursor(cursor: &str) -> Result<HashMap<String, AttributeValue>, ApiError> {
+    let invalid = || ApiError::BadRequest("The cursor is not valid".to_string());
+    let json = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+    let key: HashMap<String, String> = serde_json::from_slice(&json).map_err(|_| invalid())?;
+    if key.is_empty() {
+        return Err(invalid());
+    }
+    Ok(key
+        .into_iter()
+        .map(|(name, value)| (name, AttributeValue::S(value)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let key = HashMap::from([
+            ("user_id".to_string(), AttributeValue::S("a".to_string())),
+            ("athlete_id".to_string(), AttributeValue::S("b".to_string())),
+        ]);
+        let cursor = encode_cursor(&key).unwrap();
+        assert_eq!(decode_cursor(&cursor).unwrap(), key);
+    }
+
+    #[test]
+    fn test_bad_cursor_and_limit() {
+        assert!(matches!(
+            decode_cursor("not a cursor"),
+            Err(ApiError::BadRequest(_))
+        ));
+        let params = PageParams {
+            limit: Some(0),
+            cursor: None,
+        };
+        assert!(matches!(params.limit(), Err(ApiError::BadRequest(_))));
+    }
+}
@@ -0,0 +1,207 @@
+use std::future::Future;
+
+use super::auth::AuthUser;
+use super::competition::Competition;
+use super::error::ApiError;
+use super::event::Event;
+use super::user::User;
+use super::utils::{fetch_existing_item, fetch_item, Item};
+use aws_sdk_dynamodb::Client;
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// What a user is allowed to change
+/// Everyone can read everything; the role only decides what a user can write.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Can change anything, and is the only role that can hand out roles
+    Admin,
+    /// Runs competitions, and can change the events, rounds and results of the competitions they direct
+    MeetDirector,
+    /// Can change their own athlete profile and calendar
+    Athlete,
+    /// Can follow athletes and change their own user
+    #[default]
+    Fan,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::MeetDirector => "meet_director",
+            Self::Athlete => "athlete",
+            Self::Fan => "fan",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        [Self::Admin, Self::MeetDirector, Self::Athlete, Self::Fan]
+            .into_iter()
+            .find(|role| role.as_str() == value)
+    }
+}
+
+/// The signed in user making a request, with the role it had when the request was made
+/// The role is read from the database rather than the token, so a changed role takes effect straight away.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Caller {
+    pub user_id: Uuid,
+    pub role: Role,
+}
+
+impl Caller {
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+
+    /// Check that the caller is an admin or has one of `roles`
+    pub fn require_role(&self, roles: &[Role], action: &str) -> Result<(), ApiError> {
+        match self.is_admin() || roles.contains(&self.role) {
+            true => Ok(()),
+            false => Err(forbidden(action)),
+        }
+    }
+
+    /// Check that the caller is an admin or the user `user_id`
+    pub fn require_self(&self, user_id: Uuid, action: &str) -> Result<(), ApiError> {
+        match self.is_admin() || self.user_id == user_id {
+            true => Ok(()),
+            false => Err(forbidden(action)),
+        }
+    }
+
+    /// Check that the caller is an admin or the meet director of the competition
+    pub async fn require_director(
+        &self,
+        db_client: &Client,
+        competition_id: Uuid,
+    ) -> Result<(), ApiError> {
+        if self.is_admin() {
+            return Ok(());
+        }
+        let directs = self.role == Role::MeetDirector
+            && fetch_item::<Competition>(db_client, competition_id)
+                .await?
+                .is_some_and(|competition| competition.director_id() == Some(self.user_id));
+        match directs {
+            true => Ok(()),
+            false => Err(ApiError::Forbidden(format!(
+                "Only the meet director of competition {competition_id} can change it"
+            ))),
+        }
+    }
+
+    /// Check that the caller is an admin or the meet director of the competition the event is held at
+    /// Returns a 404 if the event does not exist.
+    pub async fn require_event_director(
+        &self,
+        db_client: &Client,
+        event_id: Uuid,
+    ) -> Result<(), ApiError> {
+        if self.is_admin() {
+            return Ok(());
+        }
+        let event = fetch_existing_item::<Event>(db_client, event_id).await?;
+        self.require_director(db_client, event.event_data.competition_id)
+            .await
+    }
+}
+
+pub fn forbidden(action: &str) -> ApiError {
+    ApiError::Forbidden(format!("You are not allowed to {action}"))
+}
+
+#[async_trait]
+impl FromRequestParts<Client> for Caller {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        db_client: &Client,
+    ) -> Result<Self, Self::Rejection> {
+        let AuthUser { user_id } = AuthUser::from_request_parts(parts, db_client).await?;
+        let user = fetch_item::<User>(db_client, user_id)
+            .await?
+            .ok_or_else(|| ApiError::Unauthorized(format!("User {user_id} no longer exists")))?;
+        Ok(Self {
+            user_id,
+            role: user.role(),
+        })
+    }
+}
+
+/// The permission checks of an item that users write through the API
+/// Admins pass every check, so implementations only decide what the other roles can do.
+pub trait Guarded: Item + Send + Sync {
+    /// Check that the caller may store this new item
+    /// The caller can be made the owner of the item here, i.e. the meet director of a new competition.
+    fn authorize_create(
+        &mut self,
+        db_client: &Client,
+        caller: &Caller,
+    ) -> impl Future<Output = Result<(), ApiError>> + Send;
+
+    /// Check that the caller may change or delete this stored item
+    fn authorize_modify(
+        &self,
+        db_client: &Client,
+        caller: &Caller,
+    ) -> impl Future<Output = Result<(), ApiError>> + Send;
+
+    /// Check that the caller may replace `old` with this item
+    /// By default the caller must be allowed to change `old` and to create the replacement, so an item can
+    /// never be moved out of what the caller owns.
+    fn authorize_replace(
+        &mut self,
+        old: &Self,
+        db_client: &Client,
+        caller: &Caller,
+    ) -> impl Future<Output = Result<(), ApiError>> + Send {
+        async move {
+            old.authorize_modify(db_client, caller).await?;
+            self.authorize_create(db_client, caller).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role_names() {
+        for role in [Role::Admin, Role::MeetDirector, Role::Athlete, Role::Fan] {
+            assert_eq!(Role::parse(role.as_str()), Some(role));
+            assert_eq!(
+                serde_json::to_value(role).unwrap(),
+                serde_json::json!(role.as_str())
+            );
+        }
+        assert_eq!(Role::parse("coach"), None);
+    }
+
+    #[test]
+    fn test_caller_checks() {
+        let fan = Caller {
+            user_id: Uuid::new_v4(),
+            role: Role::Fan,
+        };
+        let admin = Caller {
+            user_id: Uuid::new_v4(),
+            role: Role::Admin,
+        };
+        assert!(fan.require_self(fan.user_id, "edit").is_ok());
+        assert!(matches!(
+            fan.require_self(admin.user_id, "edit"),
+            Err(ApiError::Forbidden(_))
+        ));
+        assert!(admin.require_self(fan.user_id, "edit").is_ok());
+        assert!(fan.require_role(&[Role::MeetDirector], "edit").is_err());
+        assert!(admin.require_role(&[Role::MeetDirector], "edit").is_ok());
+    }
+}
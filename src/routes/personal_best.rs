@@ -0,0 +1,487 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::athlete::Athlete;
+use super::discipline::Discipline;
+use super::error::ApiError;
+use super::event::Event;
+use super::result::{self, EventResult, Mark};
+use super::utils::fetch_items;
+use super::utils::{fetch_existing_item, get_date_time, get_number, get_string, get_uuid};
+use super::utils::{put_item, query_all, Item, ItemDecodeError};
+use aws_sdk_dynamodb::{self, types::AttributeValue, Client};
+use axum::extract::{Path, State};
+use axum::routing::get;
+use axum::Json;
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+pub const TABLE_NAME: &str = "personal_bests";
+pub const ATHLETE_ID_KEY: &str = "athlete_id";
+pub const DISCIPLINE_KEY: &str = "discipline";
+const SEASON_KEY: &str = "season";
+/// Attributes of the personal best are prefixed with `pb_`, those of the season best with `sb_`
+const PERSONAL_BEST_PREFIX: &str = "pb_";
+const SEASON_BEST_PREFIX: &str = "sb_";
+const RESULT_ID_KEY: &str = "result_id";
+const EVENT_ID_KEY: &str = "event_id";
+const DATE_TIME_KEY: &str = "date_time";
+const MARK_TYPE_KEY: &str = "mark_type";
+const MARK_KEY: &str = "mark";
+
+/// The result behind a best mark
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Performance {
+    pub result_id: Uuid,
+    pub event_id: Uuid,
+    pub date_time: DateTime<Utc>,
+    pub mark: Mark,
+}
+
+impl Performance {
+    /// The performance of a result, or `None` for a field result without a fair attempt
+    fn new(result: &EventResult, event: &Event) -> Option<Self> {
+        Some(Self {
+            result_id: result.id,
+            event_id: event.id,
+            date_time: event.event_data.date_time,
+            mark: result.result_data.mark?,
+        })
+    }
+
+    fn insert_into(&self, map: &mut HashMap<String, AttributeValue>, prefix: &str) {
+        map.insert(
+            format!("{prefix}{RESULT_ID_KEY}"),
+            AttributeValue::S(self.result_id.to_string()),
+        );
+        map.insert(
+            format!("{prefix}{EVENT_ID_KEY}"),
+            AttributeValue::S(self.event_id.to_string()),
+        );
+        map.insert(
+            format!("{prefix}{DATE_TIME_KEY}"),
+            AttributeValue::S(self.date_time.to_rfc3339()),
+        );
+        self.mark.insert_into(
+            map,
+            &format!("{prefix}{MARK_TYPE_KEY}"),
+            &format!("{prefix}{MARK_KEY}"),
+        );
+    }
+
+    fn from_map(
+        map: &HashMap<String, AttributeValue>,
+        prefix: &str,
+    ) -> Result<Self, ItemDecodeError> {
+        Ok(Self {
+            result_id: get_uuid::<PersonalBest>(map, &format!("{prefix}{RESULT_ID_KEY}"))?,
+            event_id: get_uuid::<PersonalBest>(map, &format!("{prefix}{EVENT_ID_KEY}"))?,
+            date_time: get_date_time::<PersonalBest>(map, &format!("{prefix}{DATE_TIME_KEY}"))?,
+            mark: Mark::from_map::<PersonalBest>(
+                map,
+                &format!("{prefix}{MARK_TYPE_KEY}"),
+                &format!("{prefix}{MARK_KEY}"),
+            )?,
+        })
+    }
+}
+
+/// The best marks of an athlete in one discipline, kept up to date whenever one of their results is written
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PersonalBest {
+    pub athlete_id: Uuid,
+    pub discipline: Discipline,
+    pub personal_best: Performance,
+    /// Seasons are calendar years
+    pub season: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub season_best: Option<Performance>,
+}
+
+impl Item for PersonalBest {
+    fn table_name() -> &'static str {
+        TABLE_NAME
+    }
+    fn partition_key_name() -> &'static str {
+        ATHLETE_ID_KEY
+    }
+
+    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError> {
+        let athlete_id = get_uuid::<Self>(&map, ATHLETE_ID_KEY)?;
+        let code = get_string::<Self>(&map, DISCIPLINE_KEY)?;
+        let discipline = Discipline::parse(&code).ok_or_else(|| {
+            ItemDecodeError::invalid::<Self>(
+                &map,
+                DISCIPLINE_KEY,
+                format!("unknown discipline {code}"),
+            )
+        })?;
+        let personal_best = Performance::from_map(&map, PERSONAL_BEST_PREFIX)?;
+        let season = get_number::<Self, i32>(&map, SEASON_KEY)?;
+        let season_best = match map.contains_key(&format!("{SEASON_BEST_PREFIX}{RESULT_ID_KEY}")) {
+            true => Some(Performance::from_map(&map, SEASON_BEST_PREFIX)?),
+            false => None,
+        };
+        Ok(Self {
+            athlete_id,
+            discipline,
+            personal_best,
+            season,
+            season_best,
+        })
+    }
+
+    fn into_hashmap(self) -> HashMap<String, AttributeValue> {
+        let mut map = HashMap::new();
+        map.insert(
+            ATHLETE_ID_KEY.to_string(),
+            AttributeValue::S(self.athlete_id.to_string()),
+        );
+        map.insert(
+            DISCIPLINE_KEY.to_string(),
+            AttributeValue::S(self.discipline.code().to_string()),
+        );
+        self.personal_best
+            .insert_into(&mut map, PERSONAL_BEST_PREFIX);
+        map.insert(
+            SEASON_KEY.to_string(),
+            AttributeValue::N(self.season.to_string()),
+        );
+        if let Some(season_best) = self.season_best {
+            season_best.insert_into(&mut map, SEASON_BEST_PREFIX);
+        }
+        map
+    }
+}
+
+/// Keep `candidate` if it beats the current best
+/// Ties go to the earlier performance, which is seen first since results are walked in date order.
+fn keep_best(best: &mut Option<Performance>, candidate: Performance) {
+    match best {
+        Some(current) if !candidate.mark.beats(&current.mark) => {}
+        _ => *best = Some(candidate),
+    }
+}
+
+/// Whether the wind of a result allows its mark to count towards bests
+/// Only wind-aided marks are left out: a mark recorded without a reading counts, since many meets have no gauge.
+fn legal_wind(result: &EventResult, discipline: Discipline) -> bool {
+    match discipline.wind_applies() {
+        true => result.result_data.wind_aided() != Some(true),
+        false => true,
+    }
+}
+
+/// Work out the personal and season bests of an athlete in every discipline they have a result in
+/// Wind-aided marks are left out, so a discipline with only such marks has no best.
+fn compute_bests(
+    athlete_id: Uuid,
+    mut results: Vec<(EventResult, Event)>,
+    season: i32,
+) -> Vec<PersonalBest> {
+    results.sort_by_key(|(_, event)| event.event_data.date_time);
+    let mut disciplines: BTreeMap<Discipline, (Option<Performance>, Option<Performance>)> =
+        BTreeMap::new();
+    for (result, event) in &results {
+        if !legal_wind(result, event.event_data.discipline) {
+            continue;
+        }
+        let Some(performance) = Performance::new(result, event) else {
+            continue;
+        };
+        let (personal_best, season_best) =
+            disciplines.entry(event.event_data.discipline).or_default();
+        if event.event_data.date_time.year() == season {
+            keep_best(season_best, performance.clone());
+        }
+        keep_best(personal_best, performance);
+    }
+    disciplines
+        .into_iter()
+        .filter_map(|(discipline, (personal_best, season_best))| {
+            Some(PersonalBest {
+                athlete_id,
+                discipline,
+                personal_best: personal_best?,
+                season,
+                season_best,
+            })
+        })
+        .collect()
+}
+
+/// The disciplines of every stored best of an athlete
+/// Only the sort key is read, so rows for disciplines that no longer exist are found too.
+async fn stored_disciplines(db_client: &Client, athlete_id: Uuid) -> Result<Vec<String>, ApiError> {
+    let mut disciplines = Vec::new();
+    let mut exclusive_start_key = None;
+    loop {
+        let result = db_client
+            .query()
+            .table_name(TABLE_NAME)
+            .key_condition_expression("#pk = :pk")
+            .projection_expression("#sk")
+            .expression_attribute_names("#pk", ATHLETE_ID_KEY)
+            .expression_attribute_names("#sk", DISCIPLINE_KEY)
+            .expression_attribute_values(":pk", AttributeValue::S(athlete_id.to_string()))
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+        disciplines.extend(
+            result
+                .items
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|row| row.get(DISCIPLINE_KEY)?.as_s().ok().cloned()),
+        );
+        exclusive_start_key = result.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            return Ok(disciplines);
+        }
+    }
+}
+
+/// Recompute the bests of an athlete from all of their results and write them to the `personal_bests` table
+async fn refresh_athlete(db_client: &Client, athlete_id: Uuid) -> Result<(), ApiError> {
+    let results = query_all::<EventResult>(
+        db_client,
+        Some(result::ATHLETE_INDEX),
+        result::ATHLETE_ID_KEY,
+        athlete_id,
+    )
+    .await?;
+    let event_ids: Vec<Uuid> = results
+        .iter()
+        .map(|result| result.result_data.event_id)
+        .collect();
+    let events: HashMap<Uuid, Event> = fetch_items::<Event>(db_client, &event_ids)
+        .await?
+        .into_iter()
+        .map(|event| (event.id, event))
+        .collect();
+    // Results of events that were deleted no longer count
+    let results = results
+        .into_iter()
+        .filter_map(|result| {
+            let event = events.get(&result.result_data.event_id)?.clone();
+            Some((result, event))
+        })
+        .collect();
+    let bests = compute_bests(athlete_id, results, Utc::now().year());
+
+    let disciplines: HashSet<&str> = bests.iter().map(|best| best.discipline.code()).collect();
+    for stale in stored_disciplines(db_client, athlete_id)
+        .await?
+        .into_iter()
+        .filter(|code| !disciplines.contains(code.as_str()))
+    {
+        db_client
+            .delete_item()
+            .table_name(TABLE_NAME)
+            .key(ATHLETE_ID_KEY, AttributeValue::S(athlete_id.to_string()))
+            .key(DISCIPLINE_KEY, AttributeValue::S(stale))
+            .send()
+            .await?;
+    }
+    for best in &bests {
+        put_item(db_client, best).await?;
+    }
+    Ok(())
+}
+
+/// Recompute the bests of every athlete whose results were just written
+/// The results are already saved, so a failure is logged rather than returned; the next write for the athlete
+/// repairs their bests.
+pub async fn refresh(db_client: &Client, athlete_ids: &[Uuid]) {
+    let athlete_ids: HashSet<Uuid> = athlete_ids.iter().copied().collect();
+    for athlete_id in athlete_ids {
+        if let Err(err) = refresh_athlete(db_client, athlete_id).await {
+            warn!(
+                "Could not refresh the bests of athlete {}: {:?}",
+                athlete_id, err
+            );
+        }
+    }
+}
+
+/// Recompute the bests of every athlete with a result in the event, after the event was renamed, moved or deleted
+pub async fn refresh_event(db_client: &Client, event_id: Uuid) {
+    match query_all::<EventResult>(
+        db_client,
+        Some(result::EVENT_INDEX),
+        result::EVENT_ID_KEY,
+        event_id,
+    )
+    .await
+    {
+        Ok(results) => {
+            let athlete_ids: Vec<Uuid> = results
+                .iter()
+                .filter_map(|result| result.result_data.athlete_id)
+                .collect();
+            refresh(db_client, &athlete_ids).await;
+        }
+        Err(err) => warn!(
+            "Could not read the results of event {}: {:?}",
+            event_id, err
+        ),
+    }
+}
+
+/// Get the personal and current-season bests of an athlete in every discipline, ordered by discipline
+async fn get_bests(
+    Path(athlete_id): Path<Uuid>,
+    State(db_client): State<Client>,
+) -> Result<Json<Vec<PersonalBest>>, ApiError> {
+    fetch_existing_item::<Athlete>(&db_client, athlete_id).await?;
+    let season = Utc::now().year();
+    let mut bests = query_all::<PersonalBest>(&db_client, None, ATHLETE_ID_KEY, athlete_id).await?;
+    // Bests written in an earlier season hold that season's best, which is not the current one
+    for best in bests.iter_mut().filter(|best| best.season != season) {
+        best.season = season;
+        best.season_best = None;
+    }
+    Ok(Json(bests))
+}
+
+/// The personal best routes nested under `/athletes`
+pub fn personal_best_routes() -> axum::Router<Client> {
+    axum::Router::new().route("/:athlete_id/bests", get(get_bests))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::discipline::{AgeGroup, Category, Gender};
+    use crate::routes::event::EventData;
+    use crate::routes::result::ResultData;
+    use chrono::TimeZone;
+
+    /// A result with a still wind reading where one is taken
+    fn entry(discipline: Discipline, year: i32, mark: Mark) -> (EventResult, Event) {
+        let wind = discipline.wind_applies().then_some(0.0);
+        windy_entry(discipline, year, mark, wind)
+    }
+
+    fn windy_entry(
+        discipline: Discipline,
+        year: i32,
+        mark: Mark,
+        wind: Option<f64>,
+    ) -> (EventResult, Event) {
+        let event = Event {
+            id: Uuid::new_v4(),
+            event_data: EventData {
+                competition_id: Uuid::new_v4(),
+                athlete_ids: Vec::new(),
+                team_ids: Vec::new(),
+                discipline,
+                category: Category {
+                    gender: Gender::Men,
+                    age_group: AgeGroup::default(),
+                },
+                date_time: Utc.with_ymd_and_hms(year, 5, 1, 12, 0, 0).unwrap(),
+                combined_event_id: None,
+                round_count: 0,
+            },
+        };
+        let result = EventResult::from(ResultData {
+            event_id: event.id,
+            athlete_id: Some(Uuid::new_v4()),
+            team_id: None,
+            round_id: None,
+            place: 1,
+            mark: Some(mark),
+            wind,
+            attempts: Vec::new(),
+        });
+        (result, event)
+    }
+
+    #[test]
+    fn test_compute_bests() {
+        let athlete_id = Uuid::new_v4();
+        let old_pb = entry(Discipline::Sprint200m, 2022, Mark::Time { seconds: 20.1 });
+        let this_season = entry(Discipline::Sprint200m, 2024, Mark::Time { seconds: 20.4 });
+        let jump = entry(Discipline::LongJump, 2023, Mark::Distance { meters: 7.9 });
+        let bests = compute_bests(
+            athlete_id,
+            vec![
+                this_season.clone(),
+                old_pb.clone(),
+                entry(Discipline::Sprint200m, 2024, Mark::Time { seconds: 20.6 }),
+                jump.clone(),
+            ],
+            2024,
+        );
+
+        assert_eq!(bests.len(), 2);
+        assert_eq!(bests[0].discipline, Discipline::Sprint200m);
+        assert_eq!(bests[0].personal_best.result_id, old_pb.0.id);
+        assert_eq!(
+            bests[0].season_best.as_ref().unwrap().result_id,
+            this_season.0.id
+        );
+        assert_eq!(bests[1].discipline, Discipline::LongJump);
+        assert_eq!(bests[1].personal_best.result_id, jump.0.id);
+        assert_eq!(bests[1].season_best, None);
+    }
+
+    #[test]
+    fn test_compute_bests_skips_wind_aided_marks() {
+        let legal = windy_entry(
+            Discipline::Sprint100m,
+            2024,
+            Mark::Time { seconds: 9.9 },
+            Some(2.0),
+        );
+        let aided = windy_entry(
+            Discipline::Sprint100m,
+            2024,
+            Mark::Time { seconds: 9.7 },
+            Some(3.1),
+        );
+        let only_aided = windy_entry(
+            Discipline::LongJump,
+            2024,
+            Mark::Distance { meters: 8.5 },
+            Some(2.4),
+        );
+        let unmeasured = windy_entry(
+            Discipline::Sprint100m,
+            2024,
+            Mark::Time { seconds: 9.6 },
+            None,
+        );
+        let bests = compute_bests(
+            Uuid::new_v4(),
+            vec![legal, aided, only_aided, unmeasured.clone()],
+            2024,
+        );
+
+        assert_eq!(bests.len(), 1);
+        assert_eq!(bests[0].personal_best.result_id, unmeasured.0.id);
+    }
+
+    #[test]
+    fn test_personal_best_into_hashmap() {
+        let (result, event) = entry(Discipline::HighJump, 2024, Mark::Height { meters: 2.01 });
+        let best = PersonalBest {
+            athlete_id: Uuid::new_v4(),
+            discipline: event.event_data.discipline,
+            personal_best: Performance::new(&result, &event).unwrap(),
+            season: 2024,
+            season_best: Some(Performance::new(&result, &event).unwrap()),
+        };
+        let map = best.clone().into_hashmap();
+        assert_eq!(PersonalBest::from_hashmap(map).unwrap(), best);
+
+        let best = PersonalBest {
+            season_best: None,
+            ..best
+        };
+        let map = best.clone().into_hashmap();
+        assert_eq!(PersonalBest::from_hashmap(map).unwrap(), best);
+    }
+}
@@ -0,0 +1,629 @@
+use std::collections::HashMap;
+
+use super::athlete::Athlete;
+use super::athlete_event::{AthleteEvent, RelayLeg};
+use super::error::ApiError;
+use super::event::{self, Event};
+use super::filter::{Expression, ListFilter, ListQuery};
+use super::permission::{Caller, Guarded, Role};
+use super::transaction::{
+    self, exists_check, put_new, transact, update_existing, TransactionStep, MAX_TRANSACTION_STEPS,
+};
+use super::utils::fetch_items;
+use super::utils::{
+    apply_patch, decode_rows, fetch_existing_item, fetch_item, get_item, get_items,
+};
+use super::utils::{
+    get_number, get_string, get_uuid, get_uuid_list, uuid_list, Item, ItemDecodeError,
+};
+use aws_sdk_dynamodb::{
+    self,
+    types::{AttributeValue, ConditionCheck, TransactWriteItem, Update},
+    Client,
+};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get, patch, post, put};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+pub const TABLE_NAME: &str = "relay_teams";
+pub const ID_KEY: &str = "id";
+const NAME_KEY: &str = "name";
+const LEGS_KEY: &str = "legs";
+const ENTRY_VERSION_KEY: &str = "entry_version";
+const CREATED_BY_KEY: &str = "created_by";
+/// Every relay in the programme is run over four legs
+pub const RELAY_LEGS: usize = 4;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RelayTeam {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub team_data: RelayTeamData,
+    /// Moved on every time the team is entered in an event, so changing the legs notices an entry made after it
+    /// looked up the events the team is entered in
+    #[serde(skip)]
+    entry_version: u32,
+    /// The meet director who made the team up, who manages it until it is entered in an event
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    created_by: Option<Uuid>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RelayTeamData {
+    pub name: String,
+    /// The athletes running each leg, in running order
+    pub legs: Vec<Uuid>,
+}
+
+impl From<RelayTeamData> for RelayTeam {
+    fn from(team_data: RelayTeamData) -> Self {
+        let id = Uuid::new_v4();
+        Self {
+            id,
+            team_data,
+            entry_version: 0,
+            created_by: None,
+        }
+    }
+}
+
+impl RelayTeam {
+    /// The calendar entry of every leg of the team for an event it is entered in
+    pub fn calendar_entries(&self, event_id: Uuid) -> Vec<AthleteEvent> {
+        self.team_data
+            .legs
+            .iter()
+            .zip(1..)
+            .map(|(athlete_id, leg)| {
+                AthleteEvent::relay(
+                    *athlete_id,
+                    event_id,
+                    RelayLeg {
+                        team_id: self.id,
+                        leg,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+/// The query-string filters accepted by `GET /relay-teams`
+#[derive(Debug, Default, Deserialize)]
+struct RelayTeamFilter {
+    name: Option<String>,
+    /// Only teams with the athlete on one of their legs
+    athlete_id: Option<Uuid>,
+}
+
+impl ListFilter<RelayTeam> for RelayTeamFilter {
+    fn list_query(&self) -> ListQuery {
+        let mut filter = Expression::new("f");
+        if let Some(name) = &self.name {
+            filter.equals(NAME_KEY, AttributeValue::S(name.clone()));
+        }
+        if let Some(athlete_id) = self.athlete_id {
+            filter.contains(LEGS_KEY, AttributeValue::S(athlete_id.to_string()));
+        }
+        ListQuery::scan(filter)
+    }
+}
+
+impl Item for RelayTeam {
+    fn table_name() -> &'static str {
+        TABLE_NAME
+    }
+    fn partition_key_name() -> &'static str {
+        ID_KEY
+    }
+
+    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError> {
+        let id = get_uuid::<Self>(&map, ID_KEY)?;
+        let name = get_string::<Self>(&map, NAME_KEY)?;
+        let legs = get_uuid_list::<Self>(&map, LEGS_KEY)?;
+        let entry_version = match map.contains_key(ENTRY_VERSION_KEY) {
+            true => get_number::<Self, u32>(&map, ENTRY_VERSION_KEY)?,
+            false => 0,
+        };
+        let created_by = match map.contains_key(CREATED_BY_KEY) {
+            true => Some(get_uuid::<Self>(&map, CREATED_BY_KEY)?),
+            false => None,
+        };
+        Ok(Self {
+            id,
+            team_data: RelayTeamData { name, legs },
+            entry_version,
+            created_by,
+        })
+    }
+
+    fn into_hashmap(self) -> HashMap<String, AttributeValue> {
+        let mut map = HashMap::new();
+        map.insert(ID_KEY.to_string(), AttributeValue::S(self.id.to_string()));
+        map.insert(NAME_KEY.to_string(), AttributeValue::S(self.team_data.name));
+        map.insert(LEGS_KEY.to_string(), uuid_list(&self.team_data.legs));
+        if self.entry_version > 0 {
+            map.insert(
+                ENTRY_VERSION_KEY.to_string(),
+                AttributeValue::N(self.entry_version.to_string()),
+            );
+        }
+        if let Some(created_by) = self.created_by {
+            map.insert(
+                CREATED_BY_KEY.to_string(),
+                AttributeValue::S(created_by.to_string()),
+            );
+        }
+        map
+    }
+}
+
+/// Teams are not tied to a competition, so any meet director can make them up
+/// A team is changed by the meet director who made it until it is entered in an event, and from then on by the
+/// meet directors of every competition it is entered at.
+impl Guarded for RelayTeam {
+    async fn authorize_create(
+        &mut self,
+        _db_client: &Client,
+        caller: &Caller,
+    ) -> Result<(), ApiError> {
+        caller.require_role(&[Role::MeetDirector], "manage relay teams")?;
+        if !caller.is_admin() {
+            self.created_by = Some(caller.user_id);
+        }
+        Ok(())
+    }
+
+    async fn authorize_modify(&self, db_client: &Client, caller: &Caller) -> Result<(), ApiError> {
+        if caller.is_admin() {
+            return Ok(());
+        }
+        caller.require_role(&[Role::MeetDirector], "manage relay teams")?;
+        let mut competition_ids: Vec<Uuid> = entered_events(db_client, self.id)
+            .await?
+            .iter()
+            .map(|event| event.event_data.competition_id)
+            .collect();
+        if competition_ids.is_empty() {
+            return match self.created_by == Some(caller.user_id) {
+                true => Ok(()),
+                false => Err(ApiError::Forbidden(format!(
+                    "Only the meet director who made relay team {} can change it",
+                    self.id
+                ))),
+            };
+        }
+        competition_ids.sort();
+        competition_ids.dedup();
+        for competition_id in competition_ids {
+            caller.require_director(db_client, competition_id).await?;
+        }
+        Ok(())
+    }
+}
+
+/// A team has one distinct athlete per leg
+fn validate_legs(legs: &[Uuid]) -> Result<(), ApiError> {
+    if legs.len() != RELAY_LEGS {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "A relay team has {RELAY_LEGS} legs, got {}",
+            legs.len()
+        )));
+    }
+    for (i, athlete_id) in legs.iter().enumerate() {
+        if legs[..i].contains(athlete_id) {
+            return Err(ApiError::UnprocessableEntity(format!(
+                "Athlete {athlete_id} runs more than one leg"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// A step for each leg, checking its athlete exists
+fn athlete_checks(legs: &[Uuid]) -> Result<Vec<TransactionStep>, ApiError> {
+    legs.iter()
+        .map(|athlete_id| {
+            exists_check::<Athlete>(
+                *athlete_id,
+                ApiError::NotFound(format!("Athlete {athlete_id} does not exist")),
+            )
+        })
+        .collect()
+}
+
+/// A step that checks the team still has the given legs, so calendar entries are never written for stale legs
+fn legs_check(team: &RelayTeam) -> Result<TransactionStep, ApiError> {
+    let check = ConditionCheck::builder()
+        .table_name(TABLE_NAME)
+        .key(ID_KEY, AttributeValue::S(team.id.to_string()))
+        .condition_expression("#legs = :legs")
+        .expression_attribute_names("#legs", LEGS_KEY)
+        .expression_attribute_values(":legs", uuid_list(&team.team_data.legs))
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok(TransactionStep::new(
+        TransactWriteItem::builder().condition_check(check).build(),
+        Some(ApiError::Conflict(format!(
+            "Relay team {} changed, please retry the request",
+            team.id
+        ))),
+    ))
+}
+
+/// A step that moves the entry version of the team on, checking it still has the given legs
+/// A team being entered in an event needs its calendar entries written for the legs it has now.
+fn entry_step(team: &RelayTeam) -> Result<TransactionStep, ApiError> {
+    let update = Update::builder()
+        .table_name(TABLE_NAME)
+        .key(ID_KEY, AttributeValue::S(team.id.to_string()))
+        .condition_expression("#legs = :legs")
+        .update_expression("SET #version = if_not_exists(#version, :zero) + :one")
+        .expression_attribute_names("#legs", LEGS_KEY)
+        .expression_attribute_names("#version", ENTRY_VERSION_KEY)
+        .expression_attribute_values(":legs", uuid_list(&team.team_data.legs))
+        .expression_attribute_values(":zero", AttributeValue::N("0".to_string()))
+        .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok(TransactionStep::new(
+        TransactWriteItem::builder().update(update).build(),
+        Some(ApiError::Conflict(format!(
+            "Relay team {} changed, please retry the request",
+            team.id
+        ))),
+    ))
+}
+
+/// Every event the team is entered in
+/// Entries are only held on the events, so this scans the events table.
+async fn entered_events(db_client: &Client, team_id: Uuid) -> Result<Vec<Event>, ApiError> {
+    let mut events = Vec::new();
+    let mut exclusive_start_key = None;
+    loop {
+        let result = db_client
+            .scan()
+            .table_name(event::TABLE_NAME)
+            .filter_expression("contains(#teams, :team)")
+            .expression_attribute_names("#teams", event::TEAM_IDS_KEY)
+            .expression_attribute_values(":team", AttributeValue::S(team_id.to_string()))
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+        events.extend(decode_rows(result.items.unwrap_or_default()));
+        exclusive_start_key = result.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            return Ok(events);
+        }
+    }
+}
+
+/// The writes that move the calendar entries of an event from the old legs of a team to the new ones
+/// Athletes who keep running for the team have their entry overwritten with their new leg.
+fn calendar_steps(
+    event_id: Uuid,
+    old: &RelayTeam,
+    new: &RelayTeam,
+) -> Result<Vec<TransactionStep>, ApiError> {
+    let mut steps = Vec::new();
+    for entry in old.calendar_entries(event_id) {
+        if !new.team_data.legs.contains(&entry.athlete_id) {
+            steps.push(transaction::delete::<AthleteEvent>(entry.key())?);
+        }
+    }
+    for entry in new.calendar_entries(event_id) {
+        steps.push(match old.team_data.legs.contains(&entry.athlete_id) {
+            true => transaction::put(entry)?,
+            false => {
+                let athlete_id = entry.athlete_id;
+                put_new(
+                    entry,
+                    ApiError::Conflict(format!(
+                        "Event {event_id} is already on the calendar of athlete {athlete_id}"
+                    )),
+                )?
+            }
+        });
+    }
+    Ok(steps)
+}
+
+/// Overwrite a team, moving the calendar entries of every event it is entered in to the new legs
+/// The write fails if the team was entered in or withdrawn from an event since the events were looked up.
+async fn replace_team(
+    db_client: &Client,
+    old: RelayTeam,
+    new: RelayTeam,
+) -> Result<RelayTeam, ApiError> {
+    let new = RelayTeam {
+        id: old.id,
+        team_data: new.team_data,
+        entry_version: old.entry_version,
+        created_by: old.created_by,
+    };
+    validate_legs(&new.team_data.legs)?;
+    let mut steps = athlete_checks(&new.team_data.legs)?;
+    if old.team_data.legs != new.team_data.legs {
+        let events = entered_events(db_client, old.id).await?;
+        // Each event takes a check that the team is still entered, and a delete and a put for every leg
+        let needed = steps.len() + 1 + events.len() * (1 + 2 * RELAY_LEGS);
+        if needed > MAX_TRANSACTION_STEPS {
+            return Err(ApiError::UnprocessableEntity(format!(
+                "Relay team {} is entered in {} events, which is too many to change its legs at once; \
+                 withdraw it from some of them first",
+                old.id,
+                events.len()
+            )));
+        }
+        for event in events {
+            steps.push(event::entered_check(
+                event.id,
+                event::TEAM_IDS_KEY,
+                old.id,
+                ApiError::Conflict(format!(
+                    "Relay team {} was withdrawn from event {}, please retry the request",
+                    old.id, event.id
+                )),
+            )?);
+            steps.extend(calendar_steps(event.id, &old, &new)?);
+        }
+    }
+    steps.push(update_existing(
+        old.id,
+        old.clone().into_hashmap(),
+        new.clone(),
+        ApiError::Conflict(format!(
+            "Relay team {} was changed or deleted since it was read, please retry the request",
+            old.id
+        )),
+    )?);
+    transact(db_client, steps).await?;
+    Ok(new)
+}
+
+/// Create a team whose legs are existing athletes
+async fn add_team(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Json(team_data): Json<RelayTeamData>,
+) -> Result<Json<RelayTeam>, ApiError> {
+    validate_legs(&team_data.legs)?;
+    let mut team = RelayTeam::from(team_data);
+    team.authorize_create(&db_client, &caller).await?;
+    let mut steps = athlete_checks(&team.team_data.legs)?;
+    steps.push(put_new(
+        team.clone(),
+        ApiError::Conflict(format!("Relay team {} already exists", team.id)),
+    )?);
+    transact(&db_client, steps).await?;
+    Ok(Json(team))
+}
+
+async fn update_team(
+    caller: Caller,
+    Path(team_id): Path<Uuid>,
+    State(db_client): State<Client>,
+    Json(team_data): Json<RelayTeamData>,
+) -> Result<Json<RelayTeam>, ApiError> {
+    let old = fetch_existing_item::<RelayTeam>(&db_client, team_id).await?;
+    let mut new = RelayTeam::from(team_data);
+    new.authorize_replace(&old, &db_client, &caller).await?;
+    replace_team(&db_client, old, new).await.map(Json)
+}
+
+async fn patch_team(
+    caller: Caller,
+    Path(team_id): Path<Uuid>,
+    State(db_client): State<Client>,
+    Json(patch): Json<Value>,
+) -> Result<Json<RelayTeam>, ApiError> {
+    let old = fetch_existing_item::<RelayTeam>(&db_client, team_id).await?;
+    let mut new = apply_patch::<RelayTeam, RelayTeamData>(old.clone(), patch)?;
+    new.authorize_replace(&old, &db_client, &caller).await?;
+    replace_team(&db_client, old, new).await.map(Json)
+}
+
+/// Delete a team that is not entered in any event
+async fn delete_team(
+    caller: Caller,
+    Path(team_id): Path<Uuid>,
+    State(db_client): State<Client>,
+) -> Result<StatusCode, ApiError> {
+    if let Some(team) = fetch_item::<RelayTeam>(&db_client, team_id).await? {
+        team.authorize_modify(&db_client, &caller).await?;
+    }
+    if let Some(event) = entered_events(&db_client, team_id).await?.first() {
+        return Err(ApiError::Conflict(format!(
+            "Relay team {team_id} is entered in event {}, withdraw it first",
+            event.id
+        )));
+    }
+    db_client
+        .delete_item()
+        .table_name(TABLE_NAME)
+        .key(ID_KEY, AttributeValue::S(team_id.to_string()))
+        .send()
+        .await?;
+    Ok(StatusCode::OK)
+}
+
+/// Enter a team in a relay event, adding the event to the calendar of every leg
+async fn enter_team(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Path((event_id, team_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<Vec<AthleteEvent>>, ApiError> {
+    let event = fetch_existing_item::<Event>(&db_client, event_id).await?;
+    caller
+        .require_director(&db_client, event.event_data.competition_id)
+        .await?;
+    if !event.event_data.is_relay() {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "Event {event_id} is not a relay, teams can only be entered in relays"
+        )));
+    }
+    let team = fetch_existing_item::<RelayTeam>(&db_client, team_id).await?;
+    let enter_team = Update::builder()
+        .table_name(event::TABLE_NAME)
+        .key(event::ID_KEY, AttributeValue::S(event_id.to_string()))
+        .condition_expression("attribute_exists(#pk) AND NOT contains(#ids, :team)")
+        .update_expression("SET #ids = list_append(if_not_exists(#ids, :empty), :teams)")
+        .expression_attribute_names("#pk", event::ID_KEY)
+        .expression_attribute_names("#ids", event::TEAM_IDS_KEY)
+        .expression_attribute_values(":empty", uuid_list(&[]))
+        .expression_attribute_values(":team", AttributeValue::S(team_id.to_string()))
+        .expression_attribute_values(":teams", uuid_list(&[team_id]))
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    let entries = team.calendar_entries(event_id);
+    let mut steps = vec![
+        entry_step(&team)?,
+        TransactionStep::new(
+            TransactWriteItem::builder().update(enter_team).build(),
+            Some(ApiError::Conflict(format!(
+                "Relay team {team_id} is already entered in event {event_id}"
+            ))),
+        ),
+    ];
+    for entry in &entries {
+        steps.push(put_new(
+            entry.clone(),
+            ApiError::Conflict(format!(
+                "Event {event_id} is already on the calendar of athlete {}",
+                entry.athlete_id
+            )),
+        )?);
+    }
+    transact(&db_client, steps).await?;
+    Ok(Json(entries))
+}
+
+/// Withdraw a team from an event, taking the event off the calendar of every leg
+/// Withdrawing a team that is not entered still succeeds
+async fn withdraw_team(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Path((event_id, team_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, ApiError> {
+    let Some(event) = fetch_item::<Event>(&db_client, event_id).await? else {
+        return Ok(StatusCode::OK);
+    };
+    caller
+        .require_director(&db_client, event.event_data.competition_id)
+        .await?;
+    let entry = event
+        .event_data
+        .team_ids
+        .iter()
+        .position(|id| *id == team_id);
+    let Some(index) = entry else {
+        return Ok(StatusCode::OK);
+    };
+    // Lists can only be edited by index, so make sure the team is still at that index
+    let leave_event = Update::builder()
+        .table_name(event::TABLE_NAME)
+        .key(event::ID_KEY, AttributeValue::S(event_id.to_string()))
+        .condition_expression(format!("#ids[{index}] = :team"))
+        .update_expression(format!("REMOVE #ids[{index}]"))
+        .expression_attribute_names("#ids", event::TEAM_IDS_KEY)
+        .expression_attribute_values(":team", AttributeValue::S(team_id.to_string()))
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    let mut steps = vec![TransactionStep::new(
+        TransactWriteItem::builder().update(leave_event).build(),
+        Some(ApiError::Conflict(format!(
+            "The teams entered in event {event_id} changed, please retry the request"
+        ))),
+    )];
+    if let Some(team) = fetch_item::<RelayTeam>(&db_client, team_id).await? {
+        steps.push(legs_check(&team)?);
+        for entry in team.calendar_entries(event_id) {
+            steps.push(transaction::delete::<AthleteEvent>(entry.key())?);
+        }
+    }
+    transact(&db_client, steps).await?;
+    Ok(StatusCode::OK)
+}
+
+/// Get the teams entered in the event, in the order they were entered
+async fn get_event_teams(
+    Path(event_id): Path<Uuid>,
+    State(db_client): State<Client>,
+) -> Result<Json<Vec<RelayTeam>>, ApiError> {
+    let event = fetch_existing_item::<Event>(&db_client, event_id).await?;
+    let team_ids = &event.event_data.team_ids;
+    let mut teams = fetch_items::<RelayTeam>(&db_client, team_ids).await?;
+    teams.sort_by_key(|team| team_ids.iter().position(|id| *id == team.id));
+    Ok(Json(teams))
+}
+
+pub fn relay_team_routes() -> axum::Router<Client> {
+    axum::Router::new()
+        .route("/", post(add_team))
+        .route("/", get(get_items::<RelayTeam, RelayTeamFilter>))
+        .route("/:team_id", get(get_item::<RelayTeam>))
+        .route("/:team_id", delete(delete_team))
+        .route("/:team_id", put(update_team))
+        .route("/:team_id", patch(patch_team))
+}
+
+/// The team entry routes nested under `/events`
+pub fn event_team_routes() -> axum::Router<Client> {
+    axum::Router::new()
+        .route("/:event_id/teams", get(get_event_teams))
+        .route(
+            "/:event_id/teams/:team_id",
+            post(enter_team).delete(withdraw_team),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn team(legs: Vec<Uuid>) -> RelayTeam {
+        RelayTeam {
+            id: Uuid::new_v4(),
+            team_data: RelayTeamData {
+                name: "Jamaica".to_string(),
+                legs,
+            },
+            entry_version: 3,
+            created_by: Some(Uuid::new_v4()),
+        }
+    }
+
+    #[test]
+    fn test_relay_team_into_hashmap() {
+        let team = team((0..4).map(|_| Uuid::new_v4()).collect());
+        let hashmap = team.clone().into_hashmap();
+        assert_eq!(RelayTeam::from_hashmap(hashmap).unwrap(), team);
+    }
+
+    #[test]
+    fn test_validate_legs() {
+        let athletes: Vec<Uuid> = (0..4).map(|_| Uuid::new_v4()).collect();
+        assert!(validate_legs(&athletes).is_ok());
+        assert!(validate_legs(&athletes[..3]).is_err());
+        let repeated = vec![athletes[0], athletes[1], athletes[2], athletes[0]];
+        assert!(validate_legs(&repeated).is_err());
+    }
+
+    #[test]
+    fn test_calendar_steps_keep_remaining_legs() {
+        let athletes: Vec<Uuid> = (0..5).map(|_| Uuid::new_v4()).collect();
+        let old = team(athletes[..4].to_vec());
+        let mut new = old.clone();
+        // The fifth athlete replaces the first, who is dropped from the calendar
+        new.team_data.legs = vec![athletes[1], athletes[2], athletes[3], athletes[4]];
+        let steps = calendar_steps(Uuid::new_v4(), &old, &new).unwrap();
+        assert_eq!(steps.len(), 5);
+        let entries = new.calendar_entries(Uuid::new_v4());
+        assert_eq!(entries[0].relay.unwrap().leg, 1);
+        assert_eq!(entries[3].athlete_id, athletes[4]);
+    }
+}
@@ -0,0 +1,1066 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+use super::athlete::Athlete;
+use super::attempt::{
+    attempts_into_attribute, best_attempt, countback, get_attempts, validate_series, Attempt,
+    BarOutcome, SeriesKind,
+};
+use super::discipline::Discipline;
+use super::error::ApiError;
+use super::event::{self, Event};
+use super::filter::{Expression, IndexQuery, ListFilter, ListQuery};
+use super::permission::{Caller, Guarded};
+use super::personal_best;
+use super::relay_team::RelayTeam;
+use super::result_entry;
+use super::round::Round;
+use super::transaction::{self, exists_check, put_new, transact, update_existing, TransactionStep};
+use super::utils::{apply_patch, fetch_existing_item, fetch_item, get_item, get_items};
+use super::utils::{get_number, get_string, get_uuid, query_all, Item, ItemDecodeError};
+use aws_sdk_dynamodb::{
+    self,
+    types::{AttributeValue, Put, TransactWriteItem, Update},
+    Client,
+};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get, patch, post, put};
+use axum::Json;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use uuid::Uuid;
+
+pub const TABLE_NAME: &str = "results";
+pub const ID_KEY: &str = "id";
+pub const EVENT_ID_KEY: &str = "event_id";
+pub const ATHLETE_ID_KEY: &str = "athlete_id";
+const TEAM_ID_KEY: &str = "team_id";
+const ROUND_ID_KEY: &str = "round_id";
+const PLACE_KEY: &str = "place";
+const MARK_TYPE_KEY: &str = "mark_type";
+const MARK_KEY: &str = "mark";
+const WIND_KEY: &str = "wind";
+const ATTEMPTS_KEY: &str = "attempts";
+/// The strongest tailwind, in m/s, for a mark to count for records
+pub const MAX_LEGAL_WIND: f64 = 2.0;
+/// Results of an event
+pub const EVENT_INDEX: &str = "event_id-index";
+/// Results of an athlete
+pub const ATHLETE_INDEX: &str = "athlete_id-index";
+
+/// The performance of an athlete in an event
+/// Running events are timed, field events are measured and multi-events are scored.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Mark {
+    Time { seconds: f64 },
+    Distance { meters: f64 },
+    Height { meters: f64 },
+    Points { points: u32 },
+}
+
+/// The kind of mark a discipline is decided by
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkType {
+    Time,
+    Distance,
+    Height,
+    Points,
+}
+
+impl MarkType {
+    /// The name the mark type is stored under, the same as its JSON name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Time => "time",
+            Self::Distance => "distance",
+            Self::Height => "height",
+            Self::Points => "points",
+        }
+    }
+}
+
+impl Mark {
+    pub fn mark_type(&self) -> MarkType {
+        match self {
+            Self::Time { .. } => MarkType::Time,
+            Self::Distance { .. } => MarkType::Distance,
+            Self::Height { .. } => MarkType::Height,
+            Self::Points { .. } => MarkType::Points,
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        self.mark_type().as_str()
+    }
+
+    /// The number stored for the mark, as a DynamoDB number
+    fn value(&self) -> String {
+        match self {
+            Self::Time { seconds } => seconds.to_string(),
+            Self::Distance { meters } | Self::Height { meters } => meters.to_string(),
+            Self::Points { points } => points.to_string(),
+        }
+    }
+
+    /// Whether this mark is better than `other`
+    /// Shorter times are better, every other mark is better when larger. Marks of different types never beat each other.
+    pub fn beats(&self, other: &Mark) -> bool {
+        match (self, other) {
+            (Self::Time { seconds: a }, Self::Time { seconds: b }) => a < b,
+            (Self::Distance { meters: a }, Self::Distance { meters: b })
+            | (Self::Height { meters: a }, Self::Height { meters: b }) => a > b,
+            (Self::Points { points: a }, Self::Points { points: b }) => a > b,
+            _ => false,
+        }
+    }
+
+    /// Store the mark as a type attribute and a number attribute
+    pub fn insert_into(
+        &self,
+        map: &mut HashMap<String, AttributeValue>,
+        type_attribute: &str,
+        value_attribute: &str,
+    ) {
+        map.insert(
+            type_attribute.to_string(),
+            AttributeValue::S(self.type_name().to_string()),
+        );
+        map.insert(value_attribute.to_string(), AttributeValue::N(self.value()));
+    }
+
+    /// Read a mark stored by `insert_into` from a row of `T`
+    pub fn from_map<T: Item>(
+        map: &HashMap<String, AttributeValue>,
+        type_attribute: &str,
+        value_attribute: &str,
+    ) -> Result<Self, ItemDecodeError> {
+        Ok(match get_string::<T>(map, type_attribute)?.as_str() {
+            "time" => Self::Time {
+                seconds: get_number::<T, f64>(map, value_attribute)?,
+            },
+            "distance" => Self::Distance {
+                meters: get_number::<T, f64>(map, value_attribute)?,
+            },
+            "height" => Self::Height {
+                meters: get_number::<T, f64>(map, value_attribute)?,
+            },
+            "points" => Self::Points {
+                points: get_number::<T, u32>(map, value_attribute)?,
+            },
+            other => {
+                return Err(ItemDecodeError::invalid::<T>(
+                    map,
+                    type_attribute,
+                    format!("unknown mark type {other}"),
+                ))
+            }
+        })
+    }
+
+    /// Times, distances and heights must be positive; points can be zero
+    fn validate(&self) -> Result<(), ApiError> {
+        let measured = match self {
+            Self::Time { seconds } => *seconds,
+            Self::Distance { meters } | Self::Height { meters } => *meters,
+            Self::Points { .. } => return Ok(()),
+        };
+        if measured.is_finite() && measured > 0.0 {
+            Ok(())
+        } else {
+            Err(ApiError::UnprocessableEntity(format!(
+                "A {} mark must be a positive number, got {measured}",
+                self.type_name()
+            )))
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct EventResult {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub result_data: ResultData,
+}
+
+/// How a result is sent to clients, with the wind flag worked out from the reading
+#[derive(Serialize)]
+struct EventResultView<'a> {
+    id: Uuid,
+    #[serde(flatten)]
+    result_data: &'a ResultData,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wind_aided: Option<bool>,
+}
+
+impl Serialize for EventResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        EventResultView {
+            id: self.id,
+            result_data: &self.result_data,
+            wind_aided: self.result_data.wind_aided(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ResultData {
+    pub event_id: Uuid,
+    /// Exactly one of `athlete_id` and `team_id` is set; relay events have team results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub athlete_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team_id: Option<Uuid>,
+    /// The round the result was set in, for events held in rounds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub round_id: Option<Uuid>,
+    /// The finishing place of the athlete, starting at 1
+    /// In a round this is the place within the athlete's heat.
+    pub place: u32,
+    /// Only a field result can be without a mark, until it has a fair attempt ("NM")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mark: Option<Mark>,
+    /// The wind reading in m/s, positive for a tailwind, for disciplines where wind applies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wind: Option<f64>,
+    /// The attempt series of a field result, which sets its mark and wind when present
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attempts: Vec<Attempt>,
+}
+
+impl ResultData {
+    /// Whether the mark was helped by a wind over the legal limit, or `None` without a wind reading
+    /// Wind-aided marks are still results, but they do not count towards bests.
+    pub fn wind_aided(&self) -> Option<bool> {
+        self.wind.map(|wind| wind > MAX_LEGAL_WIND)
+    }
+}
+
+impl From<ResultData> for EventResult {
+    fn from(result_data: ResultData) -> Self {
+        let id = Uuid::new_v4();
+        Self { id, result_data }
+    }
+}
+
+/// The query-string filters accepted by `GET /results`
+/// Filtering by `event_id` reads the event index instead of scanning the table.
+#[derive(Debug, Default, Deserialize)]
+struct ResultFilter {
+    event_id: Option<Uuid>,
+    athlete_id: Option<Uuid>,
+    team_id: Option<Uuid>,
+    round_id: Option<Uuid>,
+}
+
+impl ListFilter<EventResult> for ResultFilter {
+    fn list_query(&self) -> ListQuery {
+        let mut filter = Expression::new("f");
+        if let Some(athlete_id) = self.athlete_id {
+            filter.equals(ATHLETE_ID_KEY, AttributeValue::S(athlete_id.to_string()));
+        }
+        if let Some(team_id) = self.team_id {
+            filter.equals(TEAM_ID_KEY, AttributeValue::S(team_id.to_string()));
+        }
+        if let Some(round_id) = self.round_id {
+            filter.equals(ROUND_ID_KEY, AttributeValue::S(round_id.to_string()));
+        }
+        let Some(event_id) = self.event_id else {
+            return ListQuery::scan(filter);
+        };
+        let mut key_condition = Expression::new("k");
+        key_condition.equals(EVENT_ID_KEY, AttributeValue::S(event_id.to_string()));
+        ListQuery {
+            index: Some(IndexQuery {
+                index_name: EVENT_INDEX,
+                key_condition,
+                ascending: true,
+            }),
+            filter,
+        }
+    }
+}
+
+impl Item for EventResult {
+    fn table_name() -> &'static str {
+        TABLE_NAME
+    }
+    fn partition_key_name() -> &'static str {
+        ID_KEY
+    }
+
+    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError> {
+        let id = get_uuid::<Self>(&map, ID_KEY)?;
+        let event_id = get_uuid::<Self>(&map, EVENT_ID_KEY)?;
+        let athlete_id = match map.contains_key(ATHLETE_ID_KEY) {
+            true => Some(get_uuid::<Self>(&map, ATHLETE_ID_KEY)?),
+            false => None,
+        };
+        let team_id = match map.contains_key(TEAM_ID_KEY) {
+            true => Some(get_uuid::<Self>(&map, TEAM_ID_KEY)?),
+            false => None,
+        };
+        let round_id = match map.contains_key(ROUND_ID_KEY) {
+            true => Some(get_uuid::<Self>(&map, ROUND_ID_KEY)?),
+            false => None,
+        };
+        let place = get_number::<Self, u32>(&map, PLACE_KEY)?;
+        let mark = match map.contains_key(MARK_TYPE_KEY) {
+            true => Some(Mark::from_map::<Self>(&map, MARK_TYPE_KEY, MARK_KEY)?),
+            false => None,
+        };
+        let wind = match map.contains_key(WIND_KEY) {
+            true => Some(get_number::<Self, f64>(&map, WIND_KEY)?),
+            false => None,
+        };
+        let attempts = match map.contains_key(ATTEMPTS_KEY) {
+            true => get_attempts::<Self>(&map, ATTEMPTS_KEY)?,
+            false => Vec::new(),
+        };
+        Ok(Self {
+            id,
+            result_data: ResultData {
+                event_id,
+                athlete_id,
+                team_id,
+                round_id,
+                place,
+                mark,
+                wind,
+                attempts,
+            },
+        })
+    }
+
+    fn into_hashmap(self) -> HashMap<String, AttributeValue> {
+        let mut map = HashMap::new();
+        map.insert(ID_KEY.to_string(), AttributeValue::S(self.id.to_string()));
+        map.insert(
+            EVENT_ID_KEY.to_string(),
+            AttributeValue::S(self.result_data.event_id.to_string()),
+        );
+        if let Some(athlete_id) = self.result_data.athlete_id {
+            map.insert(
+                ATHLETE_ID_KEY.to_string(),
+                AttributeValue::S(athlete_id.to_string()),
+            );
+        }
+        if let Some(team_id) = self.result_data.team_id {
+            map.insert(
+                TEAM_ID_KEY.to_string(),
+                AttributeValue::S(team_id.to_string()),
+            );
+        }
+        if let Some(round_id) = self.result_data.round_id {
+            map.insert(
+                ROUND_ID_KEY.to_string(),
+                AttributeValue::S(round_id.to_string()),
+            );
+        }
+        map.insert(
+            PLACE_KEY.to_string(),
+            AttributeValue::N(self.result_data.place.to_string()),
+        );
+        if let Some(mark) = self.result_data.mark {
+            mark.insert_into(&mut map, MARK_TYPE_KEY, MARK_KEY);
+        }
+        if let Some(wind) = self.result_data.wind {
+            map.insert(WIND_KEY.to_string(), AttributeValue::N(wind.to_string()));
+        }
+        if !self.result_data.attempts.is_empty() {
+            map.insert(
+                ATTEMPTS_KEY.to_string(),
+                attempts_into_attribute(&self.result_data.attempts),
+            );
+        }
+        map
+    }
+}
+
+/// Results are recorded by the meet director of the competition the event is held at
+impl Guarded for EventResult {
+    async fn authorize_create(
+        &mut self,
+        db_client: &Client,
+        caller: &Caller,
+    ) -> Result<(), ApiError> {
+        caller
+            .require_event_director(db_client, self.result_data.event_id)
+            .await
+    }
+
+    async fn authorize_modify(&self, db_client: &Client, caller: &Caller) -> Result<(), ApiError> {
+        caller
+            .require_event_director(db_client, self.result_data.event_id)
+            .await
+    }
+}
+
+/// Set the mark and wind of a field result from its attempts
+/// A result without attempts keeps the mark and wind it was given.
+fn score_attempts(discipline: Discipline, result_data: &mut ResultData) -> Result<(), ApiError> {
+    if result_data.attempts.is_empty() {
+        return Ok(());
+    }
+    validate_series(discipline, &result_data.attempts)?;
+    let best = best_attempt(&result_data.attempts);
+    result_data.mark = best.map(|(mark, _)| mark);
+    result_data.wind = best.and_then(|(_, wind)| wind);
+    Ok(())
+}
+
+/// Check a result and build the writes that make sure its athlete or team is entered in its event
+/// The mark of a field result is worked out from its attempts first.
+/// The entry list is read first for a clear error, then checked again in the transaction in case it changed.
+async fn entry_checks(
+    db_client: &Client,
+    result_data: &mut ResultData,
+) -> Result<Vec<TransactionStep>, ApiError> {
+    if result_data.place == 0 {
+        return Err(ApiError::UnprocessableEntity(
+            "place starts at 1".to_string(),
+        ));
+    }
+    let event_id = result_data.event_id;
+    let event = fetch_item::<Event>(db_client, event_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Event {event_id} does not exist")))?;
+    let discipline = event.event_data.discipline;
+    score_attempts(discipline, result_data)?;
+    match &result_data.mark {
+        Some(mark) => {
+            mark.validate()?;
+            if !discipline.accepts(mark) {
+                return Err(ApiError::UnprocessableEntity(format!(
+                    "Results in the {} are recorded as a {} mark",
+                    discipline.name(),
+                    discipline.mark_type().as_str()
+                )));
+            }
+        }
+        None if SeriesKind::of(discipline).is_none() => {
+            return Err(ApiError::UnprocessableEntity(format!(
+                "A result in the {} needs a mark",
+                discipline.name()
+            )))
+        }
+        None => {}
+    }
+    if let Some(wind) = result_data.wind {
+        if !discipline.wind_applies() {
+            return Err(ApiError::UnprocessableEntity(format!(
+                "No wind reading is taken in the {}",
+                discipline.name()
+            )));
+        }
+        if !wind.is_finite() {
+            return Err(ApiError::UnprocessableEntity(format!(
+                "The wind must be a number of m/s, got {wind}"
+            )));
+        }
+    }
+    let athlete_id = match (result_data.athlete_id, result_data.team_id) {
+        (Some(athlete_id), None) => athlete_id,
+        (None, Some(team_id)) => return team_checks(&event, team_id, result_data.round_id),
+        _ => {
+            return Err(ApiError::UnprocessableEntity(
+                "A result has either an athlete_id or a team_id".to_string(),
+            ))
+        }
+    };
+    if !event.event_data.athlete_ids.contains(&athlete_id) {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "Athlete {athlete_id} is not entered in event {event_id}"
+        )));
+    }
+    let mut steps = Vec::new();
+    if let Some(round_id) = result_data.round_id {
+        let round = fetch_item::<Round>(db_client, round_id)
+            .await?
+            .ok_or_else(|| ApiError::NotFound(format!("Round {round_id} does not exist")))?;
+        if round.round_data.event_id != event_id || round.heat_of(athlete_id).is_none() {
+            return Err(ApiError::UnprocessableEntity(format!(
+                "Athlete {athlete_id} is not in a heat of round {round_id} of event {event_id}"
+            )));
+        }
+        steps.push(exists_check::<Round>(
+            round_id,
+            ApiError::NotFound(format!("Round {round_id} does not exist")),
+        )?);
+    }
+    steps.extend([
+        exists_check::<Athlete>(
+            athlete_id,
+            ApiError::NotFound(format!("Athlete {athlete_id} does not exist")),
+        )?,
+        event::entered_check(
+            event_id,
+            event::ATHLETE_IDS_KEY,
+            athlete_id,
+            ApiError::Conflict(format!(
+                "Athlete {athlete_id} was removed from event {event_id}, please retry the request"
+            )),
+        )?,
+    ]);
+    Ok(steps)
+}
+
+/// The checks of a relay result, which belongs to a team entered in the event
+fn team_checks(
+    event: &Event,
+    team_id: Uuid,
+    round_id: Option<Uuid>,
+) -> Result<Vec<TransactionStep>, ApiError> {
+    let event_id = event.id;
+    if round_id.is_some() {
+        return Err(ApiError::UnprocessableEntity(
+            "Relays are not held in rounds yet".to_string(),
+        ));
+    }
+    if !event.event_data.team_ids.contains(&team_id) {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "Relay team {team_id} is not entered in event {event_id}"
+        )));
+    }
+    Ok(vec![
+        exists_check::<RelayTeam>(
+            team_id,
+            ApiError::NotFound(format!("Relay team {team_id} does not exist")),
+        )?,
+        event::entered_check(
+            event_id,
+            event::TEAM_IDS_KEY,
+            team_id,
+            ApiError::Conflict(format!(
+                "Relay team {team_id} was withdrawn from event {event_id}, please retry the request"
+            )),
+        )?,
+    ])
+}
+
+/// Overwrite a result, keeping its id
+async fn replace_result(
+    db_client: &Client,
+    old: EventResult,
+    new: EventResult,
+) -> Result<EventResult, ApiError> {
+    let new = EventResult {
+        id: old.id,
+        result_data: new.result_data,
+    };
+    let mut new = new;
+    let mut steps = entry_checks(db_client, &mut new.result_data).await?;
+    if result_entry::entry(&old.result_data) != result_entry::entry(&new.result_data) {
+        steps.push(result_entry::release(&old)?);
+        steps.push(result_entry::reserve(&new)?);
+    }
+    steps.push(update_existing(
+        old.id,
+        old.clone().into_hashmap(),
+        new.clone(),
+        ApiError::Conflict(format!(
+            "Result {} was changed or deleted since it was read, please retry the request",
+            old.id
+        )),
+    )?);
+    transact(db_client, steps).await?;
+    // Relay results do not count towards the bests of the legs
+    let athlete_ids: Vec<Uuid> = [old.result_data.athlete_id, new.result_data.athlete_id]
+        .into_iter()
+        .flatten()
+        .collect();
+    personal_best::refresh(db_client, &athlete_ids).await;
+    Ok(new)
+}
+
+/// Record the result of an athlete entered in the event
+/// An athlete or team has at most one result in an event, or in each round of it, so a second one is a 409.
+async fn add_result(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Json(result_data): Json<ResultData>,
+) -> Result<Json<EventResult>, ApiError> {
+    let mut result = EventResult::from(result_data);
+    result.authorize_create(&db_client, &caller).await?;
+    let mut steps = entry_checks(&db_client, &mut result.result_data).await?;
+    steps.push(result_entry::reserve(&result)?);
+    steps.push(put_new(
+        result.clone(),
+        ApiError::Conflict(format!("Result {} already exists", result.id)),
+    )?);
+    transact(&db_client, steps).await?;
+    if let Some(athlete_id) = result.result_data.athlete_id {
+        personal_best::refresh(&db_client, &[athlete_id]).await;
+    }
+    Ok(Json(result))
+}
+
+async fn update_result(
+    caller: Caller,
+    Path(result_id): Path<Uuid>,
+    State(db_client): State<Client>,
+    Json(result_data): Json<ResultData>,
+) -> Result<Json<EventResult>, ApiError> {
+    let old = fetch_existing_item::<EventResult>(&db_client, result_id).await?;
+    let mut new = EventResult::from(result_data);
+    new.authorize_replace(&old, &db_client, &caller).await?;
+    replace_result(&db_client, old, new).await.map(Json)
+}
+
+async fn patch_result(
+    caller: Caller,
+    Path(result_id): Path<Uuid>,
+    State(db_client): State<Client>,
+    Json(patch): Json<Value>,
+) -> Result<Json<EventResult>, ApiError> {
+    let old = fetch_existing_item::<EventResult>(&db_client, result_id).await?;
+    let mut new = apply_patch::<EventResult, ResultData>(old.clone(), patch)?;
+    new.authorize_replace(&old, &db_client, &caller).await?;
+    replace_result(&db_client, old, new).await.map(Json)
+}
+
+/// Delete a result, freeing its entry, and recompute the bests of its athlete
+async fn delete_result(
+    caller: Caller,
+    Path(result_id): Path<Uuid>,
+    State(db_client): State<Client>,
+) -> Result<StatusCode, ApiError> {
+    let Some(result) = fetch_item::<EventResult>(&db_client, result_id).await? else {
+        return Ok(StatusCode::OK);
+    };
+    result.authorize_modify(&db_client, &caller).await?;
+    transact(
+        &db_client,
+        vec![
+            transaction::delete::<EventResult>(HashMap::from([(
+                ID_KEY.to_string(),
+                AttributeValue::S(result_id.to_string()),
+            )]))?,
+            result_entry::release(&result)?,
+        ],
+    )
+    .await?;
+    if let Some(athlete_id) = result.result_data.athlete_id {
+        personal_best::refresh(&db_client, &[athlete_id]).await;
+    }
+    Ok(StatusCode::OK)
+}
+
+/// The results whose place differs from their rank in the standings, with the place they move to
+/// In a round the place is the place within the heat, so every heat of `round` is ranked on its own; results set
+/// outside of rounds are ranked across the whole event.
+fn moved_places(results: Vec<EventResult>, round: Option<&Round>) -> Vec<(EventResult, u32)> {
+    let mut heats: BTreeMap<Option<u32>, Vec<EventResult>> = BTreeMap::new();
+    for result in results {
+        let heat = round
+            .zip(result.result_data.athlete_id)
+            .and_then(|(round, athlete_id)| round.heat_of(athlete_id));
+        heats.entry(heat).or_default().push(result);
+    }
+    heats
+        .into_values()
+        .flat_map(rank_field_results)
+        .filter(|standing| standing.rank != standing.result.result_data.place)
+        .map(|standing| (standing.result, standing.rank))
+        .collect()
+}
+
+/// A step that moves another result of the round to its new place in the standings
+/// The step fails if the result was changed since the standings were worked out.
+fn place_step(result: &EventResult, place: u32) -> Result<TransactionStep, ApiError> {
+    let recorded = result.result_data.attempts.len();
+    let update = Update::builder()
+        .table_name(TABLE_NAME)
+        .key(ID_KEY, AttributeValue::S(result.id.to_string()))
+        .update_expression("SET #place = :place")
+        .expression_attribute_names("#place", PLACE_KEY)
+        .expression_attribute_names("#attempts", ATTEMPTS_KEY)
+        .expression_attribute_values(":place", AttributeValue::N(place.to_string()))
+        .expression_attribute_values(
+            ":stored",
+            AttributeValue::N(result.result_data.place.to_string()),
+        );
+    let update = match recorded {
+        0 => update.condition_expression("#place = :stored AND attribute_not_exists(#attempts)"),
+        _ => update
+            .condition_expression("#place = :stored AND size(#attempts) = :recorded")
+            .expression_attribute_values(":recorded", AttributeValue::N(recorded.to_string())),
+    }
+    .build()
+    .map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok(TransactionStep::new(
+        TransactWriteItem::builder().update(update).build(),
+        Some(ApiError::Conflict(format!(
+            "The standings of event {} changed, please retry the request",
+            result.result_data.event_id
+        ))),
+    ))
+}
+
+/// Append an attempt to the series of a field result, as the competition goes on
+/// The result is only written if no other attempt was appended since it was read. Places in its heat are
+/// worked out again from the standings, so the result and any result it passes move in the same transaction.
+async fn add_attempt(
+    caller: Caller,
+    Path(result_id): Path<Uuid>,
+    State(db_client): State<Client>,
+    Json(attempt): Json<Attempt>,
+) -> Result<Json<EventResult>, ApiError> {
+    let mut result = fetch_existing_item::<EventResult>(&db_client, result_id).await?;
+    result.authorize_modify(&db_client, &caller).await?;
+    if result.result_data.attempts.is_empty() && result.result_data.mark.is_some() {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "Result {result_id} was entered as a single mark, replace it with its attempts before adding more"
+        )));
+    }
+    let recorded = result.result_data.attempts.len();
+    result.result_data.attempts.push(attempt);
+    let mut steps = entry_checks(&db_client, &mut result.result_data).await?;
+    let event_id = result.result_data.event_id;
+    let round = match result.result_data.round_id {
+        Some(round_id) => Some(fetch_existing_item::<Round>(&db_client, round_id).await?),
+        None => None,
+    };
+    let mut round_results: Vec<EventResult> =
+        query_all::<EventResult>(&db_client, Some(EVENT_INDEX), EVENT_ID_KEY, event_id)
+            .await?
+            .into_iter()
+            .filter(|other| {
+                other.id != result_id && other.result_data.round_id == result.result_data.round_id
+            })
+            .collect();
+    round_results.push(result.clone());
+    for (moved, place) in moved_places(round_results, round.as_ref()) {
+        match moved.id == result_id {
+            true => result.result_data.place = place,
+            false => steps.push(place_step(&moved, place)?),
+        }
+    }
+    let put = Put::builder()
+        .table_name(TABLE_NAME)
+        .set_item(Some(result.clone().into_hashmap()))
+        .expression_attribute_names("#attempts", ATTEMPTS_KEY);
+    let put = match recorded {
+        0 => put
+            .condition_expression("attribute_exists(#pk) AND attribute_not_exists(#attempts)")
+            .expression_attribute_names("#pk", ID_KEY),
+        _ => put
+            .condition_expression("size(#attempts) = :recorded")
+            .expression_attribute_values(":recorded", AttributeValue::N(recorded.to_string())),
+    }
+    .build()
+    .map_err(|err| ApiError::Internal(err.to_string()))?;
+    steps.push(TransactionStep::new(
+        TransactWriteItem::builder().put(put).build(),
+        Some(ApiError::Conflict(format!(
+            "The attempts of result {result_id} changed, please retry the request"
+        ))),
+    ));
+    transact(&db_client, steps).await?;
+    if let Some(athlete_id) = result.result_data.athlete_id {
+        personal_best::refresh(&db_client, &[athlete_id]).await;
+    }
+    Ok(Json(result))
+}
+
+/// The query-string filters accepted by `GET /events/:event_id/field-standings`
+#[derive(Debug, Default, Deserialize)]
+struct StandingsFilter {
+    /// Rank the results of a round, instead of those set outside of rounds
+    round_id: Option<Uuid>,
+}
+
+/// A field result and its place in the standings
+#[derive(Debug, Serialize)]
+struct FieldStanding {
+    rank: u32,
+    #[serde(flatten)]
+    result: EventResult,
+}
+
+/// The attempts a result is ranked by
+/// A result entered without attempts counts as a series of its one mark.
+fn ranked_series(result_data: &ResultData) -> Vec<Attempt> {
+    if !result_data.attempts.is_empty() {
+        return result_data.attempts.clone();
+    }
+    match result_data.mark {
+        Some(Mark::Distance { meters }) => vec![Attempt::Mark {
+            meters,
+            wind: result_data.wind,
+        }],
+        Some(Mark::Height { meters }) => vec![Attempt::Bar {
+            height: meters,
+            outcome: BarOutcome::Cleared,
+        }],
+        _ => Vec::new(),
+    }
+}
+
+/// Rank field results best first, with countback deciding ties and results still tied sharing a rank
+fn rank_field_results(results: Vec<EventResult>) -> Vec<FieldStanding> {
+    let mut ranked: Vec<(Vec<Attempt>, EventResult)> = results
+        .into_iter()
+        .map(|result| (ranked_series(&result.result_data), result))
+        .collect();
+    ranked.sort_by(|(a, _), (b, _)| countback(a, b));
+    let mut standings: Vec<FieldStanding> = Vec::with_capacity(ranked.len());
+    for (i, (series, result)) in ranked.iter().enumerate() {
+        let rank = match i {
+            0 => 1,
+            _ if countback(&ranked[i - 1].0, series) == Ordering::Equal => standings[i - 1].rank,
+            _ => i as u32 + 1,
+        };
+        standings.push(FieldStanding {
+            rank,
+            result: result.clone(),
+        });
+    }
+    standings
+}
+
+/// Get the live standings of a field event, worked out from the attempts of every result
+async fn get_field_standings(
+    Path(event_id): Path<Uuid>,
+    Query(filter): Query<StandingsFilter>,
+    State(db_client): State<Client>,
+) -> Result<Json<Vec<FieldStanding>>, ApiError> {
+    let event = fetch_existing_item::<Event>(&db_client, event_id).await?;
+    let discipline = event.event_data.discipline;
+    if SeriesKind::of(discipline).is_none() {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "The {} is not a field event",
+            discipline.name()
+        )));
+    }
+    let results: Vec<EventResult> =
+        query_all::<EventResult>(&db_client, Some(EVENT_INDEX), EVENT_ID_KEY, event_id)
+            .await?
+            .into_iter()
+            .filter(|result| result.result_data.round_id == filter.round_id)
+            .collect();
+    Ok(Json(rank_field_results(results)))
+}
+
+/// Get every result of the event, ordered by place
+async fn get_event_results(
+    Path(event_id): Path<Uuid>,
+    State(db_client): State<Client>,
+) -> Result<Json<Vec<EventResult>>, ApiError> {
+    fetch_existing_item::<Event>(&db_client, event_id).await?;
+    let mut results =
+        query_all::<EventResult>(&db_client, Some(EVENT_INDEX), EVENT_ID_KEY, event_id).await?;
+    results.sort_by_key(|result| result.result_data.place);
+    Ok(Json(results))
+}
+
+pub fn result_routes() -> axum::Router<Client> {
+    axum::Router::new()
+        .route("/", post(add_result))
+        .route("/", get(get_items::<EventResult, ResultFilter>))
+        .route("/:result_id", get(get_item::<EventResult>))
+        .route("/:result_id", delete(delete_result))
+        .route("/:result_id", put(update_result))
+        .route("/:result_id", patch(patch_result))
+        .route("/:result_id/attempts", post(add_attempt))
+}
+
+/// The result routes nested under `/events`
+pub fn event_result_routes() -> axum::Router<Client> {
+    axum::Router::new()
+        .route("/:event_id/results", get(get_event_results))
+        .route("/:event_id/field-standings", get(get_field_standings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::round::{Assignment, Heat, RoundData, RoundType};
+
+    fn result(mark: Mark) -> EventResult {
+        EventResult {
+            id: Uuid::new_v4(),
+            result_data: ResultData {
+                event_id: Uuid::new_v4(),
+                athlete_id: Some(Uuid::new_v4()),
+                team_id: None,
+                round_id: Some(Uuid::new_v4()),
+                place: 3,
+                mark: Some(mark),
+                wind: Some(-0.4),
+                attempts: vec![],
+            },
+        }
+    }
+
+    #[test]
+    fn test_result_into_hashmap() {
+        for mark in [
+            Mark::Time { seconds: 9.58 },
+            Mark::Distance { meters: 8.95 },
+            Mark::Height { meters: 2.45 },
+            Mark::Points { points: 9126 },
+        ] {
+            let result = result(mark);
+            let map = result.clone().into_hashmap();
+            assert_eq!(EventResult::from_hashmap(map).unwrap(), result);
+        }
+
+        // A field result without a fair attempt has attempts but no mark
+        let mut no_mark = result(Mark::Distance { meters: 8.95 });
+        no_mark.result_data.mark = None;
+        no_mark.result_data.wind = None;
+        no_mark.result_data.attempts = vec![Attempt::Foul, Attempt::Pass];
+        let map = no_mark.clone().into_hashmap();
+        assert_eq!(EventResult::from_hashmap(map).unwrap(), no_mark);
+    }
+
+    #[test]
+    fn test_score_attempts() {
+        let mut result = result(Mark::Distance { meters: 1.0 });
+        result.result_data.attempts = vec![
+            Attempt::Mark {
+                meters: 8.31,
+                wind: Some(2.5),
+            },
+            Attempt::Foul,
+        ];
+        score_attempts(Discipline::LongJump, &mut result.result_data).unwrap();
+        assert_eq!(
+            result.result_data.mark,
+            Some(Mark::Distance { meters: 8.31 })
+        );
+        assert_eq!(result.result_data.wind_aided(), Some(true));
+    }
+
+    #[test]
+    fn test_rank_field_results() {
+        let with_attempts = |marks: &[f64]| {
+            let mut result = result(Mark::Distance { meters: 1.0 });
+            result.result_data.attempts = marks
+                .iter()
+                .map(|meters| Attempt::Mark {
+                    meters: *meters,
+                    wind: None,
+                })
+                .collect();
+            result
+        };
+        let second = with_attempts(&[8.1, 7.9]);
+        let first = with_attempts(&[8.1, 8.0]);
+        let tied = with_attempts(&[8.0, 8.1]);
+        let mut no_mark = with_attempts(&[]);
+        no_mark.result_data.mark = None;
+        let standings = rank_field_results(vec![second.clone(), no_mark, first.clone(), tied]);
+        let ranks: Vec<u32> = standings.iter().map(|standing| standing.rank).collect();
+        assert_eq!(ranks, vec![1, 1, 3, 4]);
+        assert_eq!(standings[2].result, second);
+
+        // Places follow the standings, and results already in their place are left alone
+        let mut placed = second.clone();
+        placed.result_data.place = 2;
+        let moved = moved_places(vec![placed, first.clone()], None);
+        assert_eq!(moved.len(), 1);
+        assert_eq!(moved[0].0.id, first.id);
+        assert_eq!(moved[0].1, 1);
+    }
+
+    #[test]
+    fn test_moved_places_restart_in_every_heat() {
+        let with_mark = |meters: f64| {
+            let mut result = result(Mark::Distance { meters: 1.0 });
+            result.result_data.attempts = vec![Attempt::Mark { meters, wind: None }];
+            result
+        };
+        let results = vec![
+            with_mark(8.0),
+            with_mark(7.5),
+            with_mark(7.9),
+            with_mark(7.0),
+        ];
+        let heat = |number: u32, results: &[EventResult]| Heat {
+            number,
+            assignments: results
+                .iter()
+                .zip(1..)
+                .map(|(result, position)| Assignment {
+                    athlete_id: result.result_data.athlete_id.unwrap(),
+                    position,
+                })
+                .collect(),
+        };
+        let round = Round {
+            id: Uuid::new_v4(),
+            round_data: RoundData {
+                event_id: Uuid::new_v4(),
+                round_type: RoundType::Heats,
+                number: 1,
+                heats: vec![heat(1, &results[..2]), heat(2, &results[2..])],
+                advancement: None,
+            },
+        };
+        let mut places: Vec<(Uuid, u32)> = moved_places(results.clone(), Some(&round))
+            .into_iter()
+            .map(|(result, place)| (result.id, place))
+            .collect();
+        places.sort_by_key(|(id, _)| results.iter().position(|result| result.id == *id));
+        let expected: Vec<(Uuid, u32)> = results
+            .iter()
+            .map(|result| result.id)
+            .zip([1, 2, 1, 2])
+            .collect();
+        assert_eq!(places, expected);
+
+        // Outside of rounds the whole event is ranked together
+        let places: Vec<u32> = moved_places(results, None)
+            .into_iter()
+            .map(|(_, place)| place)
+            .collect();
+        assert_eq!(places, vec![1, 2, 4]);
+    }
+
+    #[test]
+    fn test_mark_json() {
+        let mark: Mark =
+            serde_json::from_value(serde_json::json!({"type": "time", "seconds": 19.19})).unwrap();
+        assert_eq!(mark, Mark::Time { seconds: 19.19 });
+        assert!(Mark::Distance { meters: -1.0 }.validate().is_err());
+        assert!(Mark::Points { points: 0 }.validate().is_ok());
+    }
+
+    #[test]
+    fn test_wind_aided_flag() {
+        let mut result = result(Mark::Distance { meters: 8.95 });
+        result.result_data.wind = Some(2.0);
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["wind"], 2.0);
+        assert_eq!(json["wind_aided"], false);
+        // The flag is worked out from the reading, so it is not read back from a request
+        let parsed: EventResult = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, result);
+
+        result.result_data.wind = Some(2.1);
+        assert_eq!(serde_json::to_value(&result).unwrap()["wind_aided"], true);
+        result.result_data.wind = None;
+        assert!(serde_json::to_value(&result)
+            .unwrap()
+            .get("wind_aided")
+            .is_none());
+    }
+
+    #[test]
+    fn test_result_from_hashmap_unknown_mark_type() {
+        let mut map = result(Mark::Time { seconds: 10.0 }).into_hashmap();
+        map.insert(
+            MARK_TYPE_KEY.to_string(),
+            AttributeValue::S("speed".to_string()),
+        );
+        assert!(matches!(
+            EventResult::from_hashmap(map),
+            Err(ItemDecodeError::InvalidValue { .. })
+        ));
+    }
+}
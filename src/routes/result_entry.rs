@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::{
+    self,
+    types::{AttributeValue, Delete, TransactWriteItem},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::error::ApiError;
+use super::result::{EventResult, ResultData};
+use super::transaction::{put_new, TransactionStep};
+use super::utils::{get_string, get_uuid, Item, ItemDecodeError};
+
+pub const TABLE_NAME: &str = "result_entries";
+pub const ENTRY_KEY: &str = "entry";
+pub const RESULT_ID_KEY: &str = "result_id";
+
+/// The reservation of the one result an athlete or team can have in an event, or in a round of it
+/// Reservations are keyed by the event, the round and the athlete or team, so a second result for the same
+/// entry fails the transaction that writes it.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ResultEntry {
+    pub entry: String,
+    pub result_id: Uuid,
+}
+
+impl ResultEntry {
+    pub fn new(result: &EventResult) -> Self {
+        Self {
+            entry: entry(&result.result_data),
+            result_id: result.id,
+        }
+    }
+}
+
+impl Item for ResultEntry {
+    fn table_name() -> &'static str {
+        TABLE_NAME
+    }
+
+    fn partition_key_name() -> &'static str {
+        ENTRY_KEY
+    }
+
+    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError> {
+        Ok(Self {
+            entry: get_string::<Self>(&map, ENTRY_KEY)?,
+            result_id: get_uuid::<Self>(&map, RESULT_ID_KEY)?,
+        })
+    }
+
+    fn into_hashmap(self) -> HashMap<String, AttributeValue> {
+        HashMap::from([
+            (ENTRY_KEY.to_string(), AttributeValue::S(self.entry)),
+            (
+                RESULT_ID_KEY.to_string(),
+                AttributeValue::S(self.result_id.to_string()),
+            ),
+        ])
+    }
+}
+
+/// The key a result is reserved under: its event, its round if any, and its athlete or team
+pub fn entry(result_data: &ResultData) -> String {
+    let round = result_data
+        .round_id
+        .map(|round_id| round_id.to_string())
+        .unwrap_or_default();
+    let competitor = match (result_data.athlete_id, result_data.team_id) {
+        (Some(athlete_id), _) => format!("athlete/{athlete_id}"),
+        (None, Some(team_id)) => format!("team/{team_id}"),
+        (None, None) => String::new(),
+    };
+    format!("{}/{round}/{competitor}", result_data.event_id)
+}
+
+/// A step that reserves the entry of the result, failing with a 409 if another result holds it
+pub fn reserve(result: &EventResult) -> Result<TransactionStep, ApiError> {
+    let competitor = match (result.result_data.athlete_id, result.result_data.team_id) {
+        (Some(athlete_id), _) => format!("Athlete {athlete_id}"),
+        (None, Some(team_id)) => format!("Relay team {team_id}"),
+        (None, None) => "The athlete".to_string(),
+    };
+    let round = match result.result_data.round_id {
+        Some(round_id) => format!("round {round_id} of "),
+        None => String::new(),
+    };
+    put_new(
+        ResultEntry::new(result),
+        ApiError::Conflict(format!(
+            "{competitor} already has a result in {round}event {}",
+            result.result_data.event_id
+        )),
+    )
+}
+
+/// A step that frees the entry of the result, unless it is reserved by another result
+/// Results written before entries were reserved have no reservation, which is fine to free.
+pub fn release(result: &EventResult) -> Result<TransactionStep, ApiError> {
+    let delete = Delete::builder()
+        .table_name(TABLE_NAME)
+        .key(ENTRY_KEY, AttributeValue::S(entry(&result.result_data)))
+        .condition_expression("attribute_not_exists(#pk) OR #result = :result")
+        .expression_attribute_names("#pk", ENTRY_KEY)
+        .expression_attribute_names("#result", RESULT_ID_KEY)
+        .expression_attribute_values(":result", AttributeValue::S(result.id.to_string()))
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok(TransactionStep::new(
+        TransactWriteItem::builder().delete(delete).build(),
+        Some(ApiError::Conflict(format!(
+            "The entry of result {} is reserved by another result",
+            result.id
+        ))),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_result_entry_is_per_round() {
+        let result_data = ResultData {
+            event_id: Uuid::new_v4(),
+            athlete_id: Some(Uuid::new_v4()),
+            team_id: None,
+            round_id: None,
+            place: 1,
+            mark: None,
+            wind: None,
+            attempts: Vec::new(),
+        };
+        let final_result = EventResult::from(result_data.clone());
+        let heat_result = EventResult::from(ResultData {
+            round_id: Some(Uuid::new_v4()),
+            ..result_data.clone()
+        });
+        let again = EventResult::from(result_data);
+        assert_eq!(
+            ResultEntry::new(&final_result).entry,
+            ResultEntry::new(&again).entry
+        );
+        assert_ne!(
+            ResultEntry::new(&final_result).entry,
+            ResultEntry::new(&heat_result).entry
+        );
+        let reservation = ResultEntry::new(&heat_result);
+        assert_eq!(
+            ResultEntry::from_hashmap(reservation.clone().into_hashmap()).unwrap(),
+            reservation
+        );
+    }
+}
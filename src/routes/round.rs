@@ -0,0 +1,975 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use super::athlete::Athlete;
+use super::error::ApiError;
+use super::event::{self, Event};
+use super::permission::{Caller, Guarded};
+use super::result::{self, EventResult, Mark};
+use super::transaction::{self, put_new, transact, TransactionStep};
+use super::utils::fetch_items;
+use super::utils::{fetch_existing_item, fetch_item, get_item, get_number, get_string, get_uuid};
+use super::utils::{query_all, Item, ItemDecodeError};
+use aws_sdk_dynamodb::{
+    self,
+    types::{AttributeValue, TransactWriteItem, Update},
+    Client,
+};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{delete, get, post};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const TABLE_NAME: &str = "rounds";
+pub const ID_KEY: &str = "id";
+pub const EVENT_ID_KEY: &str = "event_id";
+const ROUND_TYPE_KEY: &str = "round_type";
+const NUMBER_KEY: &str = "number";
+const HEATS_KEY: &str = "heats";
+const HEAT_NUMBER_KEY: &str = "number";
+const ASSIGNMENTS_KEY: &str = "assignments";
+const ATHLETE_ID_KEY: &str = "athlete_id";
+const POSITION_KEY: &str = "position";
+const BY_PLACE_KEY: &str = "advance_by_place";
+const BY_TIME_KEY: &str = "advance_by_time";
+/// Rounds of an event
+pub const EVENT_INDEX: &str = "event_id-index";
+
+/// The lanes (or places in the running order) of a heat when none are given
+const DEFAULT_LANES: u32 = 8;
+/// The most lanes (or places in the running order) a drawn heat can have, enough for the start list of a distance
+/// race or a field event
+const MAX_LANES: u32 = 64;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundType {
+    Heats,
+    Quarterfinals,
+    Semifinals,
+    Final,
+}
+
+impl RoundType {
+    /// The name the round type is stored under, the same as its JSON name
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Heats => "heats",
+            Self::Quarterfinals => "quarterfinals",
+            Self::Semifinals => "semifinals",
+            Self::Final => "final",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        [
+            Self::Heats,
+            Self::Quarterfinals,
+            Self::Semifinals,
+            Self::Final,
+        ]
+        .into_iter()
+        .find(|round_type| round_type.as_str() == value)
+    }
+}
+
+/// The lane of an athlete in a heat, or their place in the running order for events without lanes
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Assignment {
+    pub athlete_id: Uuid,
+    pub position: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Heat {
+    pub number: u32,
+    pub assignments: Vec<Assignment>,
+}
+
+/// Who goes through to the next round: the first `by_place` of every heat, then the `by_time` best marks of
+/// everyone else
+/// Field events are ranked by their mark the same way, so `by_time` means "by distance" there.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct AdvancementRule {
+    pub by_place: u32,
+    pub by_time: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Round {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub round_data: RoundData,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RoundData {
+    pub event_id: Uuid,
+    pub round_type: RoundType,
+    /// The order of the round within the event, starting at 1
+    pub number: u32,
+    pub heats: Vec<Heat>,
+    /// How athletes advance out of this round, if there is a next round
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub advancement: Option<AdvancementRule>,
+}
+
+impl Round {
+    /// The heat the athlete is assigned to, if any
+    pub fn heat_of(&self, athlete_id: Uuid) -> Option<u32> {
+        self.round_data
+            .heats
+            .iter()
+            .find(|heat| {
+                heat.assignments
+                    .iter()
+                    .any(|assignment| assignment.athlete_id == athlete_id)
+            })
+            .map(|heat| heat.number)
+    }
+}
+
+fn heats_into_attribute(heats: &[Heat]) -> AttributeValue {
+    AttributeValue::L(
+        heats
+            .iter()
+            .map(|heat| {
+                let assignments = heat
+                    .assignments
+                    .iter()
+                    .map(|assignment| {
+                        AttributeValue::M(HashMap::from([
+                            (
+                                ATHLETE_ID_KEY.to_string(),
+                                AttributeValue::S(assignment.athlete_id.to_string()),
+                            ),
+                            (
+                                POSITION_KEY.to_string(),
+                                AttributeValue::N(assignment.position.to_string()),
+                            ),
+                        ]))
+                    })
+                    .collect();
+                AttributeValue::M(HashMap::from([
+                    (
+                        HEAT_NUMBER_KEY.to_string(),
+                        AttributeValue::N(heat.number.to_string()),
+                    ),
+                    (ASSIGNMENTS_KEY.to_string(), AttributeValue::L(assignments)),
+                ]))
+            })
+            .collect(),
+    )
+}
+
+/// Read the heats of a round, a list of maps that each hold a list of assignment maps
+fn get_heats(map: &HashMap<String, AttributeValue>) -> Result<Vec<Heat>, ItemDecodeError> {
+    let invalid = |reason: &str| ItemDecodeError::invalid::<Round>(map, HEATS_KEY, reason);
+    let heats = map
+        .get(HEATS_KEY)
+        .ok_or_else(|| invalid("is missing"))?
+        .as_l()
+        .map_err(|_| invalid("is not a list"))?;
+    heats
+        .iter()
+        .map(|heat| {
+            let heat = heat
+                .as_m()
+                .map_err(|_| invalid("holds a heat that is not a map"))?;
+            let assignments = heat
+                .get(ASSIGNMENTS_KEY)
+                .and_then(|assignments| assignments.as_l().ok())
+                .ok_or_else(|| invalid("holds a heat without assignments"))?
+                .iter()
+                .map(|assignment| {
+                    let assignment = assignment
+                        .as_m()
+                        .map_err(|_| invalid("holds an assignment that is not a map"))?;
+                    Ok(Assignment {
+                        athlete_id: get_uuid::<Round>(assignment, ATHLETE_ID_KEY)?,
+                        position: get_number::<Round, u32>(assignment, POSITION_KEY)?,
+                    })
+                })
+                .collect::<Result<Vec<Assignment>, ItemDecodeError>>()?;
+            Ok(Heat {
+                number: get_number::<Round, u32>(heat, HEAT_NUMBER_KEY)?,
+                assignments,
+            })
+        })
+        .collect()
+}
+
+impl Item for Round {
+    fn table_name() -> &'static str {
+        TABLE_NAME
+    }
+    fn partition_key_name() -> &'static str {
+        ID_KEY
+    }
+
+    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError> {
+        let id = get_uuid::<Self>(&map, ID_KEY)?;
+        let event_id = get_uuid::<Self>(&map, EVENT_ID_KEY)?;
+        let round_type = get_string::<Self>(&map, ROUND_TYPE_KEY)?;
+        let round_type = RoundType::parse(&round_type).ok_or_else(|| {
+            ItemDecodeError::invalid::<Self>(
+                &map,
+                ROUND_TYPE_KEY,
+                format!("unknown round type {round_type}"),
+            )
+        })?;
+        let number = get_number::<Self, u32>(&map, NUMBER_KEY)?;
+        let heats = get_heats(&map)?;
+        let advancement = match map.contains_key(BY_PLACE_KEY) {
+            true => Some(AdvancementRule {
+                by_place: get_number::<Self, u32>(&map, BY_PLACE_KEY)?,
+                by_time: get_number::<Self, u32>(&map, BY_TIME_KEY)?,
+            }),
+            false => None,
+        };
+        Ok(Self {
+            id,
+            round_data: RoundData {
+                event_id,
+                round_type,
+                number,
+                heats,
+                advancement,
+            },
+        })
+    }
+
+    fn into_hashmap(self) -> HashMap<String, AttributeValue> {
+        let mut map = HashMap::new();
+        map.insert(ID_KEY.to_string(), AttributeValue::S(self.id.to_string()));
+        map.insert(
+            EVENT_ID_KEY.to_string(),
+            AttributeValue::S(self.round_data.event_id.to_string()),
+        );
+        map.insert(
+            ROUND_TYPE_KEY.to_string(),
+            AttributeValue::S(self.round_data.round_type.as_str().to_string()),
+        );
+        map.insert(
+            NUMBER_KEY.to_string(),
+            AttributeValue::N(self.round_data.number.to_string()),
+        );
+        map.insert(
+            HEATS_KEY.to_string(),
+            heats_into_attribute(&self.round_data.heats),
+        );
+        if let Some(advancement) = self.round_data.advancement {
+            map.insert(
+                BY_PLACE_KEY.to_string(),
+                AttributeValue::N(advancement.by_place.to_string()),
+            );
+            map.insert(
+                BY_TIME_KEY.to_string(),
+                AttributeValue::N(advancement.by_time.to_string()),
+            );
+        }
+        map
+    }
+}
+
+/// Rounds are drawn and deleted by the meet director of the competition the event is held at
+impl Guarded for Round {
+    async fn authorize_create(
+        &mut self,
+        db_client: &Client,
+        caller: &Caller,
+    ) -> Result<(), ApiError> {
+        caller
+            .require_event_director(db_client, self.round_data.event_id)
+            .await
+    }
+
+    async fn authorize_modify(&self, db_client: &Client, caller: &Caller) -> Result<(), ApiError> {
+        caller
+            .require_event_director(db_client, self.round_data.event_id)
+            .await
+    }
+}
+
+/// The order lanes are handed out in, best seed first: the middle lanes, then outwards
+/// With 8 lanes this is 4, 5, 3, 6, 2, 7, 1, 8.
+fn lane_order(lanes: u32) -> Vec<u32> {
+    let middle = lanes.div_ceil(2);
+    let mut order = vec![middle];
+    for offset in 1..=lanes {
+        if middle + offset <= lanes {
+            order.push(middle + offset);
+        }
+        if offset < middle {
+            order.push(middle - offset);
+        }
+    }
+    order
+}
+
+/// Draw seeded athletes (best first) into heats
+/// Seeds are dealt out in a serpentine so every heat gets a fair share of the field, and the better seeds of a
+/// heat get the middle lanes.
+fn seed_heats(seeds: &[Uuid], heat_count: u32, lanes: u32) -> Result<Vec<Heat>, ApiError> {
+    if heat_count == 0 || lanes == 0 {
+        return Err(ApiError::UnprocessableEntity(
+            "A round needs at least one heat and one lane".to_string(),
+        ));
+    }
+    let fits = heat_count
+        .checked_mul(lanes)
+        .is_none_or(|places| seeds.len() <= places as usize);
+    if !fits {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "{} athletes do not fit in {heat_count} heats of {lanes} lanes",
+            seeds.len()
+        )));
+    }
+    let lane_order = lane_order(lanes);
+    let mut heats: Vec<Heat> = (1..=heat_count)
+        .map(|number| Heat {
+            number,
+            assignments: Vec::new(),
+        })
+        .collect();
+    let heat_count = heat_count as usize;
+    for (seed, athlete_id) in seeds.iter().enumerate() {
+        let (row, column) = (seed / heat_count, seed % heat_count);
+        let heat = match row % 2 {
+            0 => column,
+            _ => heat_count - 1 - column,
+        };
+        heats[heat].assignments.push(Assignment {
+            athlete_id: *athlete_id,
+            position: lane_order[row],
+        });
+    }
+    heats.retain(|heat| !heat.assignments.is_empty());
+    Ok(heats)
+}
+
+/// Reject heats that repeat a heat number, a position in a heat or an athlete, or hold an athlete who is not one
+/// of the `seeds` of the round (the entry list for a first round, the qualifiers for a later one)
+fn validate_heats(heats: &[Heat], seeds: &[Uuid]) -> Result<(), ApiError> {
+    let mut athletes = Vec::new();
+    for (i, heat) in heats.iter().enumerate() {
+        if heat.number == 0 || heats[..i].iter().any(|other| other.number == heat.number) {
+            return Err(ApiError::UnprocessableEntity(format!(
+                "Heat numbers must be unique and start at 1, got {}",
+                heat.number
+            )));
+        }
+        for (j, assignment) in heat.assignments.iter().enumerate() {
+            if assignment.position == 0
+                || heat.assignments[..j]
+                    .iter()
+                    .any(|other| other.position == assignment.position)
+            {
+                return Err(ApiError::UnprocessableEntity(format!(
+                    "Positions in heat {} must be unique and start at 1, got {}",
+                    heat.number, assignment.position
+                )));
+            }
+            if !seeds.contains(&assignment.athlete_id) {
+                return Err(ApiError::UnprocessableEntity(format!(
+                    "Athlete {} is not entered in the event or did not qualify for this round",
+                    assignment.athlete_id
+                )));
+            }
+            if athletes.contains(&assignment.athlete_id) {
+                return Err(ApiError::UnprocessableEntity(format!(
+                    "Athlete {} is assigned twice",
+                    assignment.athlete_id
+                )));
+            }
+            athletes.push(assignment.athlete_id);
+        }
+    }
+    Ok(())
+}
+
+/// The result of an athlete in a round, placed within their heat
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeatResult {
+    pub athlete_id: Uuid,
+    pub place: u32,
+    pub mark: Mark,
+}
+
+/// How an athlete made it through to the next round
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Qualification {
+    /// "Q", by finishing high enough in their heat
+    Place,
+    /// "q", by having one of the best marks of the rest
+    Time,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq)]
+pub struct Qualifier {
+    pub athlete_id: Uuid,
+    pub qualification: Qualification,
+}
+
+/// The result of every athlete drawn in the round, placed within their heat
+/// A round can only be advanced once every athlete in it has a result with a mark.
+fn heat_results(round: &Round, results: &[EventResult]) -> Result<Vec<HeatResult>, ApiError> {
+    let mut heat_results = Vec::new();
+    for heat in &round.round_data.heats {
+        for assignment in &heat.assignments {
+            let athlete_id = assignment.athlete_id;
+            let result = results
+                .iter()
+                .find(|result| result.result_data.athlete_id == Some(athlete_id))
+                .ok_or_else(|| {
+                    ApiError::UnprocessableEntity(format!(
+                        "Athlete {athlete_id} in heat {} has no result in round {}",
+                        heat.number, round.id
+                    ))
+                })?;
+            let mark = result.result_data.mark.ok_or_else(|| {
+                ApiError::UnprocessableEntity(format!(
+                    "The result of athlete {athlete_id} in heat {} of round {} has no mark",
+                    heat.number, round.id
+                ))
+            })?;
+            heat_results.push(HeatResult {
+                athlete_id,
+                place: result.result_data.place,
+                mark,
+            });
+        }
+    }
+    Ok(heat_results)
+}
+
+/// Order marks best first
+fn compare_marks(a: &Mark, b: &Mark) -> Ordering {
+    if a.beats(b) {
+        Ordering::Less
+    } else if b.beats(a) {
+        Ordering::Greater
+    } else {
+        Ordering::Equal
+    }
+}
+
+/// Apply an advancement rule to the results of a round
+/// Qualifiers come back in seeding order for the next round: those who qualified by place (ranked by place, then
+/// mark), then those who qualified by time (ranked by mark).
+pub fn advance(results: &[HeatResult], rule: &AdvancementRule) -> Vec<Qualifier> {
+    let (mut by_place, mut rest): (Vec<HeatResult>, Vec<HeatResult>) = results
+        .iter()
+        .partition(|result| result.place <= rule.by_place);
+    by_place.sort_by(|a, b| {
+        a.place
+            .cmp(&b.place)
+            .then_with(|| compare_marks(&a.mark, &b.mark))
+    });
+    rest.sort_by(|a, b| compare_marks(&a.mark, &b.mark));
+    rest.truncate(rule.by_time as usize);
+    by_place
+        .iter()
+        .map(|result| Qualifier {
+            athlete_id: result.athlete_id,
+            qualification: Qualification::Place,
+        })
+        .chain(rest.iter().map(|result| Qualifier {
+            athlete_id: result.athlete_id,
+            qualification: Qualification::Time,
+        }))
+        .collect()
+}
+
+/// The rounds of an event, in order
+async fn event_rounds(db_client: &Client, event_id: Uuid) -> Result<Vec<Round>, ApiError> {
+    let mut rounds =
+        query_all::<Round>(db_client, Some(EVENT_INDEX), EVENT_ID_KEY, event_id).await?;
+    rounds.sort_by_key(|round| round.round_data.number);
+    Ok(rounds)
+}
+
+/// How to draw a new round
+/// Give `heats` to set the draw by hand; otherwise athletes are seeded into `heat_count` heats of `lanes` lanes
+/// (8 lanes and as few heats as fit by default).
+#[derive(Debug, Deserialize)]
+struct NewRound {
+    round_type: RoundType,
+    heats: Option<Vec<Heat>>,
+    heat_count: Option<u32>,
+    lanes: Option<u32>,
+    advancement: Option<AdvancementRule>,
+}
+
+impl NewRound {
+    /// Draw the round from athletes in seeding order, unless the heats were given
+    fn draw(&self, seeds: &[Uuid]) -> Result<Vec<Heat>, ApiError> {
+        if let Some(heats) = &self.heats {
+            return Ok(heats.clone());
+        }
+        let lanes = self.lanes.unwrap_or(DEFAULT_LANES);
+        if lanes > MAX_LANES {
+            return Err(ApiError::UnprocessableEntity(format!(
+                "A heat has at most {MAX_LANES} lanes, got {lanes}"
+            )));
+        }
+        let heat_count = match self.heat_count {
+            Some(heat_count) => heat_count,
+            None => (seeds.len() as u32).div_ceil(lanes.max(1)).max(1),
+        };
+        if heat_count as usize > seeds.len().max(1) {
+            return Err(ApiError::UnprocessableEntity(format!(
+                "{heat_count} heats are more than the {} athletes to draw",
+                seeds.len()
+            )));
+        }
+        seed_heats(seeds, heat_count, lanes)
+    }
+}
+
+/// A step that moves the round count of the event from `previous` to `count`
+/// It fails if another round was added or deleted since the event was read, so two rounds cannot get the same
+/// number. Events whose rounds were added before they were counted have no count yet, which counts as 0.
+fn round_count_step(
+    event_id: Uuid,
+    previous: u32,
+    count: u32,
+) -> Result<TransactionStep, ApiError> {
+    let condition = match previous {
+        0 => "attribute_exists(#pk) AND (attribute_not_exists(#rounds) OR #rounds = :previous)",
+        _ => "#rounds = :previous",
+    };
+    let update = Update::builder()
+        .table_name(event::TABLE_NAME)
+        .key(event::ID_KEY, AttributeValue::S(event_id.to_string()))
+        .condition_expression(condition)
+        .update_expression("SET #rounds = :count")
+        .expression_attribute_names("#rounds", event::ROUND_COUNT_KEY)
+        .expression_attribute_values(":previous", AttributeValue::N(previous.to_string()))
+        .expression_attribute_values(":count", AttributeValue::N(count.to_string()));
+    let update = match previous {
+        0 => update.expression_attribute_names("#pk", event::ID_KEY),
+        _ => update,
+    }
+    .build()
+    .map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok(TransactionStep::new(
+        TransactWriteItem::builder().update(update).build(),
+        Some(ApiError::Conflict(format!(
+            "The rounds of event {event_id} changed since it was read, please retry the request"
+        ))),
+    ))
+}
+
+/// Write the next round of an event, after its current `rounds`
+/// The round is numbered from the round count of the event, which is moved on in the same transaction.
+async fn create_round(
+    db_client: &Client,
+    event: &Event,
+    rounds: &[Round],
+    new_round: &NewRound,
+    seeds: &[Uuid],
+) -> Result<Round, ApiError> {
+    if event.event_data.is_relay() {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "Event {} is a relay, relays are not held in rounds yet",
+            event.id
+        )));
+    }
+    let heats = new_round.draw(seeds)?;
+    validate_heats(&heats, seeds)?;
+    let previous = event.event_data.round_count;
+    let number = rounds
+        .last()
+        .map_or(0, |round| round.round_data.number)
+        .max(previous)
+        + 1;
+    let round = Round {
+        id: Uuid::new_v4(),
+        round_data: RoundData {
+            event_id: event.id,
+            round_type: new_round.round_type,
+            number,
+            heats,
+            advancement: new_round.advancement,
+        },
+    };
+    transact(
+        db_client,
+        vec![
+            round_count_step(event.id, previous, number)?,
+            put_new(
+                round.clone(),
+                ApiError::Conflict(format!("Round {} already exists", round.id)),
+            )?,
+        ],
+    )
+    .await?;
+    Ok(round)
+}
+
+/// Add the first round to an event and draw its start list
+/// The round is seeded from the entry list in entry order. Later rounds are seeded from the results of the round
+/// before, see `advance_round`.
+async fn add_round(
+    caller: Caller,
+    Path(event_id): Path<Uuid>,
+    State(db_client): State<Client>,
+    Json(new_round): Json<NewRound>,
+) -> Result<Json<Round>, ApiError> {
+    let event = fetch_existing_item::<Event>(&db_client, event_id).await?;
+    caller
+        .require_director(&db_client, event.event_data.competition_id)
+        .await?;
+    let rounds = event_rounds(&db_client, event_id).await?;
+    if !rounds.is_empty() {
+        return Err(ApiError::Conflict(format!(
+            "Event {event_id} already has rounds, add the next one with POST /rounds/:round_id/advance"
+        )));
+    }
+    let seeds = event.event_data.athlete_ids.clone();
+    create_round(&db_client, &event, &rounds, &new_round, &seeds)
+        .await
+        .map(Json)
+}
+
+async fn get_event_rounds(
+    Path(event_id): Path<Uuid>,
+    State(db_client): State<Client>,
+) -> Result<Json<Vec<Round>>, ApiError> {
+    fetch_existing_item::<Event>(&db_client, event_id).await?;
+    Ok(Json(event_rounds(&db_client, event_id).await?))
+}
+
+/// One athlete on a start list
+#[derive(Debug, Serialize)]
+struct StartListEntry {
+    position: u32,
+    athlete: Athlete,
+}
+
+#[derive(Debug, Serialize)]
+struct StartListHeat {
+    number: u32,
+    entries: Vec<StartListEntry>,
+}
+
+/// Get the start list of a round: every heat in order, with its athletes in lane order
+async fn get_start_list(
+    Path(round_id): Path<Uuid>,
+    State(db_client): State<Client>,
+) -> Result<Json<Vec<StartListHeat>>, ApiError> {
+    let round = fetch_existing_item::<Round>(&db_client, round_id).await?;
+    let athlete_ids: Vec<Uuid> = round
+        .round_data
+        .heats
+        .iter()
+        .flat_map(|heat| {
+            heat.assignments
+                .iter()
+                .map(|assignment| assignment.athlete_id)
+        })
+        .collect();
+    let athletes: HashMap<Uuid, Athlete> = fetch_items::<Athlete>(&db_client, &athlete_ids)
+        .await?
+        .into_iter()
+        .map(|athlete| (athlete.id, athlete))
+        .collect();
+    let mut heats: Vec<StartListHeat> = round
+        .round_data
+        .heats
+        .into_iter()
+        .map(|heat| {
+            let mut entries: Vec<StartListEntry> = heat
+                .assignments
+                .iter()
+                .filter_map(|assignment| {
+                    Some(StartListEntry {
+                        position: assignment.position,
+                        athlete: athletes.get(&assignment.athlete_id)?.clone(),
+                    })
+                })
+                .collect();
+            entries.sort_by_key(|entry| entry.position);
+            StartListHeat {
+                number: heat.number,
+                entries,
+            }
+        })
+        .collect();
+    heats.sort_by_key(|heat| heat.number);
+    Ok(Json(heats))
+}
+
+/// The results recorded in a round
+pub async fn round_results(
+    db_client: &Client,
+    round: &Round,
+) -> Result<Vec<EventResult>, ApiError> {
+    Ok(query_all::<EventResult>(
+        db_client,
+        Some(result::EVENT_INDEX),
+        result::EVENT_ID_KEY,
+        round.round_data.event_id,
+    )
+    .await?
+    .into_iter()
+    .filter(|result| result.result_data.round_id == Some(round.id))
+    .collect())
+}
+
+/// Seed the next round of an event from the results of this one, using this round's advancement rule
+async fn advance_round(
+    caller: Caller,
+    Path(round_id): Path<Uuid>,
+    State(db_client): State<Client>,
+    Json(new_round): Json<NewRound>,
+) -> Result<Json<Round>, ApiError> {
+    let round = fetch_existing_item::<Round>(&db_client, round_id).await?;
+    let rule = round.round_data.advancement.ok_or_else(|| {
+        ApiError::UnprocessableEntity(format!("Round {round_id} has no advancement rule"))
+    })?;
+    let event = fetch_existing_item::<Event>(&db_client, round.round_data.event_id).await?;
+    caller
+        .require_director(&db_client, event.event_data.competition_id)
+        .await?;
+    let rounds = event_rounds(&db_client, event.id).await?;
+    if rounds
+        .iter()
+        .any(|other| other.round_data.number > round.round_data.number)
+    {
+        return Err(ApiError::Conflict(format!(
+            "Round {round_id} has already been advanced"
+        )));
+    }
+    let results = heat_results(&round, &round_results(&db_client, &round).await?)?;
+    let seeds: Vec<Uuid> = advance(&results, &rule)
+        .into_iter()
+        .map(|qualifier| qualifier.athlete_id)
+        .collect();
+    create_round(&db_client, &event, &rounds, &new_round, &seeds)
+        .await
+        .map(Json)
+}
+
+/// Delete a round, which is refused while results are recorded in it
+/// Deleting the latest round of an event counts it off, so the next round added takes its number.
+async fn delete_round(
+    caller: Caller,
+    Path(round_id): Path<Uuid>,
+    State(db_client): State<Client>,
+) -> Result<StatusCode, ApiError> {
+    let Some(round) = fetch_item::<Round>(&db_client, round_id).await? else {
+        return Ok(StatusCode::OK);
+    };
+    round.authorize_modify(&db_client, &caller).await?;
+    let results = round_results(&db_client, &round).await?;
+    if !results.is_empty() {
+        return Err(ApiError::Conflict(format!(
+            "Round {round_id} has {} results, delete them before the round",
+            results.len()
+        )));
+    }
+    let mut steps = vec![transaction::delete::<Round>(HashMap::from([(
+        ID_KEY.to_string(),
+        AttributeValue::S(round_id.to_string()),
+    )]))?];
+    let number = round.round_data.number;
+    if let Some(event) = fetch_item::<Event>(&db_client, round.round_data.event_id).await? {
+        if event.event_data.round_count == number {
+            steps.push(round_count_step(event.id, number, number - 1)?);
+        }
+    }
+    transact(&db_client, steps).await?;
+    Ok(StatusCode::OK)
+}
+
+pub fn round_routes() -> axum::Router<Client> {
+    axum::Router::new()
+        .route("/:round_id", get(get_item::<Round>))
+        .route("/:round_id", delete(delete_round))
+        .route("/:round_id/start-list", get(get_start_list))
+        .route("/:round_id/advance", post(advance_round))
+}
+
+/// The round routes nested under `/events`
+pub fn event_round_routes() -> axum::Router<Client> {
+    axum::Router::new()
+        .route("/:event_id/rounds", post(add_round))
+        .route("/:event_id/rounds", get(get_event_rounds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::result::ResultData;
+
+    fn ids(count: usize) -> Vec<Uuid> {
+        (0..count).map(|_| Uuid::new_v4()).collect()
+    }
+
+    #[test]
+    fn test_round_into_hashmap() {
+        let athletes = ids(3);
+        let round = Round {
+            id: Uuid::new_v4(),
+            round_data: RoundData {
+                event_id: Uuid::new_v4(),
+                round_type: RoundType::Semifinals,
+                number: 2,
+                heats: seed_heats(&athletes, 2, 8).unwrap(),
+                advancement: Some(AdvancementRule {
+                    by_place: 2,
+                    by_time: 2,
+                }),
+            },
+        };
+        let map = round.clone().into_hashmap();
+        assert_eq!(Round::from_hashmap(map).unwrap(), round);
+    }
+
+    #[test]
+    fn test_lane_order() {
+        assert_eq!(lane_order(8), vec![4, 5, 3, 6, 2, 7, 1, 8]);
+        assert_eq!(lane_order(9), vec![5, 6, 4, 7, 3, 8, 2, 9, 1]);
+        assert_eq!(lane_order(1), vec![1]);
+    }
+
+    #[test]
+    fn test_seed_heats_serpentine() {
+        let athletes = ids(6);
+        let heats = seed_heats(&athletes, 2, 4).unwrap();
+        let heat_one: Vec<Uuid> = heats[0].assignments.iter().map(|a| a.athlete_id).collect();
+        assert_eq!(heat_one, vec![athletes[0], athletes[3], athletes[4]]);
+        let lanes: Vec<u32> = heats[0].assignments.iter().map(|a| a.position).collect();
+        assert_eq!(lanes, vec![2, 3, 1]);
+        assert!(validate_heats(&heats, &athletes).is_ok());
+        assert!(seed_heats(&ids(9), 2, 4).is_err());
+    }
+
+    #[test]
+    fn test_draw_caps_heats_and_lanes() {
+        let new_round = |heat_count: Option<u32>, lanes: Option<u32>| NewRound {
+            round_type: RoundType::Heats,
+            heats: None,
+            heat_count,
+            lanes,
+            advancement: None,
+        };
+        assert!(new_round(None, Some(4_000_000_000)).draw(&ids(4)).is_err());
+        assert!(new_round(Some(5), None).draw(&ids(4)).is_err());
+        assert_eq!(new_round(Some(2), None).draw(&ids(4)).unwrap().len(), 2);
+        assert_eq!(new_round(None, None).draw(&ids(20)).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_validate_heats_rejects_duplicates() {
+        let athletes = ids(2);
+        let heat = |assignments| Heat {
+            number: 1,
+            assignments,
+        };
+        let lane = |athlete_id, position| Assignment {
+            athlete_id,
+            position,
+        };
+        assert!(validate_heats(
+            &[heat(vec![lane(athletes[0], 1), lane(athletes[1], 1)])],
+            &athletes
+        )
+        .is_err());
+        assert!(validate_heats(
+            &[heat(vec![lane(athletes[0], 1), lane(athletes[0], 2)])],
+            &athletes
+        )
+        .is_err());
+        assert!(validate_heats(&[heat(vec![lane(Uuid::new_v4(), 1)])], &athletes).is_err());
+    }
+
+    #[test]
+    fn test_heat_results_need_a_mark_for_everyone() {
+        let athletes = ids(2);
+        let round = Round {
+            id: Uuid::new_v4(),
+            round_data: RoundData {
+                event_id: Uuid::new_v4(),
+                round_type: RoundType::Heats,
+                number: 1,
+                heats: seed_heats(&athletes, 1, 8).unwrap(),
+                advancement: None,
+            },
+        };
+        let result = |athlete_id, mark| {
+            EventResult::from(ResultData {
+                event_id: round.round_data.event_id,
+                athlete_id: Some(athlete_id),
+                team_id: None,
+                round_id: Some(round.id),
+                place: 1,
+                mark,
+                wind: None,
+                attempts: Vec::new(),
+            })
+        };
+        let first = result(athletes[0], Some(Mark::Time { seconds: 45.1 }));
+        assert!(matches!(
+            heat_results(&round, std::slice::from_ref(&first)),
+            Err(ApiError::UnprocessableEntity(_))
+        ));
+        assert!(matches!(
+            heat_results(&round, &[first.clone(), result(athletes[1], None)]),
+            Err(ApiError::UnprocessableEntity(_))
+        ));
+        let second = result(athletes[1], Some(Mark::Time { seconds: 45.6 }));
+        assert_eq!(heat_results(&round, &[second, first]).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_advance_top_by_place_then_by_time() {
+        let athletes = ids(6);
+        let result = |i: usize, place, seconds| HeatResult {
+            athlete_id: athletes[i],
+            place,
+            mark: Mark::Time { seconds },
+        };
+        let results = vec![
+            result(0, 1, 45.1),
+            result(1, 2, 45.3),
+            result(2, 3, 45.9),
+            result(3, 1, 44.9),
+            result(4, 2, 45.6),
+            result(5, 3, 45.4),
+        ];
+        let qualifiers = advance(
+            &results,
+            &AdvancementRule {
+                by_place: 1,
+                by_time: 2,
+            },
+        );
+        assert_eq!(
+            qualifiers,
+            vec![
+                Qualifier {
+                    athlete_id: athletes[3],
+                    qualification: Qualification::Place
+                },
+                Qualifier {
+                    athlete_id: athletes[0],
+                    qualification: Qualification::Place
+                },
+                Qualifier {
+                    athlete_id: athletes[1],
+                    qualification: Qualification::Time
+                },
+                Qualifier {
+                    athlete_id: athletes[5],
+                    qualification: Qualification::Time
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,411 @@
+use super::discipline::{Discipline, DisciplineGroup, Gender};
+use super::error::ApiError;
+use super::event::{self, Event};
+use super::result::{self, EventResult, Mark};
+use super::utils::{fetch_existing_item, query_all};
+use aws_sdk_dynamodb::Client;
+use axum::extract::{Path, State};
+use axum::routing::get;
+use axum::Json;
+use serde::Serialize;
+use uuid::Uuid;
+
+/// The constants of the World Athletics scoring formula for one component of a combined event
+/// Running events score `a * (b - seconds)^c`, jumps `a * (centimeters - b)^c` and throws `a * (meters - b)^c`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScoringTable {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+}
+
+const fn table(a: f64, b: f64, c: f64) -> ScoringTable {
+    ScoringTable { a, b, c }
+}
+
+/// The scoring table of a component discipline, from the World Athletics scoring tables for combined events
+pub fn scoring_table(gender: Gender, discipline: Discipline) -> Option<ScoringTable> {
+    use Discipline::*;
+    Some(match (gender, discipline) {
+        (Gender::Men, Sprint60m) => table(58.015, 11.5, 1.81),
+        (Gender::Men, Sprint100m) => table(25.4347, 18.0, 1.81),
+        (Gender::Men, Sprint400m) => table(1.53775, 82.0, 1.81),
+        (Gender::Men, Run1000m) => table(0.08713, 305.5, 1.85),
+        (Gender::Men, Run1500m) => table(0.03768, 480.0, 1.85),
+        (Gender::Men, Hurdles60m) => table(20.5173, 15.5, 1.92),
+        (Gender::Men, Hurdles110m) => table(5.74352, 28.5, 1.92),
+        (Gender::Men, HighJump) => table(0.8465, 75.0, 1.42),
+        (Gender::Men, PoleVault) => table(0.2797, 100.0, 1.35),
+        (Gender::Men, LongJump) => table(0.14354, 220.0, 1.4),
+        (Gender::Men, ShotPut) => table(51.39, 1.5, 1.05),
+        (Gender::Men, DiscusThrow) => table(12.91, 4.0, 1.1),
+        (Gender::Men, JavelinThrow) => table(10.14, 7.0, 1.08),
+        (Gender::Women, Sprint100m) => table(17.857, 21.0, 1.81),
+        (Gender::Women, Sprint200m) => table(4.99087, 42.5, 1.81),
+        (Gender::Women, Sprint400m) => table(1.34285, 91.7, 1.81),
+        (Gender::Women, Run800m) => table(0.11193, 254.0, 1.88),
+        (Gender::Women, Run1500m) => table(0.02883, 535.0, 1.88),
+        (Gender::Women, Hurdles60m) => table(20.0479, 17.0, 1.835),
+        (Gender::Women, Hurdles100m) => table(9.23076, 26.7, 1.835),
+        (Gender::Women, HighJump) => table(1.84523, 75.0, 1.348),
+        (Gender::Women, PoleVault) => table(0.44125, 100.0, 1.35),
+        (Gender::Women, LongJump) => table(0.188807, 210.0, 1.41),
+        (Gender::Women, ShotPut) => table(56.0211, 1.5, 1.05),
+        (Gender::Women, DiscusThrow) => table(12.3311, 3.0, 1.1),
+        (Gender::Women, JavelinThrow) => table(15.9803, 3.8, 1.04),
+        _ => return None,
+    })
+}
+
+/// The components of a combined event in the order they are held, or `None` if it is not scored for the gender
+/// The heptathlon is the outdoor event for women and the indoor event for men; the pentathlon is the women's
+/// indoor event.
+pub fn components(combined: Discipline, gender: Gender) -> Option<&'static [Discipline]> {
+    use Discipline::*;
+    Some(match (combined, gender) {
+        (Decathlon, Gender::Men) => &[
+            Sprint100m,
+            LongJump,
+            ShotPut,
+            HighJump,
+            Sprint400m,
+            Hurdles110m,
+            DiscusThrow,
+            PoleVault,
+            JavelinThrow,
+            Run1500m,
+        ],
+        (Decathlon, Gender::Women) => &[
+            Sprint100m,
+            DiscusThrow,
+            PoleVault,
+            JavelinThrow,
+            Sprint400m,
+            Hurdles100m,
+            LongJump,
+            ShotPut,
+            HighJump,
+            Run1500m,
+        ],
+        (Heptathlon, Gender::Women) => &[
+            Hurdles100m,
+            HighJump,
+            ShotPut,
+            Sprint200m,
+            LongJump,
+            JavelinThrow,
+            Run800m,
+        ],
+        (Heptathlon, Gender::Men) => &[
+            Sprint60m, LongJump, ShotPut, HighJump, Hurdles60m, PoleVault, Run1000m,
+        ],
+        (Pentathlon, Gender::Women) => &[Hurdles60m, HighJump, ShotPut, LongJump, Run800m],
+        _ => return None,
+    })
+}
+
+/// The points a mark scores in a component discipline, rounded down, or `None` if the discipline is not scored
+/// Marks worse than the `b` constant of the table score nothing.
+pub fn points(gender: Gender, discipline: Discipline, mark: &Mark) -> Option<u32> {
+    let ScoringTable { a, b, c } = scoring_table(gender, discipline)?;
+    let performance = match (discipline.group(), mark) {
+        (_, Mark::Time { seconds }) => b - seconds,
+        // Jumps are measured to the centimeter, which the jump tables are in
+        (DisciplineGroup::Jumps, Mark::Distance { meters } | Mark::Height { meters }) => {
+            (meters * 100.0).round() - b
+        }
+        (_, Mark::Distance { meters }) => meters - b,
+        _ => return None,
+    };
+    if performance <= 0.0 {
+        return Some(0);
+    }
+    Some((a * performance.powf(c)).floor() as u32)
+}
+
+/// The score of an athlete in one component of a combined event
+#[derive(Clone, Debug, Serialize, PartialEq)]
+struct ComponentScore {
+    event_id: Uuid,
+    discipline: Discipline,
+    /// The best mark of the athlete in the component, if they have one yet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mark: Option<Mark>,
+    points: u32,
+    /// The points of the athlete after this component
+    running_total: u32,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+struct CombinedStanding {
+    rank: u32,
+    athlete_id: Uuid,
+    points: u32,
+    scores: Vec<ComponentScore>,
+}
+
+/// Score every athlete in every component held so far and rank them by total, tied athletes sharing a rank
+/// `athlete_ids` is the entry list of the combined event; athletes with a component result who are not on it
+/// are scored too.
+fn combined_standings(
+    gender: Gender,
+    components: &[Event],
+    results: &[EventResult],
+    athlete_ids: &[Uuid],
+) -> Vec<CombinedStanding> {
+    let mut athletes = athlete_ids.to_vec();
+    for athlete_id in results
+        .iter()
+        .filter_map(|result| result.result_data.athlete_id)
+    {
+        if !athletes.contains(&athlete_id) {
+            athletes.push(athlete_id);
+        }
+    }
+    let mut standings: Vec<CombinedStanding> = athletes
+        .into_iter()
+        .map(|athlete_id| {
+            let mut running_total = 0;
+            let scores = components
+                .iter()
+                .map(|component| {
+                    let discipline = component.event_data.discipline;
+                    let mark = results
+                        .iter()
+                        .filter(|result| {
+                            result.result_data.event_id == component.id
+                                && result.result_data.athlete_id == Some(athlete_id)
+                        })
+                        .filter_map(|result| result.result_data.mark)
+                        .reduce(|best, mark| if mark.beats(&best) { mark } else { best });
+                    let points = mark
+                        .and_then(|mark| points(gender, discipline, &mark))
+                        .unwrap_or(0);
+                    running_total += points;
+                    ComponentScore {
+                        event_id: component.id,
+                        discipline,
+                        mark,
+                        points,
+                        running_total,
+                    }
+                })
+                .collect();
+            CombinedStanding {
+                rank: 0,
+                athlete_id,
+                points: running_total,
+                scores,
+            }
+        })
+        .collect();
+    standings.sort_by_key(|standing| std::cmp::Reverse(standing.points));
+    for i in 0..standings.len() {
+        standings[i].rank = match i {
+            0 => 1,
+            _ if standings[i - 1].points == standings[i].points => standings[i - 1].rank,
+            _ => i as u32 + 1,
+        };
+    }
+    standings
+}
+
+/// Get the running scores of a combined event, worked out from the results of its component events
+/// Component events are the events of the same competition that name this event as their `combined_event_id`.
+async fn get_combined_standings(
+    Path(event_id): Path<Uuid>,
+    State(db_client): State<Client>,
+) -> Result<Json<Vec<CombinedStanding>>, ApiError> {
+    let event = fetch_existing_item::<Event>(&db_client, event_id).await?;
+    let (discipline, gender) = (
+        event.event_data.discipline,
+        event.event_data.category.gender,
+    );
+    if components(discipline, gender).is_none() {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "Event {event_id} is not a combined event that can be scored"
+        )));
+    }
+    let mut component_events: Vec<Event> = query_all::<Event>(
+        &db_client,
+        Some(event::COMPETITION_INDEX),
+        event::COMPETITION_ID_KEY,
+        event.event_data.competition_id,
+    )
+    .await?
+    .into_iter()
+    .filter(|component| component.event_data.combined_event_id == Some(event_id))
+    .collect();
+    component_events.sort_by_key(|component| component.event_data.date_time);
+    let mut results = Vec::new();
+    for component in &component_events {
+        results.extend(
+            query_all::<EventResult>(
+                &db_client,
+                Some(result::EVENT_INDEX),
+                result::EVENT_ID_KEY,
+                component.id,
+            )
+            .await?,
+        );
+    }
+    Ok(Json(combined_standings(
+        gender,
+        &component_events,
+        &results,
+        &event.event_data.athlete_ids,
+    )))
+}
+
+/// The combined event routes nested under `/events`
+pub fn event_scoring_routes() -> axum::Router<Client> {
+    axum::Router::new().route("/:event_id/combined-standings", get(get_combined_standings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routes::discipline::{AgeGroup, Category};
+    use crate::routes::event::EventData;
+    use crate::routes::result::ResultData;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_points_of_1000_point_marks() {
+        let men = [
+            (Discipline::Sprint100m, Mark::Time { seconds: 10.395 }),
+            (Discipline::LongJump, Mark::Distance { meters: 7.76 }),
+            (Discipline::ShotPut, Mark::Distance { meters: 18.4 }),
+            (Discipline::Sprint400m, Mark::Time { seconds: 46.17 }),
+            (Discipline::Hurdles110m, Mark::Time { seconds: 13.8 }),
+            (Discipline::DiscusThrow, Mark::Distance { meters: 56.17 }),
+            (Discipline::JavelinThrow, Mark::Distance { meters: 77.19 }),
+            (Discipline::Run1500m, Mark::Time { seconds: 233.79 }),
+        ];
+        for (discipline, mark) in men {
+            assert_eq!(
+                points(Gender::Men, discipline, &mark),
+                Some(1000),
+                "{discipline}"
+            );
+        }
+        let women = [
+            (Discipline::Hurdles100m, Mark::Time { seconds: 13.85 }),
+            (Discipline::ShotPut, Mark::Distance { meters: 17.07 }),
+            (Discipline::Sprint200m, Mark::Time { seconds: 23.8 }),
+            (Discipline::JavelinThrow, Mark::Distance { meters: 57.18 }),
+            (Discipline::Run800m, Mark::Time { seconds: 127.63 }),
+        ];
+        for (discipline, mark) in women {
+            assert_eq!(
+                points(Gender::Women, discipline, &mark),
+                Some(1000),
+                "{discipline}"
+            );
+        }
+        assert_eq!(
+            points(
+                Gender::Men,
+                Discipline::HighJump,
+                &Mark::Height { meters: 2.21 }
+            ),
+            Some(1002)
+        );
+        // Slower than the b constant scores nothing, and only components are scored
+        assert_eq!(
+            points(
+                Gender::Men,
+                Discipline::Sprint100m,
+                &Mark::Time { seconds: 19.0 }
+            ),
+            Some(0)
+        );
+        assert_eq!(
+            points(
+                Gender::Men,
+                Discipline::Marathon,
+                &Mark::Time { seconds: 7200.0 }
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_components_are_scored() {
+        for combined in [
+            Discipline::Pentathlon,
+            Discipline::Heptathlon,
+            Discipline::Decathlon,
+        ] {
+            for gender in [Gender::Men, Gender::Women] {
+                for component in components(combined, gender).unwrap_or_default() {
+                    assert!(scoring_table(gender, *component).is_some(), "{component}");
+                }
+            }
+        }
+        assert_eq!(
+            components(Discipline::Decathlon, Gender::Men)
+                .unwrap()
+                .len(),
+            10
+        );
+        assert_eq!(components(Discipline::Decathlon, Gender::Mixed), None);
+    }
+
+    #[test]
+    fn test_combined_standings() {
+        let component = |discipline: Discipline, hour: u32| Event {
+            id: Uuid::new_v4(),
+            event_data: EventData {
+                competition_id: Uuid::new_v4(),
+                athlete_ids: Vec::new(),
+                team_ids: Vec::new(),
+                discipline,
+                category: Category {
+                    gender: Gender::Men,
+                    age_group: AgeGroup::default(),
+                },
+                date_time: Utc.with_ymd_and_hms(2024, 5, 1, hour, 0, 0).unwrap(),
+                combined_event_id: None,
+                round_count: 0,
+            },
+        };
+        let result = |event: &Event, athlete_id: Uuid, mark: Mark| {
+            EventResult::from(ResultData {
+                event_id: event.id,
+                athlete_id: Some(athlete_id),
+                team_id: None,
+                round_id: None,
+                place: 1,
+                mark: Some(mark),
+                wind: None,
+                attempts: Vec::new(),
+            })
+        };
+        let sprint = component(Discipline::Sprint100m, 9);
+        let jump = component(Discipline::LongJump, 11);
+        let (leader, chaser, absent) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let results = [
+            result(&sprint, leader, Mark::Time { seconds: 10.395 }),
+            result(&jump, leader, Mark::Distance { meters: 7.76 }),
+            result(&sprint, chaser, Mark::Time { seconds: 10.395 }),
+        ];
+        let standings = combined_standings(
+            Gender::Men,
+            &[sprint, jump],
+            &results,
+            &[absent, chaser, leader],
+        );
+
+        let ranked: Vec<(u32, Uuid, u32)> = standings
+            .iter()
+            .map(|standing| (standing.rank, standing.athlete_id, standing.points))
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![(1, leader, 2000), (2, chaser, 1000), (3, absent, 0)]
+        );
+        assert_eq!(standings[1].scores[1].mark, None);
+        assert_eq!(standings[1].scores[1].running_total, 1000);
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::{
+    self,
+    operation::transact_write_items::TransactWriteItemsError,
+    types::{AttributeValue, ConditionCheck, Delete, Put, TransactWriteItem, Update},
+    Client,
+};
+use uuid::Uuid;
+
+use super::error::ApiError;
+use super::utils::{Item, SetAll};
+
+/// The most steps DynamoDB accepts in one `TransactWriteItems` call
+pub const MAX_TRANSACTION_STEPS: usize = 100;
+
+/// One write of a `TransactWriteItems` call, paired with the error to return if its condition fails
+/// Steps without a condition have no error of their own.
+pub struct TransactionStep {
+    pub item: TransactWriteItem,
+    pub on_condition_failed: Option<ApiError>,
+}
+
+impl TransactionStep {
+    /// A step built by hand, for writes the helpers below do not cover
+    pub fn new(item: TransactWriteItem, on_condition_failed: Option<ApiError>) -> Self {
+        Self {
+            item,
+            on_condition_failed,
+        }
+    }
+}
+
+/// A step that only checks that the item of type `T` with the given primary key exists
+pub fn exists_check<T: Item>(
+    primary_key: Uuid,
+    on_condition_failed: ApiError,
+) -> Result<TransactionStep, ApiError> {
+    let check = ConditionCheck::builder()
+        .table_name(T::table_name())
+        .key(
+            T::partition_key_name(),
+            AttributeValue::S(primary_key.to_string()),
+        )
+        .condition_expression("attribute_exists(#pk)")
+        .expression_attribute_names("#pk", T::partition_key_name())
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok(TransactionStep::new(
+        TransactWriteItem::builder().condition_check(check).build(),
+        Some(on_condition_failed),
+    ))
+}
+
+/// A step that writes `item`, failing if an item with the same key already exists
+pub fn put_new<T: Item>(
+    item: T,
+    on_condition_failed: ApiError,
+) -> Result<TransactionStep, ApiError> {
+    let put = Put::builder()
+        .table_name(T::table_name())
+        .set_item(Some(item.into_hashmap()))
+        .condition_expression("attribute_not_exists(#pk)")
+        .expression_attribute_names("#pk", T::partition_key_name())
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok(TransactionStep::new(
+        TransactWriteItem::builder().put(put).build(),
+        Some(on_condition_failed),
+    ))
+}
+
+/// A step that writes `item`, replacing any item with the same key
+pub fn put<T: Item>(item: T) -> Result<TransactionStep, ApiError> {
+    let put = Put::builder()
+        .table_name(T::table_name())
+        .set_item(Some(item.into_hashmap()))
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok(TransactionStep::new(
+        TransactWriteItem::builder().put(put).build(),
+        None,
+    ))
+}
+
+/// A step that replaces the stored row `stored` with `item`, removing the attributes `item` does not have
+/// Like `update_attributes`, it fails if the item was deleted or changed since `stored` was read.
+pub fn update_existing<T: Item>(
+    primary_key: Uuid,
+    stored: HashMap<String, AttributeValue>,
+    item: T,
+    on_condition_failed: ApiError,
+) -> Result<TransactionStep, ApiError> {
+    let update = SetAll::replacing(stored, item)?;
+    let update = Update::builder()
+        .table_name(T::table_name())
+        .key(
+            T::partition_key_name(),
+            AttributeValue::S(primary_key.to_string()),
+        )
+        .condition_expression(update.condition)
+        .update_expression(update.expression)
+        .set_expression_attribute_names(Some(update.names))
+        .set_expression_attribute_values(Some(update.values))
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok(TransactionStep::new(
+        TransactWriteItem::builder().update(update).build(),
+        Some(on_condition_failed),
+    ))
+}
+
+/// A step that deletes the item of type `T` with the given key, if there is one
+/// `key` holds every key attribute, so this also works for tables with a sort key.
+pub fn delete<T: Item>(key: HashMap<String, AttributeValue>) -> Result<TransactionStep, ApiError> {
+    let delete = Delete::builder()
+        .table_name(T::table_name())
+        .set_key(Some(key))
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok(TransactionStep::new(
+        TransactWriteItem::builder().delete(delete).build(),
+        None,
+    ))
+}
+
+/// Run every step in a single `TransactWriteItems` call
+/// If the transaction is cancelled because a condition failed, the error of the first failing step is returned.
+/// A transaction cancelled by a concurrent one is a 409 and a throttled one a 503, both of which can be retried.
+pub async fn transact(db_client: &Client, steps: Vec<TransactionStep>) -> Result<(), ApiError> {
+    if steps.len() > MAX_TRANSACTION_STEPS {
+        return Err(ApiError::UnprocessableEntity(format!(
+            "The request needs {} writes, but at most {MAX_TRANSACTION_STEPS} can be made together",
+            steps.len()
+        )));
+    }
+    let (items, mut errors): (Vec<TransactWriteItem>, Vec<Option<ApiError>>) = steps
+        .into_iter()
+        .map(|step| (step.item, step.on_condition_failed))
+        .unzip();
+    let result = db_client
+        .transact_write_items()
+        .set_transact_items(Some(items))
+        .send()
+        .await;
+    let err = match result {
+        Ok(_) => return Ok(()),
+        Err(err) => err,
+    };
+    if let Some(TransactWriteItemsError::TransactionCanceledException(cancelled)) =
+        err.as_service_error()
+    {
+        // The reasons line up with the steps, and steps that did not fail have the code "None"
+        let failed = cancelled
+            .cancellation_reasons()
+            .iter()
+            .position(|reason| reason.code() == Some("ConditionalCheckFailed"));
+        if let Some(error) = failed.and_then(|index| errors.swap_remove(index)) {
+            return Err(error);
+        }
+        // Cancellations that are not the fault of the request are worth retrying, like their single item forms
+        for reason in cancelled.cancellation_reasons() {
+            match reason.code() {
+                Some("TransactionConflict") => {
+                    return Err(ApiError::Conflict(
+                        "The request conflicts with another request in progress, please retry it"
+                            .to_string(),
+                    ))
+                }
+                Some(
+                    "ProvisionedThroughputExceeded" | "ThrottlingError" | "RequestLimitExceeded",
+                ) => {
+                    return Err(ApiError::ServiceUnavailable(
+                        "The database is busy, please retry the request later".to_string(),
+                    ))
+                }
+                _ => {}
+            }
+        }
+    }
+    Err(ApiError::from(err))
+}
@@ -1,39 +1,107 @@
 use std::collections::HashMap;
 
-use super::user_athlete::UserAthlete;
-use super::utils::Item;
-use super::utils::{add_item, delete_item, get_item};
-use aws_sdk_dynamodb::{self, types::AttributeValue, Client};
+use super::athlete::{find_by_user, Athlete};
+use super::athlete_manager;
+use super::error::ApiError;
+use super::feed::get_feed;
+use super::permission::{Caller, Guarded, Role};
+use super::transaction::{
+    self, exists_check, put_new, transact, TransactionStep, MAX_TRANSACTION_STEPS,
+};
+use super::user_athlete::{self, UserAthlete};
+use super::username;
+use super::utils::fetch_items;
+use super::utils::{
+    fetch_existing_item, fetch_item, not_found, patch_item, query_all, update_item,
+};
+use super::utils::{get_number, get_string, get_uuid, Item, ItemDecodeError};
+use aws_sdk_dynamodb::{
+    self,
+    types::{AttributeValue, ReturnValue, TransactWriteItem, Update},
+    Client,
+};
 use axum::extract::{Path, State};
-use axum::response::Response;
-use axum::routing::{delete, get, post};
+use axum::http::StatusCode;
+use axum::routing::{delete, get, patch, post, put};
 use axum::Json;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use uuid::Uuid;
 
 pub const TABLE_NAME: &str = "users";
 pub const ID_KEY: &str = "id";
 pub const USERNAME_KEY: &str = "username";
-pub const ATHLETES_FOLLOWING_KEY: &str = "athletes_following";
+/// The string set of followed athletes users held before follows moved to the `user_athlete` table
+pub const LEGACY_ATHLETES_FOLLOWING_KEY: &str = "athletes_following";
+pub const PASSWORD_HASH_KEY: &str = "password_hash";
+pub const ROLE_KEY: &str = "role";
+pub const TOKEN_VERSION_KEY: &str = "token_version";
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-struct User {
-    id: Uuid,
+pub struct User {
+    pub id: Uuid,
     #[serde(flatten)]
     user_data: UserData,
+    /// Only admins can change roles, through `PUT /users/:id/role`
+    #[serde(default)]
+    role: Role,
+    /// The athletes the user follows, which are read from the `user_athlete` table rather than stored on the user
+    /// (see `with_following`) and changed through `/users/:user_id/follow/:athlete_id`. Left out of lists of users,
+    /// which are not read with their follows.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    athletes_following: Option<Vec<Uuid>>,
+    /// The Argon2 hash of the password of the user, which is never sent to or taken from clients
+    #[serde(skip)]
+    password_hash: Option<String>,
+    /// Refresh tokens are only accepted with the current version, which moves on every time one is used
+    #[serde(skip)]
+    token_version: u32,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 struct UserData {
     username: String,
-    athletes_following: Vec<Uuid>,
 }
 
 impl From<UserData> for User {
     fn from(user_data: UserData) -> Self {
         let id = Uuid::new_v4();
-        Self { id, user_data }
+        Self {
+            id,
+            user_data,
+            role: Role::Fan,
+            athletes_following: None,
+            password_hash: None,
+            token_version: 0,
+        }
+    }
+}
+
+impl User {
+    /// A new user that signs in with the password `password_hash` was made from
+    pub fn with_password(username: String, password_hash: String, role: Role) -> Self {
+        Self {
+            role,
+            password_hash: Some(password_hash),
+            ..Self::from(UserData { username })
+        }
+    }
+
+    pub fn username(&self) -> &str {
+        &self.user_data.username
+    }
+
+    pub fn password_hash(&self) -> Option<&str> {
+        self.password_hash.as_deref()
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn token_version(&self) -> u32 {
+        self.token_version
     }
 }
 
@@ -45,66 +113,467 @@ impl Item for User {
         ID_KEY
     }
 
-    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Option<Self> {
-        let id = map.get(ID_KEY)?.as_s().unwrap();
-        let id = Uuid::parse_str(id).unwrap();
-        let username: String = map.get(USERNAME_KEY)?.as_s().unwrap().to_string();
-        let athletes_following: Vec<Uuid> = map
-            .get(ATHLETES_FOLLOWING_KEY)?
-            .as_ss()
-            .unwrap()
-            .iter()
-            .map(|s| Uuid::parse_str(s).unwrap())
-            .collect();
-        Some(Self {
+    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError> {
+        let id = get_uuid::<Self>(&map, ID_KEY)?;
+        let username = get_string::<Self>(&map, USERNAME_KEY)?;
+        // Users created before roles existed are fans
+        let role = match map.contains_key(ROLE_KEY) {
+            true => {
+                let role = get_string::<Self>(&map, ROLE_KEY)?;
+                Role::parse(&role).ok_or_else(|| {
+                    ItemDecodeError::invalid::<Self>(
+                        &map,
+                        ROLE_KEY,
+                        format!("no role named {role}"),
+                    )
+                })?
+            }
+            false => Role::Fan,
+        };
+        // Users created before sign in existed have no password
+        let password_hash = match map.contains_key(PASSWORD_HASH_KEY) {
+            true => Some(get_string::<Self>(&map, PASSWORD_HASH_KEY)?),
+            false => None,
+        };
+        // Users that never signed in have no token version yet
+        let token_version = match map.contains_key(TOKEN_VERSION_KEY) {
+            true => get_number::<Self, u32>(&map, TOKEN_VERSION_KEY)?,
+            false => 0,
+        };
+        Ok(Self {
             id,
-            user_data: UserData {
-                username,
-                athletes_following,
-            },
+            user_data: UserData { username },
+            role,
+            athletes_following: None,
+            password_hash,
+            token_version,
         })
     }
 
     fn into_hashmap(self) -> HashMap<String, AttributeValue> {
         let mut map = HashMap::new();
-        // collect the list of athletes_following into a list of strings
-        let athletes_following: Vec<String> = self
-            .user_data
-            .athletes_following
-            .iter()
-            .map(|uuid| uuid.to_string())
-            .collect();
         map.insert(ID_KEY.to_string(), AttributeValue::S(self.id.to_string()));
         map.insert(
             USERNAME_KEY.to_string(),
             AttributeValue::S(self.user_data.username),
         );
         map.insert(
-            ATHLETES_FOLLOWING_KEY.to_string(),
-            AttributeValue::Ss(athletes_following),
+            ROLE_KEY.to_string(),
+            AttributeValue::S(self.role.as_str().to_string()),
+        );
+        if let Some(password_hash) = self.password_hash {
+            map.insert(
+                PASSWORD_HASH_KEY.to_string(),
+                AttributeValue::S(password_hash),
+            );
+        }
+        map.insert(
+            TOKEN_VERSION_KEY.to_string(),
+            AttributeValue::N(self.token_version.to_string()),
         );
         map
     }
 }
 
+/// Fill in the athletes the user follows from the `user_athlete` table
+pub async fn with_following(db_client: &Client, mut user: User) -> Result<User, ApiError> {
+    let follows =
+        query_all::<UserAthlete>(db_client, None, user_athlete::USER_ID_KEY, user.id).await?;
+    user.athletes_following = Some(follows.iter().map(|follow| follow.athlete_id).collect());
+    Ok(user)
+}
+
+impl Guarded for User {
+    /// Users sign themselves up through `/auth/register`, so only admins create them directly
+    async fn authorize_create(
+        &mut self,
+        _db_client: &Client,
+        caller: &Caller,
+    ) -> Result<(), ApiError> {
+        caller.require_role(&[], "create users")
+    }
+
+    async fn authorize_modify(&self, _db_client: &Client, caller: &Caller) -> Result<(), ApiError> {
+        caller.require_self(self.id, "change other users")
+    }
+
+    /// The role, password and token version are not part of the body of a replace, so they are carried over from
+    /// the stored user. The username is reserved separately, so it can only change through
+    /// `PUT /users/:id/username`.
+    async fn authorize_replace(
+        &mut self,
+        old: &Self,
+        db_client: &Client,
+        caller: &Caller,
+    ) -> Result<(), ApiError> {
+        old.authorize_modify(db_client, caller).await?;
+        if self.username() != old.username() {
+            return Err(ApiError::UnprocessableEntity(format!(
+                "Change the username through PUT /users/{}/username",
+                old.id
+            )));
+        }
+        self.role = old.role;
+        self.password_hash = old.password_hash.clone();
+        self.token_version = old.token_version;
+        Ok(())
+    }
+}
+
+/// A user as `GET /users/:id` returns it, with the athlete profile it manages
+#[derive(Debug, Serialize)]
+struct UserProfile {
+    #[serde(flatten)]
+    user: User,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    athlete: Option<Athlete>,
+}
+
+impl UserProfile {
+    async fn of(db_client: &Client, user: User) -> Result<Self, ApiError> {
+        let athlete = find_by_user(db_client, user.id).await?;
+        let user = with_following(db_client, user).await?;
+        Ok(Self { user, athlete })
+    }
+}
+
+async fn get_user(
+    State(db_client): State<Client>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<UserProfile>, ApiError> {
+    let user = fetch_existing_item::<User>(&db_client, user_id).await?;
+    UserProfile::of(&db_client, user).await.map(Json)
+}
+
+/// Get the user with the username, whatever its case
+async fn get_user_by_username(
+    State(db_client): State<Client>,
+    Path(name): Path<String>,
+) -> Result<Json<UserProfile>, ApiError> {
+    let user = username::find_user(&db_client, &name)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("No user is called {name}")))?;
+    UserProfile::of(&db_client, user).await.map(Json)
+}
+
+/// Store a new user, which only admins can do, reserving its username in the same transaction
+async fn add_user(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Json(user_data): Json<UserData>,
+) -> Result<Json<User>, ApiError> {
+    let mut user = User::from(user_data);
+    user.authorize_create(&db_client, &caller).await?;
+    user.user_data.username = username::validate(user.username())?;
+    store_new_user(&db_client, user).await.map(Json)
+}
+
+/// Store a new user and reserve its username, failing with a 409 if the username is taken
+pub async fn store_new_user(db_client: &Client, mut user: User) -> Result<User, ApiError> {
+    // A new user follows nobody yet
+    user.athletes_following = Some(Vec::new());
+    transact(
+        db_client,
+        vec![
+            username::reserve(user.username(), user.id)?,
+            put_new(
+                user.clone(),
+                ApiError::Conflict(format!("User {} already exists", user.id)),
+            )?,
+        ],
+    )
+    .await?;
+    Ok(user)
+}
+
+/// Delete a user, free its username, part it from the athlete it manages and drop its follows
+/// Deleting a user that does not exist still succeeds. The follows that do not fit in the transaction that deletes
+/// the user are deleted after it.
+async fn delete_user(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    let Some(user) = fetch_item::<User>(&db_client, user_id).await? else {
+        return Ok(StatusCode::OK);
+    };
+    user.authorize_modify(&db_client, &caller).await?;
+    let key = HashMap::from([(ID_KEY.to_string(), AttributeValue::S(user_id.to_string()))]);
+    let mut steps = vec![
+        transaction::delete::<User>(key)?,
+        username::release(user.username(), user_id)?,
+    ];
+    steps.extend(athlete_manager::release(&db_client, user_id).await?);
+    let follows =
+        query_all::<UserAthlete>(&db_client, None, user_athlete::USER_ID_KEY, user_id).await?;
+    let mut follows = follows
+        .iter()
+        .map(|follow| transaction::delete::<UserAthlete>(follow.key()))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .peekable();
+    steps.extend(follows.by_ref().take(MAX_TRANSACTION_STEPS - steps.len()));
+    transact(&db_client, steps).await?;
+    while follows.peek().is_some() {
+        transact(
+            &db_client,
+            follows.by_ref().take(MAX_TRANSACTION_STEPS).collect(),
+        )
+        .await?;
+    }
+    Ok(StatusCode::OK)
+}
+
+#[derive(Debug, Deserialize)]
+struct UsernameChange {
+    username: String,
+}
+
+/// Give the user a new username
+/// The user, the old reservation and the new one are written in one transaction, so the old username is only
+/// freed if the new one could be reserved.
+async fn change_username(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Path(user_id): Path<Uuid>,
+    Json(change): Json<UsernameChange>,
+) -> Result<Json<User>, ApiError> {
+    caller.require_self(user_id, "change the username of other users")?;
+    let new_username = username::validate(&change.username)?;
+    let mut user = fetch_existing_item::<User>(&db_client, user_id).await?;
+    let rename = Update::builder()
+        .table_name(TABLE_NAME)
+        .key(ID_KEY, AttributeValue::S(user_id.to_string()))
+        .condition_expression("#username = :old")
+        .update_expression("SET #username = :new")
+        .expression_attribute_names("#username", USERNAME_KEY)
+        .expression_attribute_values(":old", AttributeValue::S(user.username().to_string()))
+        .expression_attribute_values(":new", AttributeValue::S(new_username.clone()))
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    let mut steps = vec![TransactionStep::new(
+        TransactWriteItem::builder().update(rename).build(),
+        Some(ApiError::Conflict(format!(
+            "User {user_id} was changed or deleted, please retry the request"
+        ))),
+    )];
+    // Changing only the case of the username keeps the same reservation
+    if username::normalize(&new_username) != username::normalize(user.username()) {
+        steps.push(username::release(user.username(), user_id)?);
+        steps.push(username::reserve(&new_username, user_id)?);
+    }
+    transact(&db_client, steps).await?;
+    user.user_data.username = new_username;
+    with_following(&db_client, user).await.map(Json)
+}
+
+/// Replace the user (PUT), responding with the athletes it follows
+async fn update_user(
+    caller: Caller,
+    Path(user_id): Path<Uuid>,
+    State(db_client): State<Client>,
+    Json(user_data): Json<UserData>,
+) -> Result<Json<User>, ApiError> {
+    let Json(user) = update_item::<User, UserData>(
+        caller,
+        Path(user_id),
+        State(db_client.clone()),
+        Json(user_data),
+    )
+    .await?;
+    with_following(&db_client, user).await.map(Json)
+}
+
+/// Partially update the user (PATCH), responding with the athletes it follows
+async fn patch_user(
+    caller: Caller,
+    Path(user_id): Path<Uuid>,
+    State(db_client): State<Client>,
+    Json(patch): Json<Value>,
+) -> Result<Json<User>, ApiError> {
+    let Json(user) =
+        patch_item::<User, UserData>(caller, Path(user_id), State(db_client.clone()), Json(patch))
+            .await?;
+    with_following(&db_client, user).await.map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+struct RoleChange {
+    role: Role,
+}
+
+/// Give a user a new role, which only admins can do
+async fn set_role(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Path(user_id): Path<Uuid>,
+    Json(change): Json<RoleChange>,
+) -> Result<Json<User>, ApiError> {
+    caller.require_role(&[], "change roles")?;
+    let user = store_role(&db_client, user_id, change.role).await?;
+    with_following(&db_client, user).await.map(Json)
+}
+
+/// Set the role of a stored user without touching the rest of it
+pub async fn store_role(db_client: &Client, user_id: Uuid, role: Role) -> Result<User, ApiError> {
+    let result = db_client
+        .update_item()
+        .table_name(TABLE_NAME)
+        .key(ID_KEY, AttributeValue::S(user_id.to_string()))
+        .condition_expression("attribute_exists(#pk)")
+        .update_expression("SET #role = :role")
+        .expression_attribute_names("#pk", ID_KEY)
+        .expression_attribute_names("#role", ROLE_KEY)
+        .expression_attribute_values(":role", AttributeValue::S(role.as_str().to_string()))
+        .return_values(ReturnValue::AllNew)
+        .send()
+        .await
+        .map_err(|err| {
+            if err
+                .as_service_error()
+                .is_some_and(|err| err.is_conditional_check_failed_exception())
+            {
+                not_found::<User>(user_id)
+            } else {
+                ApiError::from(err)
+            }
+        })?;
+    Ok(User::from_hashmap(result.attributes.unwrap_or_default())?)
+}
+
+/// Move the token version of a stored user on from `version`, revoking the refresh tokens issued with it
+/// Returns `None` if the version already moved on, i.e. the refresh token was already used or revoked.
+pub async fn next_token_version(
+    db_client: &Client,
+    user_id: Uuid,
+    version: u32,
+) -> Result<Option<User>, ApiError> {
+    // Users that never signed in have no version stored, which counts as 0
+    let condition = match version {
+        0 => "attribute_exists(#pk) AND (attribute_not_exists(#version) OR #version = :version)",
+        _ => "attribute_exists(#pk) AND #version = :version",
+    };
+    let result = db_client
+        .update_item()
+        .table_name(TABLE_NAME)
+        .key(ID_KEY, AttributeValue::S(user_id.to_string()))
+        .condition_expression(condition)
+        .update_expression("SET #version = :next")
+        .expression_attribute_names("#pk", ID_KEY)
+        .expression_attribute_names("#version", TOKEN_VERSION_KEY)
+        .expression_attribute_values(":version", AttributeValue::N(version.to_string()))
+        .expression_attribute_values(":next", AttributeValue::N((version + 1).to_string()))
+        .return_values(ReturnValue::AllNew)
+        .send()
+        .await;
+    match result {
+        Ok(result) => Ok(Some(User::from_hashmap(
+            result.attributes.unwrap_or_default(),
+        )?)),
+        Err(err)
+            if err
+                .as_service_error()
+                .is_some_and(|err| err.is_conditional_check_failed_exception()) =>
+        {
+            Ok(None)
+        }
+        Err(err) => Err(ApiError::from(err)),
+    }
+}
+
 async fn add_user_athlete(
+    caller: Caller,
     State(db_client): State<Client>,
     Path((user_id, athlete_id)): Path<(Uuid, Uuid)>,
-) -> Response {
-    // - Validate user_id and athlete_id (For now, no validation is needed)
-    // - Create a UserAthlete object
+) -> Result<Json<UserAthlete>, ApiError> {
+    caller.require_self(user_id, "change who other users follow")?;
     let user_athlete = UserAthlete::new(user_id, athlete_id);
-    // - Add the UserAthlete to the table/database
-    add_item::<UserAthlete, UserAthlete>(State(db_client), Json(user_athlete)).await
+    // Check that the user and the athlete exist in the same transaction that adds the follow,
+    // so neither can be deleted in between
+    transact(
+        &db_client,
+        vec![
+            exists_check::<User>(
+                user_id,
+                ApiError::NotFound(format!("User {user_id} does not exist")),
+            )?,
+            exists_check::<Athlete>(
+                athlete_id,
+                ApiError::NotFound(format!("Athlete {athlete_id} does not exist")),
+            )?,
+            put_new(
+                user_athlete.clone(),
+                ApiError::Conflict(format!(
+                    "User {user_id} already follows athlete {athlete_id}"
+                )),
+            )?,
+        ],
+    )
+    .await?;
+    Ok(Json(user_athlete))
+}
+
+/// Stop the user from following the athlete
+/// The athlete is also taken out of the legacy set of a user that was not migrated yet, so the `user-follows`
+/// migration does not follow it again. Unfollowing an athlete the user does not follow still succeeds
+async fn remove_user_athlete(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Path((user_id, athlete_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, ApiError> {
+    caller.require_self(user_id, "change who other users follow")?;
+    let forget_legacy = Update::builder()
+        .table_name(TABLE_NAME)
+        .key(ID_KEY, AttributeValue::S(user_id.to_string()))
+        .condition_expression("attribute_exists(#pk)")
+        .update_expression("DELETE #legacy :athlete")
+        .expression_attribute_names("#pk", ID_KEY)
+        .expression_attribute_names("#legacy", LEGACY_ATHLETES_FOLLOWING_KEY)
+        .expression_attribute_values(":athlete", AttributeValue::Ss(vec![athlete_id.to_string()]))
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    transact(
+        &db_client,
+        vec![
+            transaction::delete::<UserAthlete>(UserAthlete::new(user_id, athlete_id).key())?,
+            TransactionStep::new(
+                TransactWriteItem::builder().update(forget_legacy).build(),
+                Some(ApiError::NotFound(format!("User {user_id} does not exist"))),
+            ),
+        ],
+    )
+    .await?;
+    Ok(StatusCode::OK)
+}
+
+/// Get every athlete the user follows
+async fn get_following(
+    State(db_client): State<Client>,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<Vec<Athlete>>, ApiError> {
+    fetch_existing_item::<User>(&db_client, user_id).await?;
+    let follows =
+        query_all::<UserAthlete>(&db_client, None, user_athlete::USER_ID_KEY, user_id).await?;
+    let athlete_ids: Vec<Uuid> = follows.iter().map(|follow| follow.athlete_id).collect();
+    Ok(Json(
+        fetch_items::<Athlete>(&db_client, &athlete_ids).await?,
+    ))
 }
 
 pub fn user_routes() -> axum::Router<Client> {
     axum::Router::new()
-        .route("/", post(add_item::<User, UserData>))
-        .route("/:id", get(get_item::<User>))
-        .route("/:id", delete(delete_item::<User>))
+        .route("/", post(add_user))
+        .route("/by-username/:name", get(get_user_by_username))
+        .route("/:id", get(get_user))
+        .route("/:id", delete(delete_user))
+        .route("/:id", put(update_user))
+        .route("/:id", patch(patch_user))
+        .route("/:id/role", put(set_role))
+        .route("/:id/username", put(change_username))
         .route("/:user_id/follow/:athlete_id", post(add_user_athlete))
-    // .route("/:user_id/follow/:athlete_id", delete(remove_user_athlete))
+        .route("/:user_id/follow/:athlete_id", delete(remove_user_athlete))
+        .route("/:user_id/following", get(get_following))
+        .route("/:user_id/feed", get(get_feed))
 }
 
 #[cfg(test)]
@@ -116,12 +585,29 @@ mod tests {
             id: Uuid::new_v4(),
             user_data: UserData {
                 username: "test".to_string(),
-                athletes_following: vec![Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()],
             },
+            role: Role::MeetDirector,
+            athletes_following: None,
+            password_hash: Some("$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA".to_string()),
+            token_version: 3,
         };
         let cloned_user = user.clone();
         let map = user.into_hashmap();
         let user2 = User::from_hashmap(map).unwrap();
         assert_eq!(cloned_user, user2);
     }
+
+    #[test]
+    fn test_follows_are_not_stored_on_the_user() {
+        let mut user = User::with_password("test".to_string(), "hash".to_string(), Role::Fan);
+        user.athletes_following = Some(vec![Uuid::new_v4()]);
+        let mut map = user.into_hashmap();
+        assert!(!map.contains_key(LEGACY_ATHLETES_FOLLOWING_KEY));
+        // Users written before the migration can still hold a list, which is ignored
+        map.insert(
+            LEGACY_ATHLETES_FOLLOWING_KEY.to_string(),
+            AttributeValue::Ss(vec![Uuid::new_v4().to_string()]),
+        );
+        assert_eq!(User::from_hashmap(map).unwrap().athletes_following, None);
+    }
 }
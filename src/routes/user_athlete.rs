@@ -1,4 +1,4 @@
-use super::utils::Item;
+use super::utils::{get_uuid, Item, ItemDecodeError};
 use aws_sdk_dynamodb::{self, types::AttributeValue};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -7,11 +7,13 @@ use uuid::Uuid;
 pub const TABLE_NAME: &str = "user_athlete";
 pub const USER_ID_KEY: &str = "user_id";
 pub const ATHLETE_ID_KEY: &str = "athlete_id";
+/// Follows of an athlete, keyed by the athlete so their followers can be queried
+pub const ATHLETE_INDEX: &str = "athlete_id-user_id-index";
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct UserAthlete {
-    user_id: Uuid,
-    athlete_id: Uuid,
+    pub user_id: Uuid,
+    pub athlete_id: Uuid,
 }
 // make a new user athlete
 impl UserAthlete {
@@ -21,6 +23,11 @@ impl UserAthlete {
             athlete_id,
         }
     }
+
+    /// The full primary key of the follow (the partition key and the sort key), which is the whole row
+    pub fn key(&self) -> HashMap<String, AttributeValue> {
+        self.clone().into_hashmap()
+    }
 }
 
 impl Item for UserAthlete {
@@ -32,14 +39,11 @@ impl Item for UserAthlete {
         USER_ID_KEY
     }
 
-    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Option<Self> {
-        let user_id = map.get(USER_ID_KEY)?.as_s().ok()?;
-        let user_id = Uuid::parse_str(user_id).ok()?;
-
-        let athlete_id = map.get(ATHLETE_ID_KEY)?.as_s().ok()?;
-        let athlete_id = Uuid::parse_str(athlete_id).ok()?;
+    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError> {
+        let user_id = get_uuid::<Self>(&map, USER_ID_KEY)?;
+        let athlete_id = get_uuid::<Self>(&map, ATHLETE_ID_KEY)?;
 
-        Some(Self {
+        Ok(Self {
             user_id,
             athlete_id,
         })
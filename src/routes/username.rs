@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::{
+    self,
+    types::{AttributeValue, Delete, TransactWriteItem},
+    Client,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::error::ApiError;
+use super::transaction::{put_new, TransactionStep};
+use super::user::User;
+use super::utils::{fetch_item, get_string, get_uuid, Item, ItemDecodeError};
+
+pub const TABLE_NAME: &str = "usernames";
+pub const USERNAME_KEY: &str = "username";
+pub const USER_ID_KEY: &str = "user_id";
+
+/// The reservation of a username by a user
+/// Usernames are unique whatever their case, so reservations are keyed by the lowercase username while
+/// the user keeps the case it chose.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Username {
+    pub username: String,
+    pub user_id: Uuid,
+}
+
+impl Username {
+    pub fn new(username: &str, user_id: Uuid) -> Self {
+        Self {
+            username: normalize(username),
+            user_id,
+        }
+    }
+}
+
+impl Item for Username {
+    fn table_name() -> &'static str {
+        TABLE_NAME
+    }
+
+    fn partition_key_name() -> &'static str {
+        USERNAME_KEY
+    }
+
+    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError> {
+        Ok(Self {
+            username: get_string::<Self>(&map, USERNAME_KEY)?,
+            user_id: get_uuid::<Self>(&map, USER_ID_KEY)?,
+        })
+    }
+
+    fn into_hashmap(self) -> HashMap<String, AttributeValue> {
+        HashMap::from([
+            (USERNAME_KEY.to_string(), AttributeValue::S(self.username)),
+            (
+                USER_ID_KEY.to_string(),
+                AttributeValue::S(self.user_id.to_string()),
+            ),
+        ])
+    }
+}
+
+/// The form of a username that is compared for uniqueness
+pub fn normalize(username: &str) -> String {
+    username.trim().to_lowercase()
+}
+
+/// Trim a username a user asked for, checking that something is left
+pub fn validate(username: &str) -> Result<String, ApiError> {
+    let username = username.trim();
+    match username.is_empty() {
+        true => Err(ApiError::UnprocessableEntity(
+            "The username cannot be empty".to_string(),
+        )),
+        false => Ok(username.to_string()),
+    }
+}
+
+/// A step that reserves `username` for the user, failing with a 409 if any user has it in any case
+pub fn reserve(username: &str, user_id: Uuid) -> Result<TransactionStep, ApiError> {
+    put_new(
+        Username::new(username, user_id),
+        ApiError::Conflict(format!("The username {} is taken", username.trim())),
+    )
+}
+
+/// A step that frees `username`, unless it is reserved by another user
+pub fn release(username: &str, user_id: Uuid) -> Result<TransactionStep, ApiError> {
+    let delete = Delete::builder()
+        .table_name(TABLE_NAME)
+        .key(USERNAME_KEY, AttributeValue::S(normalize(username)))
+        .condition_expression("attribute_not_exists(#pk) OR #user = :user")
+        .expression_attribute_names("#pk", USERNAME_KEY)
+        .expression_attribute_names("#user", USER_ID_KEY)
+        .expression_attribute_values(":user", AttributeValue::S(user_id.to_string()))
+        .build()
+        .map_err(|err| ApiError::Internal(err.to_string()))?;
+    Ok(TransactionStep::new(
+        TransactWriteItem::builder().delete(delete).build(),
+        Some(ApiError::Conflict(format!(
+            "The username {} is reserved by another user",
+            username.trim()
+        ))),
+    ))
+}
+
+/// The user that has `username`, in any case
+pub async fn find_user(db_client: &Client, username: &str) -> Result<Option<User>, ApiError> {
+    let reservation = db_client
+        .get_item()
+        .table_name(TABLE_NAME)
+        .key(USERNAME_KEY, AttributeValue::S(normalize(username)))
+        .send()
+        .await?
+        .item
+        .map(Username::from_hashmap)
+        .transpose()?;
+    match reservation {
+        Some(reservation) => fetch_item::<User>(db_client, reservation.user_id).await,
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usernames_are_case_insensitive() {
+        let user_id = Uuid::new_v4();
+        assert_eq!(
+            Username::new("  Derek ", user_id),
+            Username::new("derek", user_id)
+        );
+        let reservation = Username::new("DEREK", user_id);
+        let map = reservation.clone().into_hashmap();
+        assert_eq!(map[USERNAME_KEY], AttributeValue::S("derek".to_string()));
+        assert_eq!(Username::from_hashmap(map).unwrap(), reservation);
+        assert!(validate("   ").is_err());
+        assert_eq!(validate(" Derek ").unwrap(), "Derek");
+    }
+}
@@ -1,15 +1,27 @@
-use std::{collections::HashMap, fmt::Debug};
+use std::{collections::HashMap, fmt, fmt::Debug, str::FromStr, time::Duration};
 
-use aws_sdk_dynamodb::{self, types::AttributeValue, Client};
+use aws_sdk_dynamodb::{
+    self,
+    operation::update_item::UpdateItemError,
+    types::{AttributeValue, KeysAndAttributes, ReturnValue, ReturnValuesOnConditionCheckFailure},
+    Client,
+};
 use axum::{
-    extract::{Path, State},
-    response::{IntoResponse, Response},
+    extract::{Path, Query, State},
+    http::StatusCode,
     Json,
 };
-use serde::Serialize;
-use tracing::{info, instrument};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, instrument, warn};
 use uuid::Uuid;
 
+use super::error::ApiError;
+use super::filter::{Expression, ListFilter, ListQuery};
+use super::pagination::{encode_cursor, Page, PageParams};
+use super::permission::{Caller, Guarded};
+
 /// A item is something that can be stored in the database
 /// It must be able to convert itself into a hashmap and be created from a hashmap
 ///
@@ -17,41 +29,224 @@ pub trait Item {
     fn table_name() -> &'static str;
     fn partition_key_name() -> &'static str;
     fn into_hashmap(self) -> HashMap<String, AttributeValue>;
-    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Option<Self>
+    fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError>
     where
         Self: Sized;
 }
 
-/// An endpoint that will return all items in the database
-#[instrument(skip(db_client))]
-pub async fn get_items<T: Serialize + Item>(db_client: State<Client>) -> Response {
-    info!("Getting all items from table {}", T::table_name());
-    let result = db_client.scan().table_name(T::table_name()).send().await;
-    match result {
-        Ok(result) => {
-            let items = result.items.unwrap();
-            let converted_items: Vec<T> = items
-                .into_iter()
-                .filter_map(|item| T::from_hashmap(item))
-                .collect();
-            Json(converted_items).into_response()
-        }
-        Err(err) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            err.to_string(),
-        )
-            .into_response(),
+/// Store a list of UUIDs as a list attribute
+/// A list is used rather than a string set because DynamoDB does not allow empty sets.
+pub fn uuid_list(ids: &[Uuid]) -> AttributeValue {
+    AttributeValue::L(
+        ids.iter()
+            .map(|id| AttributeValue::S(id.to_string()))
+            .collect(),
+    )
+}
+
+/// A global secondary index on a table
+/// All key attributes in this app are strings, and every index projects all attributes.
+#[derive(Clone, Copy, Debug)]
+pub struct SecondaryIndex {
+    pub name: &'static str,
+    pub partition_key: &'static str,
+    pub sort_key: Option<&'static str>,
+}
+
+/// The reasons a row read from DynamoDB can fail to convert into an `Item`
+/// Every variant names the table, the partition key of the row (when it could be read) and the attribute that failed
+#[derive(Clone, Debug, PartialEq)]
+pub enum ItemDecodeError {
+    MissingAttribute {
+        table: &'static str,
+        key: Option<String>,
+        attribute: String,
+    },
+    WrongType {
+        table: &'static str,
+        key: Option<String>,
+        attribute: String,
+        expected: &'static str,
+    },
+    InvalidValue {
+        table: &'static str,
+        key: Option<String>,
+        attribute: String,
+        reason: String,
+    },
+}
+
+impl ItemDecodeError {
+    fn missing<T: Item>(map: &HashMap<String, AttributeValue>, attribute: &str) -> Self {
+        Self::MissingAttribute {
+            table: T::table_name(),
+            key: partition_key_value::<T>(map),
+            attribute: attribute.to_string(),
+        }
+    }
+
+    fn wrong_type<T: Item>(
+        map: &HashMap<String, AttributeValue>,
+        attribute: &str,
+        expected: &'static str,
+    ) -> Self {
+        Self::WrongType {
+            table: T::table_name(),
+            key: partition_key_value::<T>(map),
+            attribute: attribute.to_string(),
+            expected,
+        }
+    }
+
+    pub fn invalid<T: Item>(
+        map: &HashMap<String, AttributeValue>,
+        attribute: &str,
+        reason: impl ToString,
+    ) -> Self {
+        Self::InvalidValue {
+            table: T::table_name(),
+            key: partition_key_value::<T>(map),
+            attribute: attribute.to_string(),
+            reason: reason.to_string(),
+        }
     }
 }
 
-/// Endpoint that will accept a primary_key in the path and return the item that has that primary key
-///
-#[instrument(skip(db_client))]
-pub async fn get_item<T: Serialize + Item>(
-    Path(primary_key): Path<Uuid>,
-    State(db_client): State<Client>,
-) -> Response {
-    info!("Getting item from table {}", T::table_name());
+impl fmt::Display for ItemDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (table, key, attribute) = match self {
+            Self::MissingAttribute {
+                table,
+                key,
+                attribute,
+            }
+            | Self::WrongType {
+                table,
+                key,
+                attribute,
+                ..
+            }
+            | Self::InvalidValue {
+                table,
+                key,
+                attribute,
+                ..
+            } => (table, key, attribute),
+        };
+        let key = key.as_deref().unwrap_or("<unknown>");
+        write!(f, "item {key} in table {table}: attribute {attribute} ")?;
+        match self {
+            Self::MissingAttribute { .. } => write!(f, "is missing"),
+            Self::WrongType { expected, .. } => write!(f, "is not of type {expected}"),
+            Self::InvalidValue { reason, .. } => write!(f, "is invalid: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ItemDecodeError {}
+
+/// Read the partition key of a row as a string, if it is present and a string
+pub fn partition_key_value<T: Item>(map: &HashMap<String, AttributeValue>) -> Option<String> {
+    map.get(T::partition_key_name())?.as_s().ok().cloned()
+}
+
+/// Read a string attribute of an item of type `T`
+pub fn get_string<T: Item>(
+    map: &HashMap<String, AttributeValue>,
+    attribute: &str,
+) -> Result<String, ItemDecodeError> {
+    map.get(attribute)
+        .ok_or_else(|| ItemDecodeError::missing::<T>(map, attribute))?
+        .as_s()
+        .map(|s| s.to_string())
+        .map_err(|_| ItemDecodeError::wrong_type::<T>(map, attribute, "S"))
+}
+
+/// Read a number attribute of an item of type `T`
+pub fn get_number<T: Item, N>(
+    map: &HashMap<String, AttributeValue>,
+    attribute: &str,
+) -> Result<N, ItemDecodeError>
+where
+    N: FromStr,
+    N::Err: fmt::Display,
+{
+    map.get(attribute)
+        .ok_or_else(|| ItemDecodeError::missing::<T>(map, attribute))?
+        .as_n()
+        .map_err(|_| ItemDecodeError::wrong_type::<T>(map, attribute, "N"))?
+        .parse()
+        .map_err(|e: N::Err| ItemDecodeError::invalid::<T>(map, attribute, e))
+}
+
+/// Read a string attribute that holds a UUID
+pub fn get_uuid<T: Item>(
+    map: &HashMap<String, AttributeValue>,
+    attribute: &str,
+) -> Result<Uuid, ItemDecodeError> {
+    let value = get_string::<T>(map, attribute)?;
+    Uuid::parse_str(&value).map_err(|e| ItemDecodeError::invalid::<T>(map, attribute, e))
+}
+
+/// Read a string attribute that holds a date in the format %Y-%m-%d
+pub fn get_date<T: Item>(
+    map: &HashMap<String, AttributeValue>,
+    attribute: &str,
+) -> Result<NaiveDate, ItemDecodeError> {
+    let value = get_string::<T>(map, attribute)?;
+    NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+        .map_err(|e| ItemDecodeError::invalid::<T>(map, attribute, e))
+}
+
+/// Read a string attribute that holds an RFC3339 datetime
+pub fn get_date_time<T: Item>(
+    map: &HashMap<String, AttributeValue>,
+    attribute: &str,
+) -> Result<DateTime<Utc>, ItemDecodeError> {
+    let value = get_string::<T>(map, attribute)?;
+    DateTime::parse_from_rfc3339(&value)
+        .map(|date_time| date_time.with_timezone(&Utc))
+        .map_err(|e| ItemDecodeError::invalid::<T>(map, attribute, e))
+}
+
+/// Read a string set attribute that holds UUIDs
+pub fn get_uuid_set<T: Item>(
+    map: &HashMap<String, AttributeValue>,
+    attribute: &str,
+) -> Result<Vec<Uuid>, ItemDecodeError> {
+    map.get(attribute)
+        .ok_or_else(|| ItemDecodeError::missing::<T>(map, attribute))?
+        .as_ss()
+        .map_err(|_| ItemDecodeError::wrong_type::<T>(map, attribute, "SS"))?
+        .iter()
+        .map(|s| Uuid::parse_str(s).map_err(|e| ItemDecodeError::invalid::<T>(map, attribute, e)))
+        .collect()
+}
+
+/// Read a list attribute that holds UUIDs
+pub fn get_uuid_list<T: Item>(
+    map: &HashMap<String, AttributeValue>,
+    attribute: &str,
+) -> Result<Vec<Uuid>, ItemDecodeError> {
+    map.get(attribute)
+        .ok_or_else(|| ItemDecodeError::missing::<T>(map, attribute))?
+        .as_l()
+        .map_err(|_| ItemDecodeError::wrong_type::<T>(map, attribute, "L"))?
+        .iter()
+        .map(|value| {
+            let value = value
+                .as_s()
+                .map_err(|_| ItemDecodeError::wrong_type::<T>(map, attribute, "L of S"))?;
+            Uuid::parse_str(value).map_err(|e| ItemDecodeError::invalid::<T>(map, attribute, e))
+        })
+        .collect()
+}
+
+/// Fetch the stored attributes of the item with the given primary key, returning `None` if it does not exist
+pub async fn fetch_row<T: Item>(
+    db_client: &Client,
+    primary_key: Uuid,
+) -> Result<Option<HashMap<String, AttributeValue>>, ApiError> {
     let result = db_client
         .get_item()
         .table_name(T::table_name())
@@ -60,31 +255,354 @@ pub async fn get_item<T: Serialize + Item>(
             AttributeValue::S(primary_key.to_string()),
         )
         .send()
-        .await;
-    match result {
-        Ok(result) => {
-            if let Some(item) = result.item {
-                if let Some(item) = T::from_hashmap(item) {
-                    Json(item).into_response()
-                } else {
-                    // return an internal server error as well as an error message saying that the item could not be
-                    // converted from the hashmap
-                    (
-                        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                        "Could not convert item from hashmap".to_string(),
-                    )
-                        .into_response()
-                }
-            } else {
-                axum::http::StatusCode::NOT_FOUND.into_response()
+        .await?;
+    Ok(result.item)
+}
+
+/// Fetch the item with the given primary key, returning `None` if it does not exist
+pub async fn fetch_item<T: Item>(
+    db_client: &Client,
+    primary_key: Uuid,
+) -> Result<Option<T>, ApiError> {
+    let row = fetch_row::<T>(db_client, primary_key).await?;
+    Ok(row.map(T::from_hashmap).transpose()?)
+}
+
+/// Fetch the item with the given primary key, returning a 404 if it does not exist
+pub async fn fetch_existing_item<T: Item>(
+    db_client: &Client,
+    primary_key: Uuid,
+) -> Result<T, ApiError> {
+    fetch_item::<T>(db_client, primary_key)
+        .await?
+        .ok_or_else(|| not_found::<T>(primary_key))
+}
+
+/// The error returned when there is no item of type `T` with the given primary key
+pub fn not_found<T: Item>(primary_key: Uuid) -> ApiError {
+    ApiError::NotFound(format!(
+        "No item with key {} in {}",
+        primary_key,
+        T::table_name()
+    ))
+}
+
+/// Read one page of items for a list query
+pub async fn list_page<T: Item>(
+    db_client: &Client,
+    query: ListQuery,
+    page: &PageParams,
+) -> Result<Page<T>, ApiError> {
+    let (rows, last_evaluated_key) = match query.index {
+        Some(index) => {
+            let result = db_client
+                .query()
+                .table_name(T::table_name())
+                .index_name(index.index_name)
+                .set_key_condition_expression(index.key_condition.condition())
+                .set_filter_expression(query.filter.condition())
+                .set_expression_attribute_names(merge_names(&[&index.key_condition, &query.filter]))
+                .set_expression_attribute_values(merge_values(&[
+                    &index.key_condition,
+                    &query.filter,
+                ]))
+                .scan_index_forward(index.ascending)
+                .limit(page.limit()?)
+                .set_exclusive_start_key(page.exclusive_start_key()?)
+                .send()
+                .await?;
+            (result.items, result.last_evaluated_key)
+        }
+        None => {
+            let result = db_client
+                .scan()
+                .table_name(T::table_name())
+                .set_filter_expression(query.filter.condition())
+                .set_expression_attribute_names(merge_names(&[&query.filter]))
+                .set_expression_attribute_values(merge_values(&[&query.filter]))
+                .limit(page.limit()?)
+                .set_exclusive_start_key(page.exclusive_start_key()?)
+                .send()
+                .await?;
+            (result.items, result.last_evaluated_key)
+        }
+    };
+    let next_cursor = last_evaluated_key.as_ref().map(encode_cursor).transpose()?;
+    let (items, unreadable) = decode_page_rows(rows.unwrap_or_default());
+    Ok(Page {
+        items,
+        next_cursor,
+        unreadable,
+    })
+}
+
+/// Read every item of `T` whose `attribute` is `value`, following every page
+/// `index` must name an index whose partition key is `attribute`, or be `None` if `attribute` is the table's
+/// partition key.
+pub async fn query_all<T: Item>(
+    db_client: &Client,
+    index: Option<&'static str>,
+    attribute: &str,
+    value: impl std::fmt::Display,
+) -> Result<Vec<T>, ApiError> {
+    let mut key_condition = Expression::new("k");
+    key_condition.equals(attribute, AttributeValue::S(value.to_string()));
+    let mut items = Vec::new();
+    let mut exclusive_start_key = None;
+    loop {
+        let result = db_client
+            .query()
+            .table_name(T::table_name())
+            .set_index_name(index.map(str::to_string))
+            .set_key_condition_expression(key_condition.condition())
+            .set_expression_attribute_names(merge_names(&[&key_condition]))
+            .set_expression_attribute_values(merge_values(&[&key_condition]))
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+        items.extend(decode_rows(result.items.unwrap_or_default()));
+        exclusive_start_key = result.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            return Ok(items);
+        }
+    }
+}
+
+/// Combine the attribute names of several expressions, or `None` if there are none
+/// DynamoDB rejects an empty map, so it must be left unset instead
+fn merge_names(expressions: &[&Expression]) -> Option<HashMap<String, String>> {
+    let names: HashMap<String, String> = expressions
+        .iter()
+        .flat_map(|expression| expression.names().clone())
+        .collect();
+    (!names.is_empty()).then_some(names)
+}
+
+/// Combine the attribute values of several expressions, or `None` if there are none
+fn merge_values(expressions: &[&Expression]) -> Option<HashMap<String, AttributeValue>> {
+    let values: HashMap<String, AttributeValue> = expressions
+        .iter()
+        .flat_map(|expression| expression.values().clone())
+        .collect();
+    (!values.is_empty()).then_some(values)
+}
+
+/// An endpoint that will return a page of items from the database
+/// Pass `?limit=` to choose the page size and the returned `next_cursor` as `?cursor=` to fetch the next page.
+/// The rest of the query string is parsed as the filter `F` for the item type.
+///
+/// DynamoDB applies `limit` before filtering, so a filtered page can hold fewer items than the limit
+/// (even none) while `next_cursor` is still present.
+///
+/// A `sort` that only orders the items of a page is rejected together with `?cursor=`, since sorting each page
+/// on its own would not sort the list.
+///
+#[instrument(skip(db_client))]
+pub async fn get_items<T, F>(
+    db_client: State<Client>,
+    Query(page): Query<PageParams>,
+    Query(filter): Query<F>,
+) -> Result<Json<Page<T>>, ApiError>
+where
+    T: Serialize + Item,
+    F: ListFilter<T>,
+{
+    info!("Getting items from table {}", T::table_name());
+    if page.cursor.is_some() && filter.sorts_within_page() {
+        return Err(ApiError::BadRequest(
+            "sort only orders the first page of this list, so it cannot be used with cursor"
+                .to_string(),
+        ));
+    }
+    let mut page = list_page::<T>(&db_client, filter.list_query(), &page).await?;
+    filter.sort(&mut page.items);
+    Ok(Json(page))
+}
+
+/// Convert rows read from DynamoDB into items
+/// A corrupt row should not take the whole list down with it, so it is logged and skipped
+pub fn decode_rows<T: Item>(rows: Vec<HashMap<String, AttributeValue>>) -> Vec<T> {
+    rows.into_iter()
+        .filter_map(|row| match T::from_hashmap(row) {
+            Ok(item) => Some(item),
+            Err(err) => {
+                warn!("Skipping corrupt row: {}", err);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Decode the rows of a list page, keeping why each row that could not be decoded was skipped
+/// Unlike `decode_rows`, the client is told about the skipped rows, so they do not vanish from lists.
+fn decode_page_rows<T: Item>(rows: Vec<HashMap<String, AttributeValue>>) -> (Vec<T>, Vec<String>) {
+    let mut items = Vec::new();
+    let mut unreadable = Vec::new();
+    for row in rows {
+        match T::from_hashmap(row) {
+            Ok(item) => items.push(item),
+            Err(err) => {
+                warn!("Listing corrupt row as unreadable: {}", err);
+                unreadable.push(err.to_string());
             }
         }
-        Err(err) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            err.to_string(),
-        )
-            .into_response(),
     }
+    (items, unreadable)
+}
+
+/// Endpoint that will accept a primary_key in the path and return the item that has that primary key
+///
+#[instrument(skip(db_client))]
+pub async fn get_item<T: Serialize + Item>(
+    Path(primary_key): Path<Uuid>,
+    State(db_client): State<Client>,
+) -> Result<Json<T>, ApiError> {
+    info!("Getting item from table {}", T::table_name());
+    fetch_existing_item::<T>(&db_client, primary_key)
+        .await
+        .map(Json)
+}
+
+/// The most keys DynamoDB accepts in a single `BatchGetItem` call
+const BATCH_GET_CHUNK: usize = 100;
+/// The most ids or items a client can send in one batch request
+pub const MAX_BATCH_SIZE: usize = 100;
+/// How many times a batch call is sent before unprocessed keys are given up on
+pub const MAX_BATCH_ATTEMPTS: u32 = 5;
+
+/// How long to wait before retrying a batch call for the `attempt`th time
+/// The delay doubles with every attempt, starting at 50ms
+pub fn backoff(attempt: u32) -> Duration {
+    Duration::from_millis(50 * 2u64.pow(attempt.min(6)))
+}
+
+/// Check that a batch request holds no more than `MAX_BATCH_SIZE` entries
+pub fn check_batch_size(size: usize) -> Result<(), ApiError> {
+    if size > MAX_BATCH_SIZE {
+        return Err(ApiError::BadRequest(format!(
+            "At most {MAX_BATCH_SIZE} entries can be sent in one request, got {size}"
+        )));
+    }
+    Ok(())
+}
+
+/// A list of primary keys passed in a request body
+#[derive(Debug, Deserialize)]
+pub struct BatchIds {
+    pub ids: Vec<Uuid>,
+}
+
+impl BatchIds {
+    /// Check that there are no more ids than one batch request may hold
+    pub fn validate(&self) -> Result<(), ApiError> {
+        check_batch_size(self.ids.len())
+    }
+}
+
+/// The found items of a batch get, plus the ids that did not match an item
+#[derive(Debug, Serialize)]
+pub struct BatchGetResponse<T> {
+    pub items: Vec<T>,
+    pub missing: Vec<Uuid>,
+}
+
+/// Read the rows of `T` with the given primary keys using `BatchGetItem`
+/// Duplicate ids are only fetched once, and unprocessed keys are retried with backoff.
+/// Ids that do not match a row are left out of the result.
+async fn batch_get_rows<T: Item>(
+    db_client: &Client,
+    ids: &[Uuid],
+) -> Result<Vec<HashMap<String, AttributeValue>>, ApiError> {
+    let mut unique_ids: Vec<Uuid> = Vec::with_capacity(ids.len());
+    for id in ids {
+        if !unique_ids.contains(id) {
+            unique_ids.push(*id);
+        }
+    }
+    let mut rows = Vec::new();
+    for chunk in unique_ids.chunks(BATCH_GET_CHUNK) {
+        let keys: Vec<HashMap<String, AttributeValue>> = chunk
+            .iter()
+            .map(|id| {
+                HashMap::from([(
+                    T::partition_key_name().to_string(),
+                    AttributeValue::S(id.to_string()),
+                )])
+            })
+            .collect();
+        let mut request = Some(
+            KeysAndAttributes::builder()
+                .set_keys(Some(keys))
+                .build()
+                .map_err(|err| ApiError::Internal(err.to_string()))?,
+        );
+        let mut attempt = 0;
+        while let Some(keys) = request.take() {
+            if attempt > 0 {
+                tokio::time::sleep(backoff(attempt)).await;
+            }
+            let result = db_client
+                .batch_get_item()
+                .request_items(T::table_name(), keys)
+                .send()
+                .await?;
+            if let Some(mut responses) = result.responses {
+                rows.extend(responses.remove(T::table_name()).unwrap_or_default());
+            }
+            request = result
+                .unprocessed_keys
+                .and_then(|mut unprocessed| unprocessed.remove(T::table_name()))
+                .filter(|unprocessed| !unprocessed.keys().is_empty());
+            attempt += 1;
+            if request.is_some() && attempt == MAX_BATCH_ATTEMPTS {
+                return Err(ApiError::ServiceUnavailable(
+                    "The database is busy, please retry the request later".to_string(),
+                ));
+            }
+        }
+    }
+    Ok(rows)
+}
+
+/// Fetch the items of type `T` with the given primary keys
+/// Ids that do not match an item are left out of the result.
+pub async fn fetch_items<T: Item>(db_client: &Client, ids: &[Uuid]) -> Result<Vec<T>, ApiError> {
+    Ok(decode_rows(batch_get_rows::<T>(db_client, ids).await?))
+}
+
+/// Endpoint that will return every item whose primary key is in the list of ids in the request body
+/// The ids that did not match an item are returned in `missing`
+///
+#[instrument(skip(db_client))]
+pub async fn batch_get_items<T: Serialize + Item>(
+    State(db_client): State<Client>,
+    Json(batch): Json<BatchIds>,
+) -> Result<Json<BatchGetResponse<T>>, ApiError> {
+    info!("Batch getting items from table {}", T::table_name());
+    batch.validate()?;
+    let rows = batch_get_rows::<T>(&db_client, &batch.ids).await?;
+    let found: Vec<String> = rows.iter().filter_map(partition_key_value::<T>).collect();
+    let mut missing: Vec<Uuid> = Vec::new();
+    for id in batch.ids {
+        if !found.contains(&id.to_string()) && !missing.contains(&id) {
+            missing.push(id);
+        }
+    }
+    Ok(Json(BatchGetResponse {
+        items: decode_rows(rows),
+        missing,
+    }))
+}
+
+/// Write a new item to the database
+pub async fn put_item<T: Item + Clone>(db_client: &Client, item: &T) -> Result<(), ApiError> {
+    db_client
+        .put_item()
+        .table_name(T::table_name())
+        .set_item(Some(item.clone().into_hashmap()))
+        .send()
+        .await?;
+    Ok(())
 }
 
 /// Add an item to the database
@@ -95,39 +613,39 @@ pub async fn get_item<T: Serialize + Item>(
 ///
 /// `U` is the type of the item that is passed in the request body
 ///
+/// The caller must be allowed to create the item, see `Guarded::authorize_create`
+///
 #[instrument(skip(db_client))]
-pub async fn add_item<T, U>(State(db_client): State<Client>, Json(item): Json<U>) -> Response
+pub async fn add_item<T, U>(
+    caller: Caller,
+    State(db_client): State<Client>,
+    Json(item): Json<U>,
+) -> Result<Json<T>, ApiError>
 where
-    T: Serialize + Clone + Item + From<U>,
+    T: Serialize + Clone + Guarded + From<U>,
     U: Debug,
 {
     info!("Adding item to table {}", T::table_name());
-    let item = T::from(item);
-    let result = db_client
-        .put_item()
-        .table_name(T::table_name())
-        .set_item(Some(item.clone().into_hashmap()))
-        .send()
-        .await;
-    match result {
-        Ok(_) => Json(item).into_response(),
-        Err(err) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            err.to_string(),
-        )
-            .into_response(),
-    }
+    let mut item = T::from(item);
+    item.authorize_create(&db_client, &caller).await?;
+    put_item(&db_client, &item).await?;
+    Ok(Json(item))
 }
 
 /// Endpoint that will try to delete an item with the given primary key
+/// The caller must be allowed to change the item, if there is one
 ///
 #[instrument(skip(db_client))]
-pub async fn delete_item<T: Serialize + Item>(
+pub async fn delete_item<T: Serialize + Guarded>(
+    caller: Caller,
     Path(primary_key): Path<Uuid>,
     State(db_client): State<Client>,
-) -> Response {
+) -> Result<StatusCode, ApiError> {
     info!("Deleting item from table {}", T::table_name());
-    let result = db_client
+    if let Some(item) = fetch_item::<T>(&db_client, primary_key).await? {
+        item.authorize_modify(&db_client, &caller).await?;
+    }
+    db_client
         .delete_item()
         .table_name(T::table_name())
         .key(
@@ -135,14 +653,318 @@ pub async fn delete_item<T: Serialize + Item>(
             AttributeValue::S(primary_key.to_string()),
         )
         .send()
-        .await;
-    // .map_err(|e| DynamoDbError::from(e));
-    match result {
-        Ok(_) => axum::http::StatusCode::OK.into_response(),
-        Err(err) => (
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-            err.to_string(),
+        .await?;
+    Ok(StatusCode::OK)
+}
+
+/// An update expression that replaces every attribute of an item except its partition key, with the condition to
+/// write it with
+/// The partition key name is available as `#pk`.
+pub struct SetAll {
+    pub expression: String,
+    pub names: HashMap<String, String>,
+    pub values: HashMap<String, AttributeValue>,
+    pub condition: String,
+}
+
+impl SetAll {
+    /// An update that turns the stored row `stored` into `item`, removing the attributes `item` does not have
+    /// The condition checks that every attribute of `T` still holds the value it had in `stored`, so a write made
+    /// since `stored` was read is never lost. Attributes `T` does not know about (i.e. legacy attributes left for a
+    /// migration) are neither checked nor removed.
+    pub fn replacing<T: Item>(
+        stored: HashMap<String, AttributeValue>,
+        item: T,
+    ) -> Result<Self, ApiError> {
+        let known = T::from_hashmap(stored.clone())?.into_hashmap();
+        let mut new = item.into_hashmap();
+        let mut attributes: Vec<String> = known.keys().chain(new.keys()).cloned().collect();
+        attributes.sort();
+        attributes.dedup();
+        let mut names = HashMap::from([("#pk".to_string(), T::partition_key_name().to_string())]);
+        let mut values = HashMap::new();
+        let mut assignments = Vec::new();
+        let mut removals = Vec::new();
+        let mut conditions = vec!["attribute_exists(#pk)".to_string()];
+        for (i, name) in attributes
+            .into_iter()
+            .filter(|name| name != T::partition_key_name())
+            .enumerate()
+        {
+            match stored.get(&name) {
+                Some(old) => {
+                    conditions.push(format!("#a{i} = :o{i}"));
+                    values.insert(format!(":o{i}"), old.clone());
+                }
+                None => conditions.push(format!("attribute_not_exists(#a{i})")),
+            }
+            match new.remove(&name) {
+                Some(value) => {
+                    assignments.push(format!("#a{i} = :v{i}"));
+                    values.insert(format!(":v{i}"), value);
+                }
+                None if known.contains_key(&name) => removals.push(format!("#a{i}")),
+                None => {}
+            }
+            names.insert(format!("#a{i}"), name);
+        }
+        let mut clauses = Vec::new();
+        if !assignments.is_empty() {
+            clauses.push(format!("SET {}", assignments.join(", ")));
+        }
+        if !removals.is_empty() {
+            clauses.push(format!("REMOVE {}", removals.join(", ")));
+        }
+        Ok(Self {
+            expression: clauses.join(" "),
+            names,
+            values,
+            condition: conditions.join(" AND "),
+        })
+    }
+}
+
+/// Replace the stored row `stored` of the item with the given primary key with `item`
+/// Attributes `item` does not have are removed, so this is a full replace rather than a merge. The update fails
+/// with a 409 if the stored item changed since `stored` was read, and with a 404 if it was deleted.
+pub async fn update_attributes<T: Item>(
+    db_client: &Client,
+    primary_key: Uuid,
+    stored: HashMap<String, AttributeValue>,
+    item: T,
+) -> Result<T, ApiError> {
+    let update = SetAll::replacing(stored, item)?;
+    let result = db_client
+        .update_item()
+        .table_name(T::table_name())
+        .key(
+            T::partition_key_name(),
+            AttributeValue::S(primary_key.to_string()),
         )
-            .into_response(),
+        .condition_expression(update.condition)
+        .update_expression(update.expression)
+        .set_expression_attribute_names(Some(update.names))
+        .set_expression_attribute_values(Some(update.values))
+        .return_values(ReturnValue::AllNew)
+        .return_values_on_condition_check_failure(ReturnValuesOnConditionCheckFailure::AllOld)
+        .send()
+        .await
+        .map_err(|err| match err.as_service_error() {
+            Some(UpdateItemError::ConditionalCheckFailedException(failed)) => {
+                match failed.item.is_some() {
+                    true => ApiError::Conflict(format!(
+                        "The item with key {primary_key} in {} was changed since it was read, please retry the request",
+                        T::table_name()
+                    )),
+                    false => not_found::<T>(primary_key),
+                }
+            }
+            _ => ApiError::from(err),
+        })?;
+    Ok(T::from_hashmap(result.attributes.unwrap_or_default())?)
+}
+
+/// Replace the item with the given primary key (PUT)
+/// The full item is passed in the request body as a JSON object, in the same shape that `add_item` accepts
+///
+/// `T` is the type of the item that will be stored in the database
+///
+/// `U` is the type of the item that is passed in the request body
+///
+/// The caller must be allowed to replace the stored item, see `Guarded::authorize_replace`
+///
+#[instrument(skip(db_client))]
+pub async fn update_item<T, U>(
+    caller: Caller,
+    Path(primary_key): Path<Uuid>,
+    State(db_client): State<Client>,
+    Json(item): Json<U>,
+) -> Result<Json<T>, ApiError>
+where
+    T: Serialize + Guarded + From<U>,
+    U: Debug,
+{
+    info!("Updating item in table {}", T::table_name());
+    let stored = fetch_row::<T>(&db_client, primary_key)
+        .await?
+        .ok_or_else(|| not_found::<T>(primary_key))?;
+    let current = T::from_hashmap(stored.clone())?;
+    let mut item = T::from(item);
+    item.authorize_replace(&current, &db_client, &caller)
+        .await?;
+    update_attributes(&db_client, primary_key, stored, item)
+        .await
+        .map(Json)
+}
+
+/// Apply a JSON merge patch to an item, returning the patched item without writing it
+/// The patched item must still be a valid `U`, otherwise a 422 is returned.
+pub fn apply_patch<T, U>(current: T, patch: Value) -> Result<T, ApiError>
+where
+    T: Serialize + From<U>,
+    U: DeserializeOwned,
+{
+    if !patch.is_object() {
+        return Err(ApiError::UnprocessableEntity(
+            "The patch must be a JSON object".to_string(),
+        ));
+    }
+    let mut document =
+        serde_json::to_value(current).map_err(|err| ApiError::Internal(err.to_string()))?;
+    merge_patch(&mut document, patch);
+    serde_json::from_value::<U>(document)
+        .map(T::from)
+        .map_err(|err| ApiError::UnprocessableEntity(err.to_string()))
+}
+
+/// Partially update the item with the given primary key (PATCH)
+/// The request body is a JSON merge patch (RFC 7396) that is applied to the stored item.
+/// The patched item must still be a valid `U`, otherwise nothing is written and a 422 is returned.
+///
+/// `T` is the type of the item that will be stored in the database
+///
+/// `U` is the type of the item that is passed in the request body
+///
+/// The caller must be allowed to replace the stored item with the patched one, see `Guarded::authorize_replace`
+///
+#[instrument(skip(db_client))]
+pub async fn patch_item<T, U>(
+    caller: Caller,
+    Path(primary_key): Path<Uuid>,
+    State(db_client): State<Client>,
+    Json(patch): Json<Value>,
+) -> Result<Json<T>, ApiError>
+where
+    T: Serialize + Clone + Guarded + From<U>,
+    U: DeserializeOwned,
+{
+    info!("Patching item in table {}", T::table_name());
+    let stored = fetch_row::<T>(&db_client, primary_key)
+        .await?
+        .ok_or_else(|| not_found::<T>(primary_key))?;
+    let current = T::from_hashmap(stored.clone())?;
+    let mut item = apply_patch::<T, U>(current.clone(), patch)?;
+    item.authorize_replace(&current, &db_client, &caller)
+        .await?;
+    update_attributes(&db_client, primary_key, stored, item)
+        .await
+        .map(Json)
+}
+
+/// Apply a JSON merge patch (RFC 7396) to `target`
+/// Object members in the patch replace members of the target, and `null` members remove them
+fn merge_patch(target: &mut Value, patch: Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(serde_json::Map::new());
+    }
+    let target = target
+        .as_object_mut()
+        .expect("target was just made an object");
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(&key);
+        } else {
+            merge_patch(target.entry(key).or_insert(Value::Null), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_patch() {
+        let mut document = json!({
+            "id": "1",
+            "name": "Penn Relays",
+            "location": "Philadelphia",
+        });
+        merge_patch(
+            &mut document,
+            json!({ "name": "Drake Relays", "location": null }),
+        );
+        assert_eq!(document, json!({ "id": "1", "name": "Drake Relays" }));
+    }
+
+    /// An item with an optional attribute
+    #[derive(Clone, Debug, PartialEq)]
+    struct Note {
+        id: Uuid,
+        text: Option<String>,
+    }
+
+    impl Item for Note {
+        fn table_name() -> &'static str {
+            "notes"
+        }
+        fn partition_key_name() -> &'static str {
+            "id"
+        }
+        fn from_hashmap(map: HashMap<String, AttributeValue>) -> Result<Self, ItemDecodeError> {
+            Ok(Self {
+                id: get_uuid::<Self>(&map, "id")?,
+                text: match map.contains_key("text") {
+                    true => Some(get_string::<Self>(&map, "text")?),
+                    false => None,
+                },
+            })
+        }
+        fn into_hashmap(self) -> HashMap<String, AttributeValue> {
+            let mut map =
+                HashMap::from([("id".to_string(), AttributeValue::S(self.id.to_string()))]);
+            if let Some(text) = self.text {
+                map.insert("text".to_string(), AttributeValue::S(text));
+            }
+            map
+        }
+    }
+
+    #[test]
+    fn test_page_lists_unreadable_rows() {
+        let note = Note {
+            id: Uuid::new_v4(),
+            text: None,
+        };
+        let corrupt = HashMap::from([
+            ("id".to_string(), AttributeValue::S("7".to_string())),
+            ("text".to_string(), AttributeValue::N("1".to_string())),
+        ]);
+        let (items, unreadable) =
+            decode_page_rows::<Note>(vec![note.clone().into_hashmap(), corrupt]);
+        assert_eq!(items, vec![note]);
+        assert_eq!(unreadable.len(), 1);
+        assert!(unreadable[0].starts_with("item 7 in table notes"));
+    }
+
+    #[test]
+    fn test_replacing_removes_missing_attributes() {
+        let id = Uuid::new_v4();
+        let mut stored = Note {
+            id,
+            text: Some("old".to_string()),
+        }
+        .into_hashmap();
+        // Attributes the item does not know about are left alone
+        stored.insert("legacy".to_string(), AttributeValue::S("kept".to_string()));
+        let update = SetAll::replacing(stored, Note { id, text: None }).unwrap();
+        assert_eq!(update.expression, "REMOVE #a0");
+        assert_eq!(update.names["#a0"], "text");
+        assert_eq!(update.condition, "attribute_exists(#pk) AND #a0 = :o0");
+        assert_eq!(update.values[":o0"], AttributeValue::S("old".to_string()));
+    }
+
+    #[test]
+    fn test_batch_ids_limit() {
+        let batch = BatchIds {
+            ids: (0..=MAX_BATCH_SIZE).map(|_| Uuid::new_v4()).collect(),
+        };
+        assert!(matches!(batch.validate(), Err(ApiError::BadRequest(_))));
+        assert!(backoff(2) > backoff(1));
     }
 }